@@ -0,0 +1,58 @@
+use axum::http::HeaderName;
+use eyre::WrapErr;
+use sha2::{Digest, Sha256};
+
+use super::{AccessRequest, Denial};
+use crate::config::ApiKeyConfig;
+use crate::error::AppError;
+
+/// Verifies static API keys sent in a configurable header.
+pub struct ApiKeyVerifier {
+    header: HeaderName,
+    /// SHA-256 digests of the keys, so that lookups don't compare secrets directly.
+    keys: Vec<([u8; 32], String)>,
+}
+
+impl ApiKeyVerifier {
+    pub fn from_config(header: &str, keys: &[ApiKeyConfig]) -> eyre::Result<Self> {
+        let header = HeaderName::try_from(header)
+            .wrap_err_with(|| format!("invalid API key header \"{header}\""))?;
+        let keys = keys
+            .iter()
+            .map(|api_key| {
+                let key = api_key
+                    .key
+                    .resolve()
+                    .wrap_err_with(|| format!("API key \"{}\"", api_key.name))?;
+                Ok((digest(key.trim()), api_key.name.clone()))
+            })
+            .collect::<eyre::Result<_>>()?;
+
+        Ok(Self { header, keys })
+    }
+
+    /// Returns the name of the matching key.
+    pub fn verify(&self, request: &AccessRequest<'_>) -> Result<Option<String>, Denial> {
+        let key = request
+            .headers
+            .get(&self.header)
+            .ok_or(Denial::Missing)?
+            .to_str()
+            .map_err(|_| rejected())?;
+
+        let digest = digest(key.trim());
+        self.keys
+            .iter()
+            .find(|(candidate, _)| *candidate == digest)
+            .map(|(_, name)| Some(name.clone()))
+            .ok_or_else(rejected)
+    }
+}
+
+fn digest(key: &str) -> [u8; 32] {
+    Sha256::digest(key.as_bytes()).into()
+}
+
+fn rejected() -> Denial {
+    Denial::Rejected(AppError::Unauthorized("invalid API key".into()))
+}
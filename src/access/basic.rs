@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use argon2::password_hash::PasswordHash;
+use argon2::{Argon2, PasswordVerifier};
+use axum::http::header;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use eyre::{WrapErr, bail};
+use sha2::{Digest, Sha256};
+
+use super::{AccessRequest, Denial};
+use crate::config::UserConfig;
+use crate::error::AppError;
+
+/// How long a successful password check is remembered, so that pages
+/// loading many objects don't pay for a password hash on every request.
+const VERIFIED_TTL: Duration = Duration::from_secs(300);
+const VERIFIED_CAPACITY: u64 = 1024;
+
+/// Verifies `Authorization: Basic` credentials against bcrypt or argon2 hashes.
+pub struct BasicAuthVerifier {
+    users: HashMap<String, Arc<str>>,
+    /// Checked for unknown users, so that a miss costs as much as a wrong
+    /// password and user names can't be told apart by response time.
+    dummy: Option<Arc<str>>,
+    verified: moka::future::Cache<[u8; 32], ()>,
+}
+
+impl BasicAuthVerifier {
+    pub fn from_config(users: &[UserConfig]) -> eyre::Result<Self> {
+        let mut hashes = HashMap::new();
+
+        for user in users {
+            let hash = user
+                .password_hash
+                .resolve()
+                .wrap_err_with(|| format!("password hash of user \"{}\"", user.name))?;
+            let hash = hash.trim();
+            if !is_argon2(hash) && hash.parse::<bcrypt::HashParts>().is_err() {
+                bail!(
+                    "password hash of user \"{}\" is neither bcrypt nor argon2",
+                    user.name
+                );
+            }
+            hashes.insert(user.name.clone(), Arc::from(hash));
+        }
+
+        Ok(Self {
+            dummy: hashes.values().next().cloned(),
+            users: hashes,
+            verified: moka::future::Cache::builder()
+                .max_capacity(VERIFIED_CAPACITY)
+                .time_to_live(VERIFIED_TTL)
+                .build(),
+        })
+    }
+
+    /// Returns the user name.
+    pub async fn verify(&self, request: &AccessRequest<'_>) -> Result<Option<String>, Denial> {
+        let (user, password) = credentials(request).ok_or(Denial::Missing)?;
+
+        let digest: [u8; 32] = Sha256::new()
+            .chain_update(user.as_bytes())
+            .chain_update([0])
+            .chain_update(password.as_bytes())
+            .finalize()
+            .into();
+        if self.verified.contains_key(&digest) {
+            return Ok(Some(user));
+        }
+
+        let (hash, known) = match self.users.get(&user) {
+            Some(hash) => (hash.clone(), true),
+            None => (self.dummy.clone().ok_or_else(rejected)?, false),
+        };
+        let valid = tokio::task::spawn_blocking(move || verify_password(&password, &hash))
+            .await
+            .unwrap_or(false)
+            && known;
+        if !valid {
+            return Err(rejected());
+        }
+
+        self.verified.insert(digest, ()).await;
+        Ok(Some(user))
+    }
+}
+
+fn credentials(request: &AccessRequest<'_>) -> Option<(String, String)> {
+    let encoded = request
+        .headers
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Basic ")?;
+    let decoded = String::from_utf8(STANDARD.decode(encoded.trim()).ok()?).ok()?;
+    let (user, password) = decoded.split_once(':')?;
+    Some((user.to_string(), password.to_string()))
+}
+
+fn is_argon2(hash: &str) -> bool {
+    hash.starts_with("$argon2")
+}
+
+fn verify_password(password: &str, hash: &str) -> bool {
+    if is_argon2(hash) {
+        PasswordHash::new(hash)
+            .and_then(|hash| Argon2::default().verify_password(password.as_bytes(), &hash))
+            .is_ok()
+    } else {
+        bcrypt::verify(password, hash).unwrap_or(false)
+    }
+}
+
+fn rejected() -> Denial {
+    Denial::Rejected(AppError::BasicAuthRequired(
+        "invalid username or password".into(),
+    ))
+}
@@ -0,0 +1,169 @@
+use axum::http::{HeaderMap, header};
+use eyre::{WrapErr, bail, eyre};
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use serde_json::{Map, Value};
+
+use super::{AccessRequest, Denial};
+use crate::config::JwtConfig;
+use crate::error::AppError;
+
+struct VerificationKey {
+    kid: Option<String>,
+    key: DecodingKey,
+    validation: Validation,
+}
+
+/// Verifies bearer tokens taken from the `Authorization` header or a cookie.
+pub struct JwtVerifier {
+    keys: Vec<VerificationKey>,
+    cookie: Option<String>,
+    paths_claim: Option<String>,
+}
+
+impl JwtVerifier {
+    pub fn from_config(config: &JwtConfig) -> eyre::Result<Self> {
+        let keys = match (&config.jwks, &config.pem) {
+            (Some(jwks), None) => {
+                let jwks = jwks.resolve()?;
+                let jwks: JwkSet = serde_json::from_str(&jwks).wrap_err("invalid JWKS document")?;
+                jwks.keys
+                    .iter()
+                    .filter(|jwk| jwk.is_supported())
+                    .map(|jwk| {
+                        let algorithms = if config.algorithms.is_empty() {
+                            let alg = jwk
+                                .common
+                                .key_algorithm
+                                .ok_or_else(|| eyre!("JWK without `alg` needs `algorithms`"))?;
+                            vec![alg.to_string().parse()?]
+                        } else {
+                            config.algorithms.clone()
+                        };
+                        Ok(VerificationKey {
+                            kid: jwk.common.key_id.clone(),
+                            key: DecodingKey::from_jwk(jwk)?,
+                            validation: validation(config, algorithms),
+                        })
+                    })
+                    .collect::<eyre::Result<Vec<_>>>()?
+            }
+            (None, Some(pem)) => {
+                let pem = pem.resolve()?;
+                let Some(first) = config.algorithms.first() else {
+                    bail!("`algorithms` is required with a PEM key");
+                };
+                let key = match first {
+                    Algorithm::RS256
+                    | Algorithm::RS384
+                    | Algorithm::RS512
+                    | Algorithm::PS256
+                    | Algorithm::PS384
+                    | Algorithm::PS512 => DecodingKey::from_rsa_pem(pem.as_bytes()),
+                    Algorithm::ES256 | Algorithm::ES384 => DecodingKey::from_ec_pem(pem.as_bytes()),
+                    Algorithm::EdDSA => DecodingKey::from_ed_pem(pem.as_bytes()),
+                    _ => bail!("unsupported algorithm for a PEM key: {first:?}"),
+                }
+                .wrap_err("invalid PEM key")?;
+                vec![VerificationKey {
+                    kid: None,
+                    key,
+                    validation: validation(config, config.algorithms.clone()),
+                }]
+            }
+            _ => bail!("exactly one of `jwks` or `pem` is required"),
+        };
+
+        if keys.is_empty() {
+            bail!("no usable key in JWKS document");
+        }
+
+        Ok(Self {
+            keys,
+            cookie: config.cookie.clone(),
+            paths_claim: config.paths_claim.clone(),
+        })
+    }
+
+    /// Returns the token subject, if any.
+    pub fn verify(&self, request: &AccessRequest<'_>) -> Result<Option<String>, Denial> {
+        let token = self.token(request.headers).ok_or(Denial::Missing)?;
+
+        let header = jsonwebtoken::decode_header(&token).map_err(rejected)?;
+        let key = match &header.kid {
+            Some(kid) => self
+                .keys
+                .iter()
+                .find(|k| k.kid.as_deref() == Some(kid.as_str())),
+            None if self.keys.len() == 1 => self.keys.first(),
+            None => None,
+        }
+        .ok_or_else(|| rejected("no matching key"))?;
+
+        let claims = jsonwebtoken::decode::<Map<String, Value>>(&token, &key.key, &key.validation)
+            .map_err(rejected)?
+            .claims;
+
+        if let Some(paths_claim) = &self.paths_claim {
+            let allowed = match claims.get(paths_claim) {
+                Some(Value::String(prefix)) => request.file_path.starts_with(prefix.as_str()),
+                Some(Value::Array(prefixes)) => prefixes
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .any(|prefix| request.file_path.starts_with(prefix)),
+                _ => false,
+            };
+            if !allowed {
+                return Err(Denial::Rejected(AppError::Forbidden(format!(
+                    "token does not grant access to {}",
+                    request.file_path
+                ))));
+            }
+        }
+
+        Ok(claims
+            .get("sub")
+            .and_then(Value::as_str)
+            .map(str::to_string))
+    }
+
+    fn token(&self, headers: &HeaderMap) -> Option<String> {
+        let bearer = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        if let Some(token) = bearer {
+            return Some(token.trim().to_string());
+        }
+
+        let cookie = self.cookie.as_deref()?;
+        headers
+            .get_all(header::COOKIE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(';'))
+            .filter_map(|pair| pair.trim().split_once('='))
+            .find(|(name, _)| *name == cookie)
+            .map(|(_, value)| value.to_string())
+    }
+}
+
+fn validation(config: &JwtConfig, algorithms: Vec<Algorithm>) -> Validation {
+    let mut validation = Validation::new(algorithms[0]);
+    validation.algorithms = algorithms;
+    if config.audience.is_empty() {
+        validation.validate_aud = false;
+    } else {
+        validation.set_audience(&config.audience);
+        validation.required_spec_claims.insert("aud".into());
+    }
+    if !config.issuer.is_empty() {
+        validation.set_issuer(&config.issuer);
+        validation.required_spec_claims.insert("iss".into());
+    }
+    validation
+}
+
+fn rejected(reason: impl std::fmt::Display) -> Denial {
+    Denial::Rejected(AppError::Unauthorized(format!("invalid token: {reason}")))
+}
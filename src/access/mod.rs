@@ -0,0 +1,261 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+use axum::http::HeaderMap;
+use eyre::WrapErr;
+use ipnet::IpNet;
+
+use crate::config::{AppConfig, BucketConfig};
+use crate::error::AppError;
+
+mod api_key;
+mod basic;
+mod jwt;
+mod referer;
+mod signature;
+
+use api_key::ApiKeyVerifier;
+use basic::BasicAuthVerifier;
+use jwt::JwtVerifier;
+use referer::RefererPolicy;
+pub use signature::SignatureScope;
+use signature::SignatureVerifier;
+
+/// What the access policies of a bucket get to see of an incoming request.
+pub struct AccessRequest<'a> {
+    pub file_path: &'a str,
+    pub query: Option<&'a str>,
+    pub headers: &'a HeaderMap,
+    pub client_ip: Option<IpAddr>,
+}
+
+/// Outcome of a successful access check.
+#[derive(Debug, Default)]
+pub struct Grant {
+    /// User, API key or token subject that authenticated the request.
+    pub principal: Option<String>,
+    /// Key to serve instead of the requested one, for refused hotlinks.
+    pub substitute: Option<String>,
+}
+
+/// Why a single authentication method did not grant access.
+pub enum Denial {
+    /// The request carries no credentials for this method.
+    Missing,
+    /// Credentials were presented but are not acceptable.
+    Rejected(AppError),
+}
+
+#[derive(Default)]
+struct BucketAccess {
+    signature: Option<SignatureVerifier>,
+    jwt: Option<JwtVerifier>,
+    basic: Option<BasicAuthVerifier>,
+    api_keys: Option<ApiKeyVerifier>,
+    allow_cidrs: Vec<IpNet>,
+    deny_cidrs: Vec<IpNet>,
+    referer: Option<RefererPolicy>,
+}
+
+impl BucketAccess {
+    fn from_config(bc: &BucketConfig) -> eyre::Result<Self> {
+        Ok(Self {
+            allow_cidrs: bc.allow_cidrs.clone(),
+            deny_cidrs: bc.deny_cidrs.clone(),
+            referer: RefererPolicy::from_config(bc),
+            signature: bc
+                .require_signature
+                .as_ref()
+                .map(SignatureVerifier::from_config)
+                .transpose()?,
+            jwt: bc.jwt.as_ref().map(JwtVerifier::from_config).transpose()?,
+            basic: (!bc.users.is_empty())
+                .then(|| BasicAuthVerifier::from_config(&bc.users))
+                .transpose()?,
+            api_keys: (!bc.api_keys.is_empty())
+                .then(|| ApiKeyVerifier::from_config(&bc.api_key_header, &bc.api_keys))
+                .transpose()?,
+        })
+    }
+
+    /// Deny rules win over allow rules; with an allow-list, clients whose
+    /// address is unknown are refused.
+    fn check_client_ip(&self, client_ip: Option<IpAddr>) -> Result<(), AppError> {
+        let denied =
+            client_ip.is_some_and(|ip| self.deny_cidrs.iter().any(|net| net.contains(&ip)));
+        let allowed = self.allow_cidrs.is_empty()
+            || client_ip.is_some_and(|ip| self.allow_cidrs.iter().any(|net| net.contains(&ip)));
+
+        if denied || !allowed {
+            let client_ip = client_ip.map_or_else(|| "unknown".to_string(), |ip| ip.to_string());
+            return Err(AppError::Forbidden(format!(
+                "client address {client_ip} is not allowed"
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Grants access when any configured method accepts the request, returning
+    /// the authenticated principal if the method identifies one.
+    async fn authenticate(
+        &self,
+        config_name: &str,
+        request: &AccessRequest<'_>,
+    ) -> Result<Option<String>, AppError> {
+        if self.signature.is_none()
+            && self.jwt.is_none()
+            && self.basic.is_none()
+            && self.api_keys.is_none()
+        {
+            return Ok(None);
+        }
+
+        let mut rejection = None;
+
+        if let Some(verifier) = &self.signature {
+            match verifier.verify(config_name, request, signature::now()) {
+                Ok(()) => return Ok(None),
+                Err(Denial::Rejected(err)) => rejection = rejection.or(Some(err)),
+                Err(Denial::Missing) => {}
+            }
+        }
+
+        if let Some(verifier) = &self.jwt {
+            match verifier.verify(request) {
+                Ok(principal) => return Ok(principal),
+                Err(Denial::Rejected(err)) => rejection = rejection.or(Some(err)),
+                Err(Denial::Missing) => {}
+            }
+        }
+
+        if let Some(verifier) = &self.api_keys {
+            match verifier.verify(request) {
+                Ok(principal) => return Ok(principal),
+                Err(Denial::Rejected(err)) => rejection = rejection.or(Some(err)),
+                Err(Denial::Missing) => {}
+            }
+        }
+
+        if let Some(verifier) = &self.basic {
+            match verifier.verify(request).await {
+                Ok(principal) => return Ok(principal),
+                Err(Denial::Rejected(err)) => rejection = rejection.or(Some(err)),
+                Err(Denial::Missing) => {}
+            }
+        }
+
+        Err(rejection.unwrap_or_else(|| {
+            if self.basic.is_some() {
+                AppError::BasicAuthRequired("missing credentials".into())
+            } else if self.jwt.is_some() || self.api_keys.is_some() {
+                AppError::Unauthorized("missing credentials".into())
+            } else {
+                AppError::InvalidSignature("missing signature".into())
+            }
+        }))
+    }
+}
+
+/// Builds each access policy of a bucket, parsing its keys and password
+/// hashes, and returns the settings that failed along with why.
+pub fn check_bucket(bc: &BucketConfig) -> Vec<(&'static str, eyre::Report)> {
+    let checks = [
+        (
+            "require_signature",
+            bc.require_signature
+                .as_ref()
+                .map(|config| SignatureVerifier::from_config(config).map(drop)),
+        ),
+        (
+            "jwt",
+            bc.jwt
+                .as_ref()
+                .map(|config| JwtVerifier::from_config(config).map(drop)),
+        ),
+        (
+            "users",
+            (!bc.users.is_empty()).then(|| BasicAuthVerifier::from_config(&bc.users).map(drop)),
+        ),
+        (
+            "api_keys",
+            (!bc.api_keys.is_empty())
+                .then(|| ApiKeyVerifier::from_config(&bc.api_key_header, &bc.api_keys).map(drop)),
+        ),
+    ];
+    checks
+        .into_iter()
+        .filter_map(|(field, result)| Some((field, result?.err()?)))
+        .collect()
+}
+
+/// Per-bucket access policies, enforced before any call to S3.
+#[derive(Default)]
+pub struct AccessControl {
+    buckets: HashMap<String, BucketAccess>,
+}
+
+impl AccessControl {
+    pub fn from_config(config: &AppConfig) -> eyre::Result<Self> {
+        let mut buckets = HashMap::new();
+
+        for (name, bc) in &config.buckets {
+            let access = BucketAccess::from_config(bc)
+                .wrap_err_with(|| format!("invalid access settings for bucket \"{name}\""))?;
+            buckets.insert(name.clone(), access);
+        }
+
+        Ok(Self { buckets })
+    }
+
+    /// Unknown configs are let through so that the file server reports them.
+    pub async fn check(
+        &self,
+        config_name: &str,
+        request: &AccessRequest<'_>,
+    ) -> Result<Grant, AppError> {
+        let Some(access) = self.buckets.get(config_name) else {
+            return Ok(Grant::default());
+        };
+
+        access.check_client_ip(request.client_ip)?;
+        let principal = access.authenticate(config_name, request).await?;
+        let substitute = match &access.referer {
+            Some(policy) => policy.check(request.headers)?.map(str::to_string),
+            None => None,
+        };
+
+        Ok(Grant {
+            principal,
+            substitute,
+        })
+    }
+
+    /// Mints the query string of a link to `scope`, valid for `expires_in_secs`,
+    /// up to the bucket's `max_expires_secs`.
+    pub fn sign(
+        &self,
+        config_name: &str,
+        scope: &SignatureScope,
+        expires_in_secs: u64,
+        client_ip: Option<IpAddr>,
+    ) -> Result<String, AppError> {
+        let verifier = self
+            .buckets
+            .get(config_name)
+            .ok_or_else(|| AppError::ConfigNotFound(config_name.to_string()))?
+            .signature
+            .as_ref()
+            .ok_or_else(|| {
+                AppError::InvalidSigningRequest(format!(
+                    "signatures are not required for config: {config_name}"
+                ))
+            })?;
+
+        let expires = verifier.expiry(signature::now(), expires_in_secs)?;
+        verifier.sign(config_name, scope, expires, client_ip)
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,66 @@
+use axum::http::{HeaderMap, Uri, header};
+
+use crate::config::BucketConfig;
+use crate::error::AppError;
+
+/// Hotlink protection based on the `Referer` header, or `Origin` when the
+/// browser sent no referer.
+pub struct RefererPolicy {
+    /// Lowercased host patterns: `example.com`, `*.example.com` or `*`.
+    patterns: Vec<String>,
+    allow_empty: bool,
+    placeholder: Option<String>,
+}
+
+impl RefererPolicy {
+    pub fn from_config(bc: &BucketConfig) -> Option<Self> {
+        if bc.allowed_referers.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            patterns: bc
+                .allowed_referers
+                .iter()
+                .map(|pattern| pattern.to_ascii_lowercase())
+                .collect(),
+            allow_empty: bc.allow_empty_referer,
+            placeholder: bc.hotlink_placeholder.clone(),
+        })
+    }
+
+    /// Returns the placeholder key to serve instead of the requested one when
+    /// the referer is refused and a placeholder is configured.
+    pub fn check(&self, headers: &HeaderMap) -> Result<Option<&str>, AppError> {
+        let referer = headers
+            .get(header::REFERER)
+            .or_else(|| headers.get(header::ORIGIN));
+
+        let allowed = match referer {
+            None => self.allow_empty,
+            Some(value) => value
+                .to_str()
+                .ok()
+                .and_then(|value| value.parse::<Uri>().ok())
+                .and_then(|uri| uri.host().map(str::to_ascii_lowercase))
+                .is_some_and(|host| self.patterns.iter().any(|p| host_matches(p, &host))),
+        };
+
+        if allowed {
+            return Ok(None);
+        }
+
+        match &self.placeholder {
+            Some(placeholder) => Ok(Some(placeholder)),
+            None => Err(AppError::Forbidden("hotlinking is not allowed".into())),
+        }
+    }
+}
+
+fn host_matches(pattern: &str, host: &str) -> bool {
+    match pattern.strip_prefix('*') {
+        Some("") => true,
+        Some(suffix) if suffix.starts_with('.') => host.ends_with(suffix),
+        _ => pattern == host,
+    }
+}
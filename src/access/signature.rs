@@ -0,0 +1,172 @@
+use std::net::IpAddr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use super::{AccessRequest, Denial};
+use crate::config::SignatureConfig;
+use crate::error::AppError;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// What a signed link grants access to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SignatureScope {
+    /// A single object key.
+    Path(String),
+    /// Every key starting with the prefix, passed along as `?prefix=...`.
+    Prefix(String),
+}
+
+/// Signs and verifies links of the form `?expires=<unix secs>&sig=<mac>[&prefix=<prefix>]`.
+///
+/// The MAC is an unpadded base64url HMAC-SHA256 over
+/// `<config_name>\n<path|prefix>:<scope>\n<expires>\n<client ip or empty>`.
+pub struct SignatureVerifier {
+    secret: Vec<u8>,
+    bind_client_ip: bool,
+    max_expires_secs: u64,
+}
+
+impl SignatureVerifier {
+    pub fn from_config(config: &SignatureConfig) -> eyre::Result<Self> {
+        if config.max_expires_secs == 0 {
+            eyre::bail!("`max_expires_secs` must be positive");
+        }
+        let secret = config.secret.resolve()?;
+        Ok(Self {
+            secret: secret.trim().as_bytes().to_vec(),
+            bind_client_ip: config.bind_client_ip,
+            max_expires_secs: config.max_expires_secs,
+        })
+    }
+
+    /// Absolute expiry of a link valid for `expires_in_secs` from `now`.
+    pub fn expiry(&self, now: u64, expires_in_secs: u64) -> Result<u64, AppError> {
+        if expires_in_secs > self.max_expires_secs {
+            return Err(AppError::InvalidSigningRequest(format!(
+                "`expires_in_secs` must be at most {}",
+                self.max_expires_secs
+            )));
+        }
+        now.checked_add(expires_in_secs).ok_or_else(|| {
+            AppError::InvalidSigningRequest("`expires_in_secs` is out of range".into())
+        })
+    }
+
+    pub fn sign(
+        &self,
+        config_name: &str,
+        scope: &SignatureScope,
+        expires: u64,
+        client_ip: Option<IpAddr>,
+    ) -> Result<String, AppError> {
+        if self.bind_client_ip && client_ip.is_none() {
+            return Err(AppError::InvalidSigningRequest(format!(
+                "a client IP is required to sign links for config: {config_name}"
+            )));
+        }
+        let client_ip = client_ip.filter(|_| self.bind_client_ip);
+
+        let sig = URL_SAFE_NO_PAD.encode(
+            self.mac(config_name, scope, expires, client_ip)
+                .finalize()
+                .into_bytes(),
+        );
+
+        let mut query = form_urlencoded::Serializer::new(String::new());
+        query.append_pair("expires", &expires.to_string());
+        if let SignatureScope::Prefix(prefix) = scope {
+            query.append_pair("prefix", prefix);
+        }
+        query.append_pair("sig", &sig);
+        Ok(query.finish())
+    }
+
+    pub fn verify(
+        &self,
+        config_name: &str,
+        request: &AccessRequest<'_>,
+        now: u64,
+    ) -> Result<(), Denial> {
+        let mut expires = None;
+        let mut sig = None;
+        let mut prefix = None;
+
+        for (name, value) in form_urlencoded::parse(request.query.unwrap_or("").as_bytes()) {
+            match name.as_ref() {
+                "expires" => expires = Some(value),
+                "sig" => sig = Some(value),
+                "prefix" => prefix = Some(value),
+                _ => {}
+            }
+        }
+
+        let (Some(expires), Some(sig)) = (expires, sig) else {
+            return Err(Denial::Missing);
+        };
+        let expires: u64 = expires.parse().map_err(|_| rejected("malformed expiry"))?;
+        if expires < now {
+            return Err(rejected("link expired"));
+        }
+
+        let scope = match prefix {
+            Some(prefix) if request.file_path.starts_with(prefix.as_ref()) => {
+                SignatureScope::Prefix(prefix.into_owned())
+            }
+            Some(_) => return Err(rejected("path outside of signed prefix")),
+            None => SignatureScope::Path(request.file_path.to_string()),
+        };
+
+        let client_ip = if self.bind_client_ip {
+            Some(
+                request
+                    .client_ip
+                    .ok_or_else(|| rejected("unknown client address"))?,
+            )
+        } else {
+            None
+        };
+
+        let sig = URL_SAFE_NO_PAD
+            .decode(sig.as_bytes())
+            .map_err(|_| rejected("malformed signature"))?;
+
+        self.mac(config_name, &scope, expires, client_ip)
+            .verify_slice(&sig)
+            .map_err(|_| rejected("signature mismatch"))
+    }
+
+    fn mac(
+        &self,
+        config_name: &str,
+        scope: &SignatureScope,
+        expires: u64,
+        client_ip: Option<IpAddr>,
+    ) -> HmacSha256 {
+        let scope = match scope {
+            SignatureScope::Path(path) => format!("path:{path}"),
+            SignatureScope::Prefix(prefix) => format!("prefix:{prefix}"),
+        };
+        let client_ip = client_ip.map(|ip| ip.to_string()).unwrap_or_default();
+
+        let mut mac =
+            HmacSha256::new_from_slice(&self.secret).expect("HMAC accepts keys of any length");
+        mac.update(format!("{config_name}\n{scope}\n{expires}\n{client_ip}").as_bytes());
+        mac
+    }
+}
+
+fn rejected(reason: &str) -> Denial {
+    Denial::Rejected(AppError::InvalidSignature(reason.to_string()))
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
@@ -0,0 +1,672 @@
+use std::net::{IpAddr, Ipv4Addr};
+
+use argon2::password_hash::{PasswordHasher, SaltString};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use jsonwebtoken::{EncodingKey, Header};
+use serde_json::json;
+
+use super::signature::now;
+use super::*;
+
+fn test_access(bind_client_ip: bool) -> AccessControl {
+    let config: AppConfig = serde_yaml::from_str(&format!(
+        r#"
+buckets:
+  public:
+    endpoint_url: "http://localhost:9000"
+    bucket_name: "public"
+    access_key:
+        plain: "key"
+    secret_key:
+        plain: "secret"
+  private:
+    endpoint_url: "http://localhost:9000"
+    bucket_name: "private"
+    access_key:
+        plain: "key"
+    secret_key:
+        plain: "secret"
+    require_signature:
+      secret:
+        plain: "signing-secret"
+      bind_client_ip: {bind_client_ip}
+"#
+    ))
+    .unwrap();
+    AccessControl::from_config(&config).unwrap()
+}
+
+async fn check(
+    access: &AccessControl,
+    config_name: &str,
+    file_path: &str,
+    query: Option<&str>,
+    client_ip: Option<IpAddr>,
+) -> Result<Option<String>, AppError> {
+    check_with_headers(
+        access,
+        config_name,
+        file_path,
+        query,
+        client_ip,
+        &HeaderMap::new(),
+    )
+    .await
+}
+
+async fn check_with_headers(
+    access: &AccessControl,
+    config_name: &str,
+    file_path: &str,
+    query: Option<&str>,
+    client_ip: Option<IpAddr>,
+    headers: &HeaderMap,
+) -> Result<Option<String>, AppError> {
+    access
+        .check(
+            config_name,
+            &AccessRequest {
+                file_path,
+                query,
+                headers,
+                client_ip,
+            },
+        )
+        .await
+        .map(|grant| grant.principal)
+}
+
+const CLIENT: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 7));
+const OTHER_CLIENT: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 8));
+
+#[tokio::test]
+async fn unprotected_bucket_is_open() {
+    let access = test_access(false);
+    assert!(check(&access, "public", "a.jpg", None, None).await.is_ok());
+    assert!(check(&access, "unknown", "a.jpg", None, None).await.is_ok());
+}
+
+#[tokio::test]
+async fn missing_signature_is_rejected() {
+    let access = test_access(false);
+    let result = check(&access, "private", "a.jpg", None, None).await;
+    assert!(matches!(result, Err(AppError::InvalidSignature(_))));
+}
+
+#[tokio::test]
+async fn valid_path_signature_is_accepted() {
+    let access = test_access(false);
+    let query = access
+        .sign("private", &SignatureScope::Path("a.jpg".into()), 60, None)
+        .unwrap();
+    assert!(
+        check(&access, "private", "a.jpg", Some(&query), None)
+            .await
+            .is_ok()
+    );
+}
+
+#[tokio::test]
+async fn path_signature_does_not_cover_other_keys() {
+    let access = test_access(false);
+    let query = access
+        .sign("private", &SignatureScope::Path("a.jpg".into()), 60, None)
+        .unwrap();
+    let result = check(&access, "private", "b.jpg", Some(&query), None).await;
+    assert!(matches!(result, Err(AppError::InvalidSignature(_))));
+}
+
+#[tokio::test]
+async fn path_signature_cannot_be_widened_to_prefix() {
+    let access = test_access(false);
+    let query = access
+        .sign("private", &SignatureScope::Path("a".into()), 60, None)
+        .unwrap();
+    let widened = format!("{query}&prefix=a");
+    let result = check(&access, "private", "a.jpg", Some(&widened), None).await;
+    assert!(matches!(result, Err(AppError::InvalidSignature(_))));
+}
+
+#[tokio::test]
+async fn prefix_signature_covers_keys_under_prefix() {
+    let access = test_access(false);
+    let query = access
+        .sign("private", &SignatureScope::Prefix("2026/".into()), 60, None)
+        .unwrap();
+    assert!(
+        check(&access, "private", "2026/a.jpg", Some(&query), None)
+            .await
+            .is_ok()
+    );
+
+    let result = check(&access, "private", "2025/a.jpg", Some(&query), None).await;
+    assert!(matches!(result, Err(AppError::InvalidSignature(_))));
+}
+
+#[tokio::test]
+async fn expired_signature_is_rejected() {
+    let access = test_access(false);
+    let verifier = access.buckets["private"].signature.as_ref().unwrap();
+    let query = verifier
+        .sign(
+            "private",
+            &SignatureScope::Path("a.jpg".into()),
+            now() - 1,
+            None,
+        )
+        .unwrap();
+    let result = check(&access, "private", "a.jpg", Some(&query), None).await;
+    assert!(matches!(result, Err(AppError::InvalidSignature(_))));
+}
+
+#[tokio::test]
+async fn tampered_signature_is_rejected() {
+    let access = test_access(false);
+    let query = access
+        .sign("private", &SignatureScope::Path("a.jpg".into()), 60, None)
+        .unwrap();
+    let tampered = query.replace("expires=", "expires=1");
+    let result = check(&access, "private", "a.jpg", Some(&tampered), None).await;
+    assert!(matches!(result, Err(AppError::InvalidSignature(_))));
+}
+
+#[tokio::test]
+async fn ip_bound_signature_requires_same_client() {
+    let access = test_access(true);
+    let query = access
+        .sign(
+            "private",
+            &SignatureScope::Path("a.jpg".into()),
+            60,
+            Some(CLIENT),
+        )
+        .unwrap();
+
+    assert!(
+        check(&access, "private", "a.jpg", Some(&query), Some(CLIENT))
+            .await
+            .is_ok()
+    );
+
+    let result = check(
+        &access,
+        "private",
+        "a.jpg",
+        Some(&query),
+        Some(OTHER_CLIENT),
+    )
+    .await;
+    assert!(matches!(result, Err(AppError::InvalidSignature(_))));
+}
+
+#[test]
+fn ip_bound_signing_requires_client_ip() {
+    let access = test_access(true);
+    let result = access.sign("private", &SignatureScope::Path("a.jpg".into()), 60, None);
+    assert!(matches!(result, Err(AppError::InvalidSigningRequest(_))));
+}
+
+#[test]
+fn signing_beyond_max_lifetime_is_rejected() {
+    let access = test_access(false);
+    let scope = SignatureScope::Path("a.jpg".into());
+    for expires_in_secs in [7 * 24 * 60 * 60 + 1, u64::MAX] {
+        let result = access.sign("private", &scope, expires_in_secs, None);
+        assert!(matches!(result, Err(AppError::InvalidSigningRequest(_))));
+    }
+}
+
+#[test]
+fn expiry_overflow_is_rejected() {
+    let access = test_access(false);
+    let verifier = access.buckets["private"].signature.as_ref().unwrap();
+    assert_eq!(verifier.expiry(100, 60).unwrap(), 160);
+    assert!(matches!(
+        verifier.expiry(u64::MAX, 60),
+        Err(AppError::InvalidSigningRequest(_))
+    ));
+}
+
+const JWT_SECRET: &[u8] = b"jwt-test-secret";
+
+fn jwt_access(extra: &str) -> AccessControl {
+    let jwks = json!({
+        "keys": [{
+            "kty": "oct",
+            "kid": "k1",
+            "alg": "HS256",
+            "k": URL_SAFE_NO_PAD.encode(JWT_SECRET),
+        }]
+    });
+    let config: AppConfig = serde_yaml::from_str(&format!(
+        r#"
+buckets:
+  members:
+    endpoint_url: "http://localhost:9000"
+    bucket_name: "members"
+    access_key:
+        plain: "key"
+    secret_key:
+        plain: "secret"
+    jwt:
+      jwks:
+        plain: '{jwks}'
+      audience: ["media"]
+      issuer: ["https://auth.example.com"]
+      cookie: "media_token"
+{extra}
+"#
+    ))
+    .unwrap();
+    AccessControl::from_config(&config).unwrap()
+}
+
+fn token(claims: serde_json::Value) -> String {
+    let mut header = Header::new(jsonwebtoken::Algorithm::HS256);
+    header.kid = Some("k1".into());
+    jsonwebtoken::encode(&header, &claims, &EncodingKey::from_secret(JWT_SECRET)).unwrap()
+}
+
+fn valid_claims() -> serde_json::Value {
+    json!({
+        "sub": "alice",
+        "aud": "media",
+        "iss": "https://auth.example.com",
+        "exp": now() + 60,
+        "paths": ["reports/"],
+    })
+}
+
+fn bearer(token: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        axum::http::header::AUTHORIZATION,
+        format!("Bearer {token}").parse().unwrap(),
+    );
+    headers
+}
+
+#[tokio::test]
+async fn missing_token_is_unauthorized() {
+    let access = jwt_access("");
+    let result = check(&access, "members", "a.pdf", None, None).await;
+    assert!(matches!(result, Err(AppError::Unauthorized(_))));
+}
+
+#[tokio::test]
+async fn valid_bearer_token_returns_subject() {
+    let access = jwt_access("");
+    let headers = bearer(&token(valid_claims()));
+    let principal = check_with_headers(&access, "members", "a.pdf", None, None, &headers)
+        .await
+        .unwrap();
+    assert_eq!(principal.as_deref(), Some("alice"));
+}
+
+#[tokio::test]
+async fn valid_cookie_token_is_accepted() {
+    let access = jwt_access("");
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        axum::http::header::COOKIE,
+        format!("theme=dark; media_token={}", token(valid_claims()))
+            .parse()
+            .unwrap(),
+    );
+    assert!(
+        check_with_headers(&access, "members", "a.pdf", None, None, &headers)
+            .await
+            .is_ok()
+    );
+}
+
+#[tokio::test]
+async fn wrong_audience_is_unauthorized() {
+    let access = jwt_access("");
+    let mut claims = valid_claims();
+    claims["aud"] = json!("other");
+    let headers = bearer(&token(claims));
+    let result = check_with_headers(&access, "members", "a.pdf", None, None, &headers).await;
+    assert!(matches!(result, Err(AppError::Unauthorized(_))));
+}
+
+#[tokio::test]
+async fn wrong_issuer_is_unauthorized() {
+    let access = jwt_access("");
+    let mut claims = valid_claims();
+    claims["iss"] = json!("https://evil.example.com");
+    let headers = bearer(&token(claims));
+    let result = check_with_headers(&access, "members", "a.pdf", None, None, &headers).await;
+    assert!(matches!(result, Err(AppError::Unauthorized(_))));
+}
+
+#[tokio::test]
+async fn expired_token_is_unauthorized() {
+    let access = jwt_access("");
+    let mut claims = valid_claims();
+    claims["exp"] = json!(now() - 3600);
+    let headers = bearer(&token(claims));
+    let result = check_with_headers(&access, "members", "a.pdf", None, None, &headers).await;
+    assert!(matches!(result, Err(AppError::Unauthorized(_))));
+}
+
+#[tokio::test]
+async fn paths_claim_scopes_access() {
+    let access = jwt_access("      paths_claim: \"paths\"");
+    let headers = bearer(&token(valid_claims()));
+
+    assert!(
+        check_with_headers(&access, "members", "reports/q1.pdf", None, None, &headers)
+            .await
+            .is_ok()
+    );
+
+    let result =
+        check_with_headers(&access, "members", "private/q1.pdf", None, None, &headers).await;
+    assert!(matches!(result, Err(AppError::Forbidden(_))));
+}
+
+#[test]
+fn pem_key_requires_algorithms() {
+    let config: AppConfig = serde_yaml::from_str(
+        r#"
+buckets:
+  members:
+    endpoint_url: "http://localhost:9000"
+    bucket_name: "members"
+    access_key:
+        plain: "key"
+    secret_key:
+        plain: "secret"
+    jwt:
+      pem:
+        plain: "-----BEGIN PUBLIC KEY-----"
+"#,
+    )
+    .unwrap();
+    assert!(AccessControl::from_config(&config).is_err());
+}
+
+fn credentials_access() -> AccessControl {
+    let bcrypt_hash = bcrypt::hash("hunter2", 4).unwrap();
+    let argon2_hash = argon2::Argon2::default()
+        .hash_password(
+            b"correct horse",
+            &SaltString::from_b64("c29tZXNhbHRzb21lc2FsdA").unwrap(),
+        )
+        .unwrap()
+        .to_string();
+    let config: AppConfig = serde_yaml::from_str(&format!(
+        r#"
+buckets:
+  internal:
+    endpoint_url: "http://localhost:9000"
+    bucket_name: "internal"
+    access_key:
+        plain: "key"
+    secret_key:
+        plain: "secret"
+    users:
+      - name: "alice"
+        password_hash:
+          plain: "{bcrypt_hash}"
+      - name: "bob"
+        password_hash:
+          plain: "{argon2_hash}"
+    api_keys:
+      - name: "ci"
+        key:
+          plain: "ci-key"
+    api_key_header: "X-Media-Key"
+"#
+    ))
+    .unwrap();
+    AccessControl::from_config(&config).unwrap()
+}
+
+fn basic(user: &str, password: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    let encoded = base64::engine::general_purpose::STANDARD.encode(format!("{user}:{password}"));
+    headers.insert(
+        axum::http::header::AUTHORIZATION,
+        format!("Basic {encoded}").parse().unwrap(),
+    );
+    headers
+}
+
+#[tokio::test]
+async fn missing_credentials_requires_basic_auth() {
+    let access = credentials_access();
+    let result = check(&access, "internal", "a.txt", None, None).await;
+    assert!(matches!(result, Err(AppError::BasicAuthRequired(_))));
+}
+
+#[tokio::test]
+async fn bcrypt_user_is_accepted() {
+    let access = credentials_access();
+    let headers = basic("alice", "hunter2");
+    let principal = check_with_headers(&access, "internal", "a.txt", None, None, &headers)
+        .await
+        .unwrap();
+    assert_eq!(principal.as_deref(), Some("alice"));
+
+    // Served from the verified-credentials cache the second time around.
+    let principal = check_with_headers(&access, "internal", "a.txt", None, None, &headers)
+        .await
+        .unwrap();
+    assert_eq!(principal.as_deref(), Some("alice"));
+}
+
+#[tokio::test]
+async fn argon2_user_is_accepted() {
+    let access = credentials_access();
+    let headers = basic("bob", "correct horse");
+    let principal = check_with_headers(&access, "internal", "a.txt", None, None, &headers)
+        .await
+        .unwrap();
+    assert_eq!(principal.as_deref(), Some("bob"));
+}
+
+#[tokio::test]
+async fn wrong_password_is_rejected() {
+    let access = credentials_access();
+    for headers in [basic("alice", "nope"), basic("mallory", "hunter2")] {
+        let result = check_with_headers(&access, "internal", "a.txt", None, None, &headers).await;
+        assert!(matches!(result, Err(AppError::BasicAuthRequired(_))));
+    }
+}
+
+#[tokio::test]
+async fn unknown_user_is_rejected_whatever_the_password() {
+    // Unknown users are checked against a configured hash to keep timing
+    // uniform, which must never let one of its passwords through.
+    let access = credentials_access();
+    for password in ["hunter2", "correct horse"] {
+        let headers = basic("mallory", password);
+        let result = check_with_headers(&access, "internal", "a.txt", None, None, &headers).await;
+        assert!(matches!(result, Err(AppError::BasicAuthRequired(_))));
+    }
+}
+
+#[tokio::test]
+async fn api_key_is_accepted() {
+    let access = credentials_access();
+    let mut headers = HeaderMap::new();
+    headers.insert("x-media-key", "ci-key".parse().unwrap());
+    let principal = check_with_headers(&access, "internal", "a.txt", None, None, &headers)
+        .await
+        .unwrap();
+    assert_eq!(principal.as_deref(), Some("ci"));
+}
+
+#[tokio::test]
+async fn wrong_api_key_is_rejected() {
+    let access = credentials_access();
+    let mut headers = HeaderMap::new();
+    headers.insert("x-media-key", "nope".parse().unwrap());
+    let result = check_with_headers(&access, "internal", "a.txt", None, None, &headers).await;
+    assert!(matches!(result, Err(AppError::Unauthorized(_))));
+}
+
+#[test]
+fn invalid_password_hash_is_a_config_error() {
+    let config: AppConfig = serde_yaml::from_str(
+        r#"
+buckets:
+  internal:
+    endpoint_url: "http://localhost:9000"
+    bucket_name: "internal"
+    access_key:
+        plain: "key"
+    secret_key:
+        plain: "secret"
+    users:
+      - name: "alice"
+        password_hash:
+          plain: "hunter2"
+"#,
+    )
+    .unwrap();
+    assert!(AccessControl::from_config(&config).is_err());
+}
+
+fn cidr_access() -> AccessControl {
+    let config: AppConfig = serde_yaml::from_str(
+        r#"
+buckets:
+  office:
+    endpoint_url: "http://localhost:9000"
+    bucket_name: "office"
+    access_key:
+        plain: "key"
+    secret_key:
+        plain: "secret"
+    allow_cidrs: ["192.0.2.0/24"]
+    deny_cidrs: ["192.0.2.8"]
+"#,
+    )
+    .unwrap();
+    AccessControl::from_config(&config).unwrap()
+}
+
+#[tokio::test]
+async fn allow_listed_client_is_accepted() {
+    let access = cidr_access();
+    assert!(
+        check(&access, "office", "a.txt", None, Some(CLIENT))
+            .await
+            .is_ok()
+    );
+}
+
+#[tokio::test]
+async fn denied_client_is_forbidden() {
+    let access = cidr_access();
+    let result = check(&access, "office", "a.txt", None, Some(OTHER_CLIENT)).await;
+    assert!(matches!(result, Err(AppError::Forbidden(_))));
+}
+
+#[tokio::test]
+async fn client_outside_allow_list_is_forbidden() {
+    let access = cidr_access();
+    let outside = IpAddr::V4(Ipv4Addr::new(198, 51, 100, 1));
+    let result = check(&access, "office", "a.txt", None, Some(outside)).await;
+    assert!(matches!(result, Err(AppError::Forbidden(_))));
+
+    let result = check(&access, "office", "a.txt", None, None).await;
+    assert!(matches!(result, Err(AppError::Forbidden(_))));
+}
+
+fn referer_access(extra: &str) -> AccessControl {
+    let config: AppConfig = serde_yaml::from_str(&format!(
+        r#"
+buckets:
+  images:
+    endpoint_url: "http://localhost:9000"
+    bucket_name: "images"
+    access_key:
+        plain: "key"
+    secret_key:
+        plain: "secret"
+    allowed_referers: ["example.com", "*.example.org"]
+{extra}
+"#
+    ))
+    .unwrap();
+    AccessControl::from_config(&config).unwrap()
+}
+
+async fn grant(access: &AccessControl, headers: &[(&str, &str)]) -> Result<Grant, AppError> {
+    let mut header_map = HeaderMap::new();
+    for (name, value) in headers {
+        header_map.insert(
+            axum::http::HeaderName::from_bytes(name.as_bytes()).unwrap(),
+            value.parse().unwrap(),
+        );
+    }
+    access
+        .check(
+            "images",
+            &AccessRequest {
+                file_path: "cat.jpg",
+                query: None,
+                headers: &header_map,
+                client_ip: None,
+            },
+        )
+        .await
+}
+
+#[tokio::test]
+async fn allowed_referers_are_accepted() {
+    let access = referer_access("");
+    for referer in [
+        "https://example.com/gallery",
+        "https://www.example.org/",
+        "http://EXAMPLE.com",
+    ] {
+        let grant = grant(&access, &[("referer", referer)]).await.unwrap();
+        assert!(grant.substitute.is_none(), "{referer}");
+    }
+}
+
+#[tokio::test]
+async fn foreign_referer_is_forbidden() {
+    let access = referer_access("");
+    for referer in ["https://evil.com/", "https://example.org/", "not a url"] {
+        let result = grant(&access, &[("referer", referer)]).await;
+        assert!(matches!(result, Err(AppError::Forbidden(_))), "{referer}");
+    }
+}
+
+#[tokio::test]
+async fn origin_is_used_without_referer() {
+    let access = referer_access("");
+    assert!(
+        grant(&access, &[("origin", "https://example.com")])
+            .await
+            .is_ok()
+    );
+
+    let result = grant(&access, &[("origin", "https://evil.com")]).await;
+    assert!(matches!(result, Err(AppError::Forbidden(_))));
+}
+
+#[tokio::test]
+async fn empty_referer_follows_config() {
+    assert!(grant(&referer_access(""), &[]).await.is_ok());
+
+    let access = referer_access("    allow_empty_referer: false");
+    let result = grant(&access, &[]).await;
+    assert!(matches!(result, Err(AppError::Forbidden(_))));
+}
+
+#[tokio::test]
+async fn foreign_referer_gets_placeholder() {
+    let access = referer_access("    hotlink_placeholder: \"hotlink.png\"");
+    let grant = grant(&access, &[("referer", "https://evil.com/")])
+        .await
+        .unwrap();
+    assert_eq!(grant.substitute.as_deref(), Some("hotlink.png"));
+}
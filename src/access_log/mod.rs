@@ -0,0 +1,351 @@
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::task::{Context, Poll, ready};
+use std::time::{Duration, Instant};
+
+use axum::body::{Body, Bytes};
+use axum::extract::{Request, State};
+use axum::http::{HeaderMap, header};
+use axum::middleware::Next;
+use axum::response::Response;
+use eyre::WrapErr;
+use http_body::{Frame, SizeHint};
+use serde::Serialize;
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+use time::macros::format_description;
+
+use crate::client_ip::ClientIp;
+use crate::config::{AccessLogFormat, AppConfig};
+use crate::request_id::RequestId;
+
+tokio::task_local! {
+    static UPSTREAM_TIME: UpstreamTime;
+}
+
+/// Time a request spent waiting on S3, summed over its calls.
+#[derive(Clone, Default)]
+struct UpstreamTime(Arc<AtomicU64>);
+
+impl UpstreamTime {
+    fn get(&self) -> Duration {
+        Duration::from_nanos(self.0.load(Ordering::Relaxed))
+    }
+}
+
+/// Adds an S3 call to the upstream time of the request being logged, if any.
+pub fn record_upstream(elapsed: Duration) {
+    let _ = UPSTREAM_TIME.try_with(|total| {
+        let nanos = u64::try_from(elapsed.as_nanos()).unwrap_or(u64::MAX);
+        total.0.fetch_add(nanos, Ordering::Relaxed);
+    });
+}
+
+pub struct AccessLog {
+    format: AccessLogFormat,
+    sink: Sink,
+    modes: HashMap<String, &'static str>,
+}
+
+enum Sink {
+    Stdout,
+    File { path: PathBuf, file: Mutex<File> },
+}
+
+impl AccessLog {
+    /// Opens the configured log, or returns `None` when access logging is off.
+    pub fn from_config(config: &AppConfig) -> eyre::Result<Option<Self>> {
+        let Some(log) = &config.access_log else {
+            return Ok(None);
+        };
+        let sink = match &log.path {
+            Some(path) => Sink::File {
+                file: Mutex::new(open(path)?),
+                path: path.clone(),
+            },
+            None => Sink::Stdout,
+        };
+        let modes = config
+            .buckets
+            .iter()
+            .map(|(name, bc)| (name.clone(), bc.mode()))
+            .collect();
+        Ok(Some(Self {
+            format: log.format,
+            sink,
+            modes,
+        }))
+    }
+
+    /// Reopens the log file, so that logrotate can move the old one away.
+    pub fn reopen(&self) -> eyre::Result<()> {
+        if let Sink::File { path, file } = &self.sink {
+            let reopened = open(path)?;
+            *file.lock().unwrap_or_else(PoisonError::into_inner) = reopened;
+        }
+        Ok(())
+    }
+
+    fn write(&self, entry: &Entry) {
+        let mut line = match self.format {
+            AccessLogFormat::Combined => entry.combined(),
+            AccessLogFormat::Json => entry.json(),
+        };
+        line.push('\n');
+        let result = match &self.sink {
+            Sink::Stdout => std::io::stdout().lock().write_all(line.as_bytes()),
+            Sink::File { file, .. } => file
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .write_all(line.as_bytes()),
+        };
+        if let Err(err) = result {
+            tracing::error!("Failed to write access log: {err}");
+        }
+    }
+}
+
+fn open(path: &PathBuf) -> eyre::Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .wrap_err_with(|| format!("failed to open access log {}", path.display()))
+}
+
+/// A finished request, written once its body has been sent or dropped.
+struct Entry {
+    time: OffsetDateTime,
+    started: Instant,
+    client_ip: Option<IpAddr>,
+    request_line: String,
+    method: String,
+    uri: String,
+    protocol: String,
+    status: u16,
+    bytes_sent: u64,
+    referer: Option<String>,
+    user_agent: Option<String>,
+    request_id: Option<Arc<str>>,
+    bucket: Option<String>,
+    key: Option<String>,
+    mode: Option<&'static str>,
+    upstream: UpstreamTime,
+}
+
+#[derive(Serialize)]
+struct JsonEntry<'a> {
+    time: String,
+    client_ip: Option<IpAddr>,
+    method: &'a str,
+    uri: &'a str,
+    protocol: &'a str,
+    status: u16,
+    bytes_sent: u64,
+    duration_secs: f64,
+    upstream_duration_secs: f64,
+    referer: Option<&'a str>,
+    user_agent: Option<&'a str>,
+    request_id: Option<&'a str>,
+    bucket: Option<&'a str>,
+    key: Option<&'a str>,
+    mode: Option<&'a str>,
+}
+
+impl Entry {
+    /// `host ident user [time] "request" status bytes "referer" "user-agent"`
+    /// followed by duration, upstream duration, request ID, bucket, mode and key.
+    fn combined(&self) -> String {
+        let time = self
+            .time
+            .format(format_description!(
+                "[day]/[month repr:short]/[year]:[hour]:[minute]:[second] [offset_hour sign:mandatory][offset_minute]"
+            ))
+            .unwrap_or_default();
+        let bytes = match self.bytes_sent {
+            0 => "-".to_string(),
+            bytes => bytes.to_string(),
+        };
+        format!(
+            "{client} - - [{time}] \"{request}\" {status} {bytes} \"{referer}\" \"{user_agent}\" {duration:.3} {upstream:.3} {request_id} {bucket} {mode} \"{key}\"",
+            client = self.client_ip.map_or("-".to_string(), |ip| ip.to_string()),
+            request = escape(&self.request_line),
+            status = self.status,
+            referer = escape(self.referer.as_deref().unwrap_or("-")),
+            user_agent = escape(self.user_agent.as_deref().unwrap_or("-")),
+            duration = self.started.elapsed().as_secs_f64(),
+            upstream = self.upstream.get().as_secs_f64(),
+            request_id = self.request_id.as_deref().unwrap_or("-"),
+            bucket = self.bucket.as_deref().unwrap_or("-"),
+            mode = self.mode.unwrap_or("-"),
+            key = escape(self.key.as_deref().unwrap_or("-")),
+        )
+    }
+
+    fn json(&self) -> String {
+        let entry = JsonEntry {
+            time: self.time.format(&Rfc3339).unwrap_or_default(),
+            client_ip: self.client_ip,
+            method: &self.method,
+            uri: &self.uri,
+            protocol: &self.protocol,
+            status: self.status,
+            bytes_sent: self.bytes_sent,
+            duration_secs: self.started.elapsed().as_secs_f64(),
+            upstream_duration_secs: self.upstream.get().as_secs_f64(),
+            referer: self.referer.as_deref(),
+            user_agent: self.user_agent.as_deref(),
+            request_id: self.request_id.as_deref(),
+            bucket: self.bucket.as_deref(),
+            key: self.key.as_deref(),
+            mode: self.mode,
+        };
+        serde_json::to_string(&entry).unwrap_or_default()
+    }
+}
+
+/// Escapes quotes, backslashes and control characters the way Apache does, so
+/// that quoted fields cannot be broken out of.
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => escaped.push_str(&format!("\\x{:02x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn header_text(headers: &HeaderMap, name: header::HeaderName) -> Option<String> {
+    headers
+        .get(name)
+        .map(|value| String::from_utf8_lossy(value.as_bytes()).into_owned())
+}
+
+/// Logs every request once its response body has been sent.
+pub async fn log_requests(
+    State(log): State<Arc<AccessLog>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let time = OffsetDateTime::now_utc();
+    let started = Instant::now();
+    let uri = request
+        .uri()
+        .path_and_query()
+        .map_or("/", |path| path.as_str())
+        .to_string();
+    let method = request.method().to_string();
+    let protocol = format!("{:?}", request.version());
+
+    let path = request.uri().path().trim_start_matches('/');
+    let (config_name, key) = path.split_once('/').unwrap_or((path, ""));
+    let mode = log.modes.get(config_name).copied();
+    let (bucket, key) = match mode {
+        Some(_) => (
+            Some(config_name.to_string()),
+            Some(
+                percent_encoding::percent_decode_str(key)
+                    .decode_utf8_lossy()
+                    .into_owned(),
+            ),
+        ),
+        None => (None, None),
+    };
+
+    let client_ip = request
+        .extensions()
+        .get::<ClientIp>()
+        .map(|ClientIp(ip)| *ip);
+    let request_id = request
+        .extensions()
+        .get::<RequestId>()
+        .map(|RequestId(id)| id.clone());
+    let referer = header_text(request.headers(), header::REFERER);
+    let user_agent = header_text(request.headers(), header::USER_AGENT);
+
+    let upstream = UpstreamTime::default();
+    let response = UPSTREAM_TIME
+        .scope(upstream.clone(), next.run(request))
+        .await;
+
+    let entry = Entry {
+        time,
+        started,
+        client_ip,
+        request_line: format!("{method} {uri} {protocol}"),
+        method,
+        uri,
+        protocol,
+        status: response.status().as_u16(),
+        bytes_sent: 0,
+        referer,
+        user_agent,
+        request_id,
+        bucket,
+        key,
+        mode,
+        upstream,
+    };
+    response.map(|body| {
+        Body::new(LoggedBody {
+            inner: body,
+            entry: Some(entry),
+            log,
+        })
+    })
+}
+
+/// Counts the bytes of a response body and writes its log entry when dropped.
+struct LoggedBody {
+    inner: Body,
+    entry: Option<Entry>,
+    log: Arc<AccessLog>,
+}
+
+impl http_body::Body for LoggedBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, axum::Error>>> {
+        let frame = ready!(Pin::new(&mut self.inner).poll_frame(cx));
+        if let Some(Ok(frame)) = &frame
+            && let Some(data) = frame.data_ref()
+            && let Some(entry) = &mut self.entry
+        {
+            entry.bytes_sent += data.len() as u64;
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl Drop for LoggedBody {
+    fn drop(&mut self) {
+        if let Some(entry) = self.entry.take() {
+            self.log.write(&entry);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,159 @@
+use std::path::Path;
+
+use axum::Router;
+use axum::middleware;
+use axum::routing::get;
+use http_body_util::BodyExt;
+use tower::ServiceExt;
+
+use super::*;
+use crate::client_ip::TrustedProxies;
+use crate::request_id::assign_request_id;
+
+fn temp_log() -> PathBuf {
+    std::env::temp_dir().join(format!("media-server-access-{}.log", uuid::Uuid::new_v4()))
+}
+
+fn access_log(format: &str, path: &Path) -> Arc<AccessLog> {
+    let config: AppConfig = serde_yaml::from_str(&format!(
+        r#"
+access_log:
+  format: {format}
+  path: "{path}"
+buckets:
+  photos:
+    endpoint_url: "http://localhost:9000"
+    bucket_name: "photos"
+    access_key:
+        plain: "key"
+    secret_key:
+        plain: "secret"
+    proxy: true
+"#,
+        path = path.display()
+    ))
+    .unwrap();
+    Arc::new(AccessLog::from_config(&config).unwrap().unwrap())
+}
+
+fn app(log: Arc<AccessLog>) -> Router {
+    Router::new()
+        .route(
+            "/photos/{*key}",
+            get(|| async {
+                record_upstream(Duration::from_millis(250));
+                "hello"
+            }),
+        )
+        .layer(middleware::from_fn_with_state(log, log_requests))
+        .layer(middleware::from_fn(
+            |mut request: Request, next: Next| async move {
+                request
+                    .extensions_mut()
+                    .insert(ClientIp("192.0.2.7".parse().unwrap()));
+                next.run(request).await
+            },
+        ))
+        .layer(middleware::from_fn_with_state(
+            Arc::new(TrustedProxies::default()),
+            assign_request_id,
+        ))
+}
+
+async fn send(app: Router, uri: &str) -> Response {
+    let resp = app
+        .oneshot(
+            Request::builder()
+                .uri(uri)
+                .header(header::REFERER, "https://example.com/")
+                .header(header::USER_AGENT, "curl/8.0 \"quoted\"")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let (parts, body) = resp.into_parts();
+    body.collect().await.unwrap();
+    Response::from_parts(parts, Body::empty())
+}
+
+#[tokio::test]
+async fn combined_lines_carry_extra_fields() {
+    let path = temp_log();
+    let resp = send(app(access_log("combined", &path)), "/photos/a%20b.jpg?w=10").await;
+    let line = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let request_id = resp.headers()["x-request-id"].to_str().unwrap();
+    assert!(line.starts_with("192.0.2.7 - - ["), "{line}");
+    assert!(
+        line.contains(
+            r#""GET /photos/a%20b.jpg?w=10 HTTP/1.1" 200 5 "https://example.com/" "curl/8.0 \"quoted\"" "#
+        ),
+        "{line}"
+    );
+    assert!(
+        line.ends_with(&format!(" {request_id} photos proxy \"a b.jpg\"\n")),
+        "{line}"
+    );
+}
+
+#[tokio::test]
+async fn json_lines_report_bytes_and_upstream_time() {
+    let path = temp_log();
+    let resp = send(app(access_log("json", &path)), "/photos/cat.jpg").await;
+    let line = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let entry: serde_json::Value = serde_json::from_str(&line).unwrap();
+    assert_eq!(entry["client_ip"], "192.0.2.7");
+    assert_eq!(entry["status"], 200);
+    assert_eq!(entry["bytes_sent"], 5);
+    assert_eq!(entry["bucket"], "photos");
+    assert_eq!(entry["key"], "cat.jpg");
+    assert_eq!(entry["mode"], "proxy");
+    assert_eq!(entry["user_agent"], "curl/8.0 \"quoted\"");
+    assert_eq!(
+        entry["request_id"],
+        resp.headers()["x-request-id"].to_str().unwrap()
+    );
+    assert!(entry["upstream_duration_secs"].as_f64().unwrap() >= 0.25);
+}
+
+#[tokio::test]
+async fn unknown_buckets_are_logged_without_bucket_fields() {
+    let path = temp_log();
+    send(app(access_log("json", &path)), "/healthz").await;
+    let line = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let entry: serde_json::Value = serde_json::from_str(&line).unwrap();
+    assert_eq!(entry["status"], 404);
+    assert!(entry["bucket"].is_null());
+    assert!(entry["key"].is_null());
+}
+
+#[tokio::test]
+async fn reopen_starts_a_new_file() {
+    let path = temp_log();
+    let rotated = path.with_extension("log.1");
+    let log = access_log("json", &path);
+
+    send(app(log.clone()), "/photos/one.jpg").await;
+    std::fs::rename(&path, &rotated).unwrap();
+    log.reopen().unwrap();
+    send(app(log), "/photos/two.jpg").await;
+
+    let old = std::fs::read_to_string(&rotated).unwrap();
+    let new = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&rotated).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(old.contains("one.jpg") && !old.contains("two.jpg"));
+    assert!(new.contains("two.jpg"));
+}
+
+#[test]
+fn escape_protects_quoted_fields() {
+    assert_eq!(escape(r#"a"b\c"#), r#"a\"b\\c"#);
+    assert_eq!(escape("line\nbreak"), "line\\x0abreak");
+}
@@ -0,0 +1,288 @@
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use axum::Json;
+use axum::Router;
+use axum::body::Body;
+use axum::extract::{Path, Request, State};
+use axum::http::header;
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{delete, get, post};
+use serde::{Deserialize, Serialize};
+
+use crate::access::{AccessControl, SignatureScope};
+use crate::cache::PurgeTarget;
+use crate::circuit::CircuitState;
+use crate::config::AdminConfig;
+use crate::error::AppError;
+use crate::s3::{PrefetchReport, PrefetchTarget, S3Clients};
+
+#[derive(Clone)]
+struct AdminState {
+    clients: Arc<S3Clients>,
+    access: Arc<AccessControl>,
+    token: Arc<str>,
+    prefetch_concurrency: usize,
+}
+
+/// Routes of the admin API, to be nested under `/_admin`.
+pub fn router(
+    clients: Arc<S3Clients>,
+    access: Arc<AccessControl>,
+    config: &AdminConfig,
+) -> eyre::Result<Router> {
+    let token = config.token.resolve()?;
+    let state = AdminState {
+        clients,
+        access,
+        token: token.trim().into(),
+        prefetch_concurrency: config.prefetch_concurrency,
+    };
+
+    Ok(Router::new()
+        .route("/buckets/{config_name}/prefetch", post(prefetch))
+        .route("/buckets/{config_name}/purge", post(purge))
+        .route("/buckets/{config_name}/cache", delete(purge_bucket))
+        .route("/buckets/{config_name}/sign", post(sign))
+        .route("/events", post(bucket_events))
+        .route("/circuits", get(circuits))
+        .route_layer(middleware::from_fn_with_state(state.clone(), require_token))
+        .with_state(state))
+}
+
+async fn require_token(State(state): State<AdminState>, request: Request, next: Next) -> Response {
+    let provided = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+
+    match provided {
+        Some(token) if constant_time_eq(token.as_bytes(), state.token.as_bytes()) => {
+            next.run(request).await
+        }
+        _ => AppError::Unauthorized("missing or invalid admin token".into()).into_response(),
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+async fn circuits(State(state): State<AdminState>) -> Json<BTreeMap<String, CircuitState>> {
+    Json(state.clients.circuit_states())
+}
+
+/// A line of the NDJSON prefetch response.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum PrefetchEvent {
+    Progress(PrefetchReport),
+    Done(PrefetchReport),
+    Error(String),
+}
+
+/// Streams the prefetch's progress as NDJSON, ending with its totals. The
+/// prefetch runs in its own task, so it completes even if the client gives up
+/// waiting on a large prefix.
+async fn prefetch(
+    State(state): State<AdminState>,
+    Path(config_name): Path<String>,
+    Json(target): Json<PrefetchTarget>,
+) -> Result<Response, AppError> {
+    state.clients.check_prefetch(&config_name)?;
+
+    let (events, receiver) = tokio::sync::mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        let result = state
+            .clients
+            .prefetch(&config_name, target, state.prefetch_concurrency, |report| {
+                let _ = events.send(PrefetchEvent::Progress(report.clone()));
+            })
+            .await;
+        let _ = events.send(match result {
+            Ok(report) => PrefetchEvent::Done(report),
+            Err(err) => PrefetchEvent::Error(err.to_string()),
+        });
+    });
+
+    let lines = futures::stream::unfold(receiver, |mut receiver| async move {
+        let event = receiver.recv().await?;
+        let mut line = serde_json::to_vec(&event).unwrap_or_default();
+        line.push(b'\n');
+        Some((Ok::<_, std::convert::Infallible>(line), receiver))
+    });
+    Ok((
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        Body::from_stream(lines),
+    )
+        .into_response())
+}
+
+#[derive(Debug, Serialize)]
+struct PurgeReport {
+    purged: usize,
+}
+
+async fn purge(
+    State(state): State<AdminState>,
+    Path(config_name): Path<String>,
+    Json(target): Json<PurgeTarget>,
+) -> Result<Json<PurgeReport>, AppError> {
+    let purged = state.clients.purge(&config_name, &target).await?;
+    tracing::info!(bucket = config_name, ?target, purged, "Cache purged");
+    Ok(Json(PurgeReport { purged }))
+}
+
+async fn purge_bucket(
+    State(state): State<AdminState>,
+    Path(config_name): Path<String>,
+) -> Result<Json<PurgeReport>, AppError> {
+    let purged = state.clients.purge(&config_name, &PurgeTarget::All).await?;
+    tracing::info!(bucket = config_name, purged, "Cache purged");
+    Ok(Json(PurgeReport { purged }))
+}
+
+#[derive(Debug, Deserialize)]
+struct SignRequest {
+    path: Option<String>,
+    prefix: Option<String>,
+    expires_in_secs: u64,
+    client_ip: Option<IpAddr>,
+}
+
+#[derive(Debug, Serialize)]
+struct SignedLink {
+    query: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+}
+
+/// Characters escaped when building the path of a signed link.
+const PATH_ESCAPES: &percent_encoding::AsciiSet = &percent_encoding::CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+async fn sign(
+    State(state): State<AdminState>,
+    Path(config_name): Path<String>,
+    Json(request): Json<SignRequest>,
+) -> Result<Json<SignedLink>, AppError> {
+    let scope = match (request.path, request.prefix) {
+        (Some(path), None) => SignatureScope::Path(path),
+        (None, Some(prefix)) => SignatureScope::Prefix(prefix),
+        _ => {
+            return Err(AppError::InvalidSigningRequest(
+                "exactly one of `path` or `prefix` is required".into(),
+            ));
+        }
+    };
+
+    let query = state.access.sign(
+        &config_name,
+        &scope,
+        request.expires_in_secs,
+        request.client_ip,
+    )?;
+    let url = match &scope {
+        SignatureScope::Path(path) => Some(format!(
+            "/{config_name}/{}?{query}",
+            percent_encoding::utf8_percent_encode(path, PATH_ESCAPES)
+        )),
+        SignatureScope::Prefix(_) => None,
+    };
+
+    Ok(Json(SignedLink { query, url }))
+}
+
+/// S3/MinIO bucket notification payload, reduced to the fields needed for
+/// cache invalidation.
+#[derive(Debug, Deserialize)]
+struct BucketNotification {
+    #[serde(rename = "Records", default)]
+    records: Vec<EventRecord>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EventRecord {
+    event_name: String,
+    s3: EventEntity,
+}
+
+#[derive(Debug, Deserialize)]
+struct EventEntity {
+    bucket: EventBucket,
+    object: EventObject,
+}
+
+#[derive(Debug, Deserialize)]
+struct EventBucket {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct EventObject {
+    key: String,
+}
+
+#[derive(Debug, Serialize)]
+struct EventReport {
+    invalidated: usize,
+}
+
+/// Returns the `(bucket, key)` pairs whose cached copies are stale after the
+/// notification. Object keys arrive URL-encoded, with `+` for spaces.
+fn stale_objects(notification: BucketNotification) -> Vec<(String, String)> {
+    notification
+        .records
+        .into_iter()
+        .filter(|record| {
+            let event = record
+                .event_name
+                .strip_prefix("s3:")
+                .unwrap_or(&record.event_name);
+            event.starts_with("ObjectCreated:") || event.starts_with("ObjectRemoved:")
+        })
+        .map(|record| {
+            let key = record.s3.object.key.replace('+', " ");
+            let key = percent_encoding::percent_decode_str(&key)
+                .decode_utf8_lossy()
+                .into_owned();
+            (record.s3.bucket.name, key)
+        })
+        .collect()
+}
+
+async fn bucket_events(
+    State(state): State<AdminState>,
+    Json(notification): Json<BucketNotification>,
+) -> Json<EventReport> {
+    let mut invalidated = 0;
+
+    for (bucket_name, key) in stale_objects(notification) {
+        let removed = state.clients.invalidate_object(&bucket_name, &key).await;
+        tracing::debug!(
+            bucket_name,
+            key,
+            removed,
+            "Cache invalidated by bucket event"
+        );
+        invalidated += removed;
+    }
+
+    Json(EventReport { invalidated })
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,425 @@
+use std::time::Duration;
+
+use super::*;
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use http_body_util::BodyExt;
+use tower::ServiceExt;
+
+use crate::config::AppConfig;
+use crate::mock_s3::{MockEndpoint, MockS3};
+use crate::s3::{FileServer, ReadConditions};
+
+const CONFIG: &str = r#"
+cache: {}
+admin:
+  token:
+    plain: "s3cret"
+buckets:
+  docs:
+    endpoint_url: "http://localhost:9000"
+    bucket_name: "docs"
+    access_key:
+        plain: "key"
+    secret_key:
+        plain: "secret"
+    proxy: true
+    cache: true
+    circuit_breaker: {}
+  private:
+    endpoint_url: "http://localhost:9000"
+    bucket_name: "private"
+    access_key:
+        plain: "key"
+    secret_key:
+        plain: "secret"
+    require_signature:
+      secret:
+        plain: "signing-secret"
+"#;
+
+fn test_router() -> Router {
+    let config: AppConfig = serde_yaml::from_str(CONFIG).unwrap();
+    admin_router(&config)
+}
+
+fn admin_router(config: &AppConfig) -> Router {
+    let clients = Arc::new(S3Clients::from_config(config).unwrap());
+    let access = Arc::new(AccessControl::from_config(config).unwrap());
+    router(clients, access, config.admin.as_ref().unwrap()).unwrap()
+}
+
+fn prefetch_request(config_name: &str, token: Option<&str>) -> Request<Body> {
+    let mut builder = Request::builder()
+        .method("POST")
+        .uri(format!("/buckets/{config_name}/prefetch"))
+        .header(header::CONTENT_TYPE, "application/json");
+    if let Some(token) = token {
+        builder = builder.header(header::AUTHORIZATION, format!("Bearer {token}"));
+    }
+    builder.body(Body::from(r#"{"keys": []}"#)).unwrap()
+}
+
+#[tokio::test]
+async fn missing_token_is_rejected() {
+    let resp = test_router()
+        .oneshot(prefetch_request("docs", None))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn wrong_token_is_rejected() {
+    let resp = test_router()
+        .oneshot(prefetch_request("docs", Some("nope")))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(resp.headers()[header::WWW_AUTHENTICATE], "Bearer");
+}
+
+#[tokio::test]
+async fn prefetch_unknown_config_returns_404() {
+    let resp = test_router()
+        .oneshot(prefetch_request("nope", Some("s3cret")))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn prefetch_without_cache_returns_400() {
+    let config: AppConfig = serde_yaml::from_str(&CONFIG.replace("cache: {}", "")).unwrap();
+    let app = admin_router(&config);
+
+    let resp = app
+        .oneshot(prefetch_request("docs", Some("s3cret")))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn prefetch_empty_key_list_reports_totals() {
+    let resp = test_router()
+        .oneshot(prefetch_request("docs", Some("s3cret")))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.headers()[header::CONTENT_TYPE], "application/x-ndjson");
+    let totals = r#"{"listed":0,"cached":0,"skipped":0,"failed":0,"bytes":0}"#;
+    assert_eq!(
+        body_text(resp).await,
+        format!("{{\"progress\":{totals}}}\n{{\"done\":{totals}}}\n")
+    );
+}
+
+#[test]
+fn constant_time_eq_compares_contents() {
+    assert!(constant_time_eq(b"abc", b"abc"));
+    assert!(!constant_time_eq(b"abc", b"abd"));
+    assert!(!constant_time_eq(b"abc", b"abcd"));
+}
+
+async fn body_text(resp: Response) -> String {
+    let body = resp.into_body().collect().await.unwrap().to_bytes();
+    String::from_utf8(body.to_vec()).unwrap()
+}
+
+/// An admin router over a cached `docs` bucket holding a prefetched
+/// `2026/a.txt`, with the clients to read it back through.
+async fn cached_object_router() -> (Router, Arc<S3Clients>, MockEndpoint) {
+    let endpoint = MockS3::new(&["2026/a.txt"]).serve().await;
+    let config: AppConfig =
+        serde_yaml::from_str(&CONFIG.replace("http://localhost:9000", &endpoint.url)).unwrap();
+    let clients = Arc::new(S3Clients::from_config(&config).unwrap());
+    let access = Arc::new(AccessControl::from_config(&config).unwrap());
+    let app = router(clients.clone(), access, config.admin.as_ref().unwrap()).unwrap();
+
+    let resp = app
+        .clone()
+        .oneshot(admin_request(
+            "POST",
+            "/buckets/docs/prefetch",
+            r#"{"keys": ["2026/a.txt"]}"#,
+        ))
+        .await
+        .unwrap();
+    let report = body_text(resp).await;
+    assert!(
+        report.ends_with("\"cached\":1,\"skipped\":0,\"failed\":0,\"bytes\":5}}\n"),
+        "{report}"
+    );
+    clients
+        .get_file("docs", "2026/a.txt", &ReadConditions::default())
+        .await
+        .unwrap();
+    assert_eq!(endpoint.downloads(), 1, "served from the cache");
+
+    (app, clients, endpoint)
+}
+
+#[tokio::test]
+async fn prefetch_streams_progress() {
+    let keys: Vec<String> = (0..150).map(|i| format!("p/{i}.txt")).collect();
+    let keys: Vec<&str> = keys.iter().map(String::as_str).collect();
+    let endpoint = MockS3::new(&keys).serve().await;
+    let config: AppConfig =
+        serde_yaml::from_str(&CONFIG.replace("http://localhost:9000", &endpoint.url)).unwrap();
+
+    let resp = admin_router(&config)
+        .oneshot(admin_request(
+            "POST",
+            "/buckets/docs/prefetch",
+            r#"{"prefix": "p/"}"#,
+        ))
+        .await
+        .unwrap();
+    let lines: Vec<serde_json::Value> = body_text(resp)
+        .await
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[0]["progress"]["listed"], 150);
+    assert_eq!(lines[0]["progress"]["cached"], 0);
+    assert_eq!(lines[1]["progress"]["cached"], 100);
+    assert_eq!(lines[2]["done"]["cached"], 150);
+}
+
+fn admin_request(method: &str, uri: &str, body: &'static str) -> Request<Body> {
+    Request::builder()
+        .method(method)
+        .uri(uri)
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(header::AUTHORIZATION, "Bearer s3cret")
+        .body(Body::from(body))
+        .unwrap()
+}
+
+#[tokio::test]
+async fn circuits_report_buckets_with_breakers() {
+    let resp = test_router()
+        .oneshot(admin_request("GET", "/circuits", ""))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = http_body_util::BodyExt::collect(resp.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    assert_eq!(&body[..], br#"{"docs":"closed"}"#);
+}
+
+#[tokio::test]
+async fn purge_by_prefix_evicts_matching_objects() {
+    let (app, clients, endpoint) = cached_object_router().await;
+
+    let resp = app
+        .oneshot(admin_request(
+            "POST",
+            "/buckets/docs/purge",
+            r#"{"prefix": "2026/"}"#,
+        ))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(body_text(resp).await, r#"{"purged":1}"#);
+
+    clients
+        .get_file("docs", "2026/a.txt", &ReadConditions::default())
+        .await
+        .unwrap();
+    assert_eq!(endpoint.downloads(), 2, "fetched again after purge");
+}
+
+#[tokio::test]
+async fn purge_during_a_slow_fetch_is_not_undone() {
+    let endpoint = MockS3::new(&["a.txt"])
+        .delay(Duration::from_millis(300))
+        .serve()
+        .await;
+    let config: AppConfig =
+        serde_yaml::from_str(&CONFIG.replace("http://localhost:9000", &endpoint.url)).unwrap();
+    let clients = Arc::new(S3Clients::from_config(&config).unwrap());
+    let access = Arc::new(AccessControl::from_config(&config).unwrap());
+    let app = router(clients.clone(), access, config.admin.as_ref().unwrap()).unwrap();
+
+    let fetch = tokio::spawn({
+        let clients = clients.clone();
+        async move {
+            clients
+                .get_file("docs", "a.txt", &ReadConditions::default())
+                .await
+                .map(drop)
+        }
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let resp = app
+        .oneshot(admin_request(
+            "POST",
+            "/buckets/docs/purge",
+            r#"{"key": "a.txt"}"#,
+        ))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    fetch.await.unwrap().unwrap();
+
+    clients
+        .get_file("docs", "a.txt", &ReadConditions::default())
+        .await
+        .unwrap();
+    assert_eq!(endpoint.downloads(), 2, "the purged fetch was not cached");
+}
+
+#[tokio::test]
+async fn purge_with_invalid_glob_returns_400() {
+    let resp = test_router()
+        .oneshot(admin_request(
+            "POST",
+            "/buckets/docs/purge",
+            r#"{"glob": "["}"#,
+        ))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn purge_whole_bucket_evicts_everything() {
+    let (app, clients, endpoint) = cached_object_router().await;
+
+    let resp = app
+        .oneshot(admin_request("DELETE", "/buckets/docs/cache", ""))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(body_text(resp).await, r#"{"purged":1}"#);
+
+    clients
+        .get_file("docs", "2026/a.txt", &ReadConditions::default())
+        .await
+        .unwrap();
+    assert_eq!(endpoint.downloads(), 2, "fetched again after purge");
+}
+
+#[tokio::test]
+async fn bucket_events_require_token() {
+    let request = Request::builder()
+        .method("POST")
+        .uri("/events")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(r#"{"Records": []}"#))
+        .unwrap();
+    let resp = test_router().oneshot(request).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn sign_path_returns_url() {
+    let resp = test_router()
+        .oneshot(admin_request(
+            "POST",
+            "/buckets/private/sign",
+            r#"{"path": "a b.jpg", "expires_in_secs": 60}"#,
+        ))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let body = http_body_util::BodyExt::collect(resp.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    let link: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert!(
+        link["url"]
+            .as_str()
+            .unwrap()
+            .starts_with("/private/a%20b.jpg?expires=")
+    );
+}
+
+#[tokio::test]
+async fn sign_requires_path_or_prefix() {
+    let resp = test_router()
+        .oneshot(admin_request(
+            "POST",
+            "/buckets/private/sign",
+            r#"{"expires_in_secs": 60}"#,
+        ))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn sign_out_of_range_expiry_returns_400() {
+    let resp = test_router()
+        .oneshot(admin_request(
+            "POST",
+            "/buckets/private/sign",
+            r#"{"path": "a.jpg", "expires_in_secs": 18446744073709551615}"#,
+        ))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn sign_unprotected_bucket_returns_400() {
+    let resp = test_router()
+        .oneshot(admin_request(
+            "POST",
+            "/buckets/docs/sign",
+            r#"{"path": "a.pdf", "expires_in_secs": 60}"#,
+        ))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+}
+
+#[test]
+fn stale_objects_from_minio_and_aws_events() {
+    let notification: BucketNotification = serde_json::from_str(
+        r#"{
+            "EventName": "s3:ObjectCreated:Put",
+            "Key": "docs/reports/2026 q1.pdf",
+            "Records": [
+                {
+                    "eventName": "s3:ObjectCreated:Put",
+                    "s3": {
+                        "bucket": {"name": "docs"},
+                        "object": {"key": "reports/2026+q1%2Bfinal.pdf", "size": 12}
+                    }
+                },
+                {
+                    "eventName": "ObjectRemoved:Delete",
+                    "s3": {
+                        "bucket": {"name": "docs"},
+                        "object": {"key": "old.pdf"}
+                    }
+                },
+                {
+                    "eventName": "s3:ObjectAccessed:Get",
+                    "s3": {
+                        "bucket": {"name": "docs"},
+                        "object": {"key": "read.pdf"}
+                    }
+                }
+            ]
+        }"#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        stale_objects(notification),
+        vec![
+            ("docs".to_string(), "reports/2026 q1+final.pdf".to_string()),
+            ("docs".to_string(), "old.pdf".to_string()),
+        ]
+    );
+}
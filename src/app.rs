@@ -1,15 +1,120 @@
+use std::sync::Arc;
+
 use axum::Router;
+use axum::extract::FromRef;
+use axum::middleware;
 use axum::routing::get;
-use tower_http::cors::CorsLayer;
 
+use crate::access::AccessControl;
+use crate::access_log::{AccessLog, log_requests};
+use crate::client_ip::{TrustedProxies, resolve_client_ip};
 use crate::config::AppConfig;
-use crate::s3::new_file_server;
+use crate::cors::{CorsPolicies, apply_cors};
+use crate::error::{ErrorPages, render_errors};
+use crate::prometheus::{RequestLabels, record_requests};
+use crate::rate_limit::RateLimits;
+use crate::request_id::assign_request_id;
+use crate::s3::{FileServer, S3Clients};
+use crate::shutdown::Shutdown;
+use crate::telemetry::trace_requests;
+
+#[derive(Clone)]
+pub struct AppState {
+    pub server: Arc<dyn FileServer>,
+    pub access: Arc<AccessControl>,
+    pub rate_limits: Arc<RateLimits>,
+}
+
+impl FromRef<AppState> for Arc<dyn FileServer> {
+    fn from_ref(state: &AppState) -> Self {
+        state.server.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<AccessControl> {
+    fn from_ref(state: &AppState) -> Self {
+        state.access.clone()
+    }
+}
+
+/// The parts of the app holding state between requests, which a reload takes
+/// over for the settings it leaves unchanged.
+pub struct Components {
+    pub clients: Arc<S3Clients>,
+    pub rate_limits: Arc<RateLimits>,
+}
 
-pub fn build_router(config: &AppConfig) -> Router {
-    let server = new_file_server(config);
+impl Components {
+    pub fn from_config(config: &AppConfig) -> eyre::Result<Self> {
+        Ok(Self {
+            clients: Arc::new(S3Clients::from_config(config)?),
+            rate_limits: Arc::new(RateLimits::from_config(config)?),
+        })
+    }
 
-    Router::new()
+    /// Builds the components of `config`, keeping the state of `self`, built
+    /// from `previous`, where settings did not change.
+    pub fn reconfigured(&self, previous: &AppConfig, config: &AppConfig) -> eyre::Result<Self> {
+        Ok(Self {
+            clients: Arc::new(self.clients.reconfigured(previous, config)?),
+            rate_limits: Arc::new(self.rate_limits.reconfigured(previous, config)?),
+        })
+    }
+}
+
+pub fn build_router(
+    config: &AppConfig,
+    components: &Components,
+    access_log: Option<Arc<AccessLog>>,
+    shutdown: Shutdown,
+) -> eyre::Result<Router> {
+    let clients = components.clients.clone();
+    let state = AppState {
+        server: clients.clone(),
+        access: Arc::new(AccessControl::from_config(config)?),
+        rate_limits: components.rate_limits.clone(),
+    };
+
+    let mut router = Router::new()
         .route("/{config_name}/{*file_path}", get(crate::routes::get_file))
-        .with_state(server)
-        .layer(CorsLayer::new().allow_origin(tower_http::cors::Any))
+        .route_layer(middleware::from_fn_with_state(
+            Arc::new(RequestLabels::from_config(config)),
+            record_requests,
+        ))
+        .with_state(state.clone());
+
+    router = router.merge(crate::health::router(clients.clone(), config, shutdown));
+
+    if let Some(admin) = &config.admin {
+        router = router.nest(
+            "/_admin",
+            crate::admin::router(clients, state.access, admin)?,
+        );
+    }
+
+    let trusted_proxies = Arc::new(TrustedProxies::new(config.trusted_proxies.clone()));
+
+    router = router
+        .layer(middleware::from_fn_with_state(
+            Arc::new(ErrorPages::from_config(config)?),
+            render_errors,
+        ))
+        .layer(middleware::from_fn_with_state(
+            Arc::new(CorsPolicies::from_config(config)?),
+            apply_cors,
+        ));
+    if let Some(access_log) = access_log {
+        router = router.layer(middleware::from_fn_with_state(access_log, log_requests));
+    }
+
+    Ok(router
+        .layer(middleware::from_fn_with_state(
+            trusted_proxies.clone(),
+            resolve_client_ip,
+        ))
+        .layer(middleware::from_fn_with_state(
+            trusted_proxies,
+            assign_request_id,
+        ))
+        .layer(middleware::from_fn(trace_requests)))
 }
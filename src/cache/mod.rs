@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+
+use axum::body::Bytes;
+use serde::Deserialize;
+
+use crate::config::CacheConfig;
+use crate::error::AppError;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    pub config_name: String,
+    pub key: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct CachedObject {
+    pub content_type: String,
+    pub body: Bytes,
+}
+
+/// Size-bounded cache of whole objects served in proxy mode.
+pub struct ObjectCache {
+    entries: moka::future::Cache<CacheKey, CachedObject>,
+    max_object_size: u64,
+    /// Fetches under way, with whether a purge has since covered their key.
+    fills: Mutex<HashMap<u64, (CacheKey, bool)>>,
+    next_fill: AtomicU64,
+}
+
+impl ObjectCache {
+    pub fn from_config(config: &CacheConfig) -> Self {
+        let mut builder = moka::future::Cache::builder()
+            .max_capacity(config.max_size_bytes)
+            .support_invalidation_closures()
+            .weigher(|_key: &CacheKey, value: &CachedObject| {
+                u32::try_from(value.body.len()).unwrap_or(u32::MAX)
+            });
+
+        if let Some(ttl) = config.ttl_secs {
+            builder = builder.time_to_live(Duration::from_secs(ttl));
+        }
+
+        Self {
+            entries: builder.build(),
+            max_object_size: config.max_object_size_bytes,
+            fills: Mutex::default(),
+            next_fill: AtomicU64::new(0),
+        }
+    }
+
+    /// Whether an object of `size` bytes is small enough to be cached.
+    pub fn accepts(&self, size: u64) -> bool {
+        size <= self.max_object_size
+    }
+
+    pub async fn get(&self, config_name: &str, key: &str) -> Option<CachedObject> {
+        self.entries.get(&cache_key(config_name, key)).await
+    }
+
+    pub async fn insert(&self, config_name: &str, key: &str, object: CachedObject) {
+        if !self.accepts(object.body.len() as u64) {
+            return;
+        }
+        self.entries
+            .insert(cache_key(config_name, key), object)
+            .await;
+    }
+
+    /// Starts fetching `key` to cache it. The object is only stored if no
+    /// purge covered the key while it was being fetched, so that an object
+    /// replaced in S3 mid-fetch does not come back right after its purge.
+    pub fn begin_fill(&self, config_name: &str, key: &str) -> Fill<'_> {
+        let id = self.next_fill.fetch_add(1, Ordering::Relaxed);
+        self.lock_fills()
+            .insert(id, (cache_key(config_name, key), false));
+        Fill { cache: self, id }
+    }
+
+    fn lock_fills(&self) -> std::sync::MutexGuard<'_, HashMap<u64, (CacheKey, bool)>> {
+        self.fills.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Evicts the entries of `config_name` selected by `target`, returning how
+    /// many were removed.
+    pub async fn purge(&self, config_name: &str, target: &PurgeTarget) -> Result<usize, AppError> {
+        let glob = match target {
+            PurgeTarget::Glob(pattern) => Some(
+                globset::Glob::new(pattern)
+                    .map_err(|e| AppError::InvalidGlob(e.to_string()))?
+                    .compile_matcher(),
+            ),
+            _ => None,
+        };
+        let selects = |entry: &CacheKey| {
+            entry.config_name == config_name
+                && match target {
+                    PurgeTarget::Key(key) => entry.key == *key,
+                    PurgeTarget::Prefix(prefix) => entry.key.starts_with(prefix.as_str()),
+                    PurgeTarget::Glob(_) => glob.as_ref().is_some_and(|g| g.is_match(&entry.key)),
+                    PurgeTarget::All => true,
+                }
+        };
+
+        // Marked before evicting, so that a fill storing its object in
+        // between still sees the mark and takes it out again.
+        for (entry, stale) in self.lock_fills().values_mut() {
+            *stale |= selects(entry);
+        }
+
+        if let PurgeTarget::Key(key) = target {
+            let removed = self.entries.remove(&cache_key(config_name, key)).await;
+            return Ok(usize::from(removed.is_some()));
+        }
+
+        let matching: Vec<_> = self
+            .entries
+            .iter()
+            .filter(|(entry, _)| selects(entry))
+            .map(|(entry, _)| entry)
+            .collect();
+
+        for entry in &matching {
+            self.entries.invalidate(entry.as_ref()).await;
+        }
+
+        Ok(matching.len())
+    }
+
+    /// Drops every entry of `config_name`, including objects still being
+    /// fetched, for a bucket whose settings changed or that was removed.
+    pub fn forget(&self, config_name: &str) {
+        for (entry, stale) in self.lock_fills().values_mut() {
+            *stale |= entry.config_name == config_name;
+        }
+        let config_name = config_name.to_string();
+        self.entries
+            .invalidate_entries_if(move |entry, _| entry.config_name == config_name)
+            .expect("invalidation closures are enabled");
+    }
+}
+
+/// A fetch meant for the cache, from [`ObjectCache::begin_fill`].
+pub struct Fill<'a> {
+    cache: &'a ObjectCache,
+    id: u64,
+}
+
+impl Fill<'_> {
+    /// Caches `object` unless a purge covered its key since the fill began,
+    /// returning whether it was kept.
+    pub async fn insert(self, object: CachedObject) -> bool {
+        let Some((key, false)) = self.cache.lock_fills().get(&self.id).cloned() else {
+            return false;
+        };
+        self.cache.insert(&key.config_name, &key.key, object).await;
+        // A purge marking the fill after the check above may have evicted
+        // before the object landed, so check again.
+        let purged = self
+            .cache
+            .lock_fills()
+            .get(&self.id)
+            .is_some_and(|(_, stale)| *stale);
+        if purged {
+            self.cache.entries.remove(&key).await;
+        }
+        !purged
+    }
+}
+
+impl Drop for Fill<'_> {
+    fn drop(&mut self) {
+        self.cache.lock_fills().remove(&self.id);
+    }
+}
+
+/// Selection of cache entries to evict within one bucket config.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PurgeTarget {
+    Key(String),
+    Prefix(String),
+    Glob(String),
+    All,
+}
+
+fn cache_key(config_name: &str, key: &str) -> CacheKey {
+    CacheKey {
+        config_name: config_name.to_string(),
+        key: key.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests;
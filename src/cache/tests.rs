@@ -0,0 +1,152 @@
+use super::*;
+
+fn test_cache(max_object_size_bytes: u64) -> ObjectCache {
+    ObjectCache::from_config(&CacheConfig {
+        max_size_bytes: 1024,
+        max_object_size_bytes,
+        ttl_secs: None,
+    })
+}
+
+fn object(body: &'static [u8]) -> CachedObject {
+    CachedObject {
+        content_type: "text/plain".into(),
+        body: Bytes::from_static(body),
+    }
+}
+
+#[tokio::test]
+async fn insert_then_get() {
+    let cache = test_cache(64);
+    cache.insert("docs", "a.txt", object(b"hello")).await;
+
+    let hit = cache.get("docs", "a.txt").await.unwrap();
+    assert_eq!(hit.content_type, "text/plain");
+    assert_eq!(&hit.body[..], b"hello");
+}
+
+#[tokio::test]
+async fn entries_are_scoped_by_config_name() {
+    let cache = test_cache(64);
+    cache.insert("docs", "a.txt", object(b"hello")).await;
+
+    assert!(cache.get("photos", "a.txt").await.is_none());
+}
+
+#[tokio::test]
+async fn oversized_objects_are_not_cached() {
+    let cache = test_cache(4);
+    assert!(!cache.accepts(5));
+
+    cache.insert("docs", "big.txt", object(b"hello")).await;
+    assert!(cache.get("docs", "big.txt").await.is_none());
+}
+
+async fn filled_cache() -> ObjectCache {
+    let cache = test_cache(64);
+    for key in ["2026/a.jpg", "2026/b.png", "2025/c.jpg"] {
+        cache.insert("photos", key, object(b"x")).await;
+    }
+    cache.insert("docs", "2026/a.jpg", object(b"x")).await;
+    cache
+}
+
+#[tokio::test]
+async fn purge_exact_key() {
+    let cache = filled_cache().await;
+    let purged = cache
+        .purge("photos", &PurgeTarget::Key("2026/a.jpg".into()))
+        .await
+        .unwrap();
+
+    assert_eq!(purged, 1);
+    assert!(cache.get("photos", "2026/a.jpg").await.is_none());
+    assert!(cache.get("docs", "2026/a.jpg").await.is_some());
+}
+
+#[tokio::test]
+async fn purge_prefix() {
+    let cache = filled_cache().await;
+    let purged = cache
+        .purge("photos", &PurgeTarget::Prefix("2026/".into()))
+        .await
+        .unwrap();
+
+    assert_eq!(purged, 2);
+    assert!(cache.get("photos", "2025/c.jpg").await.is_some());
+}
+
+#[tokio::test]
+async fn purge_glob() {
+    let cache = filled_cache().await;
+    let purged = cache
+        .purge("photos", &PurgeTarget::Glob("**/*.jpg".into()))
+        .await
+        .unwrap();
+
+    assert_eq!(purged, 2);
+    assert!(cache.get("photos", "2026/b.png").await.is_some());
+}
+
+#[tokio::test]
+async fn purge_invalid_glob_errors() {
+    let cache = filled_cache().await;
+    let result = cache.purge("photos", &PurgeTarget::Glob("[".into())).await;
+    assert!(matches!(result, Err(AppError::InvalidGlob(_))));
+}
+
+#[tokio::test]
+async fn purge_whole_bucket() {
+    let cache = filled_cache().await;
+    let purged = cache.purge("photos", &PurgeTarget::All).await.unwrap();
+
+    assert_eq!(purged, 3);
+    assert!(cache.get("docs", "2026/a.jpg").await.is_some());
+}
+
+#[tokio::test]
+async fn purge_during_a_fill_keeps_the_object_out() {
+    let cache = test_cache(64);
+    let by_key = cache.begin_fill("docs", "a.txt");
+    let by_prefix = cache.begin_fill("docs", "2026/b.txt");
+    let elsewhere = cache.begin_fill("photos", "a.txt");
+
+    cache
+        .purge("docs", &PurgeTarget::Key("a.txt".into()))
+        .await
+        .unwrap();
+    cache
+        .purge("docs", &PurgeTarget::Prefix("2026/".into()))
+        .await
+        .unwrap();
+
+    assert!(!by_key.insert(object(b"old")).await);
+    assert!(!by_prefix.insert(object(b"old")).await);
+    assert!(elsewhere.insert(object(b"new")).await);
+    assert!(cache.get("docs", "a.txt").await.is_none());
+    assert!(cache.get("docs", "2026/b.txt").await.is_none());
+    assert!(cache.get("photos", "a.txt").await.is_some());
+}
+
+#[tokio::test]
+async fn fill_after_a_purge_is_cached() {
+    let cache = test_cache(64);
+    cache.purge("docs", &PurgeTarget::All).await.unwrap();
+
+    let fill = cache.begin_fill("docs", "a.txt");
+    assert!(fill.insert(object(b"new")).await);
+    assert!(cache.get("docs", "a.txt").await.is_some());
+}
+
+#[tokio::test]
+async fn forget_drops_one_bucket() {
+    let cache = filled_cache().await;
+    let fill = cache.begin_fill("photos", "2026/d.jpg");
+
+    cache.forget("photos");
+
+    assert!(!fill.insert(object(b"x")).await);
+    assert!(cache.get("photos", "2026/a.jpg").await.is_none());
+    assert!(cache.get("photos", "2026/d.jpg").await.is_none());
+    assert!(cache.get("docs", "2026/a.jpg").await.is_some());
+}
@@ -0,0 +1,275 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use eyre::bail;
+use serde::Serialize;
+
+use crate::config::CircuitBreakerConfig;
+use crate::error::AppError;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+impl CircuitState {
+    fn gauge_value(self) -> f64 {
+        match self {
+            Self::Closed => 0.0,
+            Self::HalfOpen => 1.0,
+            Self::Open => 2.0,
+        }
+    }
+}
+
+struct Inner {
+    state: CircuitState,
+    /// When an open circuit goes half-open.
+    open_until: Instant,
+    consecutive_failures: u32,
+    window_start: Instant,
+    window_calls: u32,
+    window_failures: u32,
+    probes_in_flight: u32,
+    /// Counts half-open rounds, so that probes outliving their round are
+    /// ignored.
+    half_open_round: u64,
+}
+
+/// Per-bucket circuit breaker around S3 calls.
+pub struct CircuitBreaker {
+    bucket: String,
+    consecutive_failures: u32,
+    failure_rate: Option<f64>,
+    min_calls: u32,
+    window: Duration,
+    open_for: Duration,
+    half_open_probes: u32,
+    inner: Mutex<Inner>,
+}
+
+impl CircuitBreaker {
+    /// Checks the settings without building a breaker, which would report
+    /// its state.
+    pub fn check_config(config: &CircuitBreakerConfig) -> eyre::Result<()> {
+        if config.consecutive_failures == 0 {
+            bail!("`consecutive_failures` must be positive");
+        }
+        if let Some(rate) = config.failure_rate
+            && !(0.0..=1.0).contains(&rate)
+        {
+            bail!("`failure_rate` must be between 0 and 1");
+        }
+        if config.half_open_probes == 0 {
+            bail!("`half_open_probes` must be positive");
+        }
+        Ok(())
+    }
+
+    pub fn from_config(bucket: &str, config: &CircuitBreakerConfig) -> eyre::Result<Self> {
+        Self::check_config(config)?;
+
+        let now = Instant::now();
+        let breaker = Self {
+            bucket: bucket.to_string(),
+            consecutive_failures: config.consecutive_failures,
+            failure_rate: config.failure_rate,
+            min_calls: config.min_calls.max(1),
+            window: Duration::from_secs(config.window_secs.max(1)),
+            open_for: Duration::from_secs(config.open_secs),
+            half_open_probes: config.half_open_probes,
+            inner: Mutex::new(Inner {
+                state: CircuitState::Closed,
+                open_until: now,
+                consecutive_failures: 0,
+                window_start: now,
+                window_calls: 0,
+                window_failures: 0,
+                probes_in_flight: 0,
+                half_open_round: 0,
+            }),
+        };
+        breaker.report(CircuitState::Closed);
+        Ok(breaker)
+    }
+
+    pub fn state(&self) -> CircuitState {
+        let inner = self.lock();
+        match inner.state {
+            CircuitState::Open if Instant::now() >= inner.open_until => CircuitState::HalfOpen,
+            state => state,
+        }
+    }
+
+    /// Admits a call, or fails fast while the circuit is open. The outcome
+    /// goes back through [`Call::finish`].
+    pub fn start(&self) -> Result<Call<'_>, AppError> {
+        self.start_at(Instant::now())
+    }
+
+    fn start_at(&self, now: Instant) -> Result<Call<'_>, AppError> {
+        let mut inner = self.lock();
+
+        if inner.state == CircuitState::Open {
+            if now < inner.open_until {
+                let wait = inner.open_until - now;
+                return Err(self.reject(wait.as_secs_f64().ceil() as u64));
+            }
+            inner.state = CircuitState::HalfOpen;
+            inner.probes_in_flight = 0;
+            inner.half_open_round += 1;
+            self.report(CircuitState::HalfOpen);
+        }
+
+        let mut probe = None;
+        if inner.state == CircuitState::HalfOpen {
+            if inner.probes_in_flight >= self.half_open_probes {
+                return Err(self.reject(1));
+            }
+            inner.probes_in_flight += 1;
+            probe = Some(inner.half_open_round);
+        }
+
+        Ok(Call {
+            breaker: self,
+            probe,
+            finished: false,
+        })
+    }
+
+    fn finish_at(&self, probe: Option<u64>, failed: bool, now: Instant) {
+        let mut inner = self.lock();
+
+        if let Some(round) = probe {
+            // Probes of an earlier round hold no slot in the current one.
+            if round != inner.half_open_round {
+                return;
+            }
+            inner.probes_in_flight -= 1;
+            if inner.state != CircuitState::HalfOpen {
+                return;
+            }
+            if failed {
+                self.open(&mut inner, now);
+            } else {
+                inner.state = CircuitState::Closed;
+                inner.consecutive_failures = 0;
+                inner.window_start = now;
+                inner.window_calls = 0;
+                inner.window_failures = 0;
+                tracing::info!(bucket = self.bucket, "Circuit closed");
+                self.report(CircuitState::Closed);
+            }
+            return;
+        }
+
+        // Calls admitted before the circuit opened don't count any more.
+        if inner.state != CircuitState::Closed {
+            return;
+        }
+
+        if now.duration_since(inner.window_start) >= self.window {
+            inner.window_start = now;
+            inner.window_calls = 0;
+            inner.window_failures = 0;
+        }
+        inner.window_calls += 1;
+        if failed {
+            inner.window_failures += 1;
+            inner.consecutive_failures += 1;
+        } else {
+            inner.consecutive_failures = 0;
+        }
+
+        let rate_exceeded = self.failure_rate.is_some_and(|rate| {
+            inner.window_calls >= self.min_calls
+                && f64::from(inner.window_failures) / f64::from(inner.window_calls) >= rate
+        });
+        if inner.consecutive_failures >= self.consecutive_failures || rate_exceeded {
+            self.open(&mut inner, now);
+        }
+    }
+
+    fn open(&self, inner: &mut Inner, now: Instant) {
+        inner.state = CircuitState::Open;
+        inner.open_until = now + self.open_for;
+        tracing::warn!(
+            bucket = self.bucket,
+            open_secs = self.open_for.as_secs(),
+            "Circuit opened"
+        );
+        self.report(CircuitState::Open);
+    }
+
+    fn reject(&self, retry_after_secs: u64) -> AppError {
+        metrics::counter!("media_server_circuit_rejected_total", "bucket" => self.bucket.clone())
+            .increment(1);
+        AppError::CircuitOpen {
+            bucket: self.bucket.clone(),
+            retry_after_secs: retry_after_secs.max(1),
+        }
+    }
+
+    fn report(&self, state: CircuitState) {
+        metrics::gauge!("media_server_circuit_state", "bucket" => self.bucket.clone())
+            .set(state.gauge_value());
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// A call admitted by a [`CircuitBreaker`]. Dropping it unfinished, as when
+/// the client goes away mid-call, records nothing.
+pub struct Call<'a> {
+    breaker: &'a CircuitBreaker,
+    /// Half-open round of a probe.
+    probe: Option<u64>,
+    finished: bool,
+}
+
+impl Call<'_> {
+    pub fn finish<T>(self, result: &Result<T, AppError>) {
+        let failed = result.as_ref().is_err_and(is_upstream_failure);
+        self.record(failed, Instant::now());
+    }
+
+    fn record(mut self, failed: bool, now: Instant) {
+        self.finished = true;
+        self.breaker.finish_at(self.probe, failed, now);
+    }
+}
+
+impl Drop for Call<'_> {
+    fn drop(&mut self) {
+        if let Some(round) = self.probe
+            && !self.finished
+        {
+            let mut inner = self.breaker.lock();
+            if round == inner.half_open_round {
+                inner.probes_in_flight -= 1;
+            }
+        }
+    }
+}
+
+/// Whether an error says the backend is unhealthy, as opposed to a problem
+/// with the request such as a missing key.
+fn is_upstream_failure(err: &AppError) -> bool {
+    matches!(
+        err,
+        AppError::S3Error(_)
+            | AppError::UpstreamTimeout(_)
+            | AppError::BadGateway(_)
+            | AppError::UpstreamUnavailable(_)
+    )
+}
+
+#[cfg(test)]
+mod tests;
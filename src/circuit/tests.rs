@@ -0,0 +1,135 @@
+use super::*;
+
+fn breaker(yaml: &str) -> CircuitBreaker {
+    CircuitBreaker::from_config("photos", &serde_yaml::from_str(yaml).unwrap()).unwrap()
+}
+
+fn fail(breaker: &CircuitBreaker, now: Instant) {
+    breaker.start_at(now).unwrap().record(true, now);
+}
+
+fn succeed(breaker: &CircuitBreaker, now: Instant) {
+    breaker.start_at(now).unwrap().record(false, now);
+}
+
+#[test]
+fn opens_after_consecutive_failures() {
+    let breaker = breaker("consecutive_failures: 3");
+    let now = Instant::now();
+
+    fail(&breaker, now);
+    fail(&breaker, now);
+    succeed(&breaker, now);
+    fail(&breaker, now);
+    fail(&breaker, now);
+    assert_eq!(breaker.state(), CircuitState::Closed);
+
+    fail(&breaker, now);
+    assert_eq!(breaker.state(), CircuitState::Open);
+    assert!(matches!(
+        breaker.start_at(now),
+        Err(AppError::CircuitOpen {
+            retry_after_secs: 30,
+            ..
+        })
+    ));
+}
+
+#[test]
+fn opens_on_failure_rate() {
+    let breaker = breaker("consecutive_failures: 100\nfailure_rate: 0.5\nmin_calls: 4");
+    let now = Instant::now();
+
+    fail(&breaker, now);
+    succeed(&breaker, now);
+    fail(&breaker, now);
+    assert_eq!(breaker.inner.lock().unwrap().state, CircuitState::Closed);
+
+    succeed(&breaker, now);
+    assert_eq!(breaker.inner.lock().unwrap().state, CircuitState::Open);
+}
+
+#[test]
+fn failure_rate_window_rolls_over() {
+    let breaker =
+        breaker("consecutive_failures: 100\nfailure_rate: 0.5\nmin_calls: 2\nwindow_secs: 10");
+    let now = Instant::now();
+
+    fail(&breaker, now);
+    succeed(&breaker, now + Duration::from_secs(11));
+    succeed(&breaker, now + Duration::from_secs(12));
+    assert_eq!(breaker.inner.lock().unwrap().state, CircuitState::Closed);
+}
+
+#[test]
+fn half_open_probe_closes_or_reopens() {
+    let breaker = breaker("consecutive_failures: 1\nopen_secs: 10");
+    let now = Instant::now();
+    fail(&breaker, now);
+
+    let later = now + Duration::from_secs(10);
+    let probe = breaker.start_at(later).unwrap();
+    // Only one probe at a time.
+    assert!(breaker.start_at(later).is_err());
+    probe.record(true, later);
+    assert_eq!(breaker.inner.lock().unwrap().state, CircuitState::Open);
+
+    let later = later + Duration::from_secs(10);
+    breaker.start_at(later).unwrap().record(false, later);
+    assert_eq!(breaker.state(), CircuitState::Closed);
+    assert!(breaker.start_at(later).is_ok());
+}
+
+#[test]
+fn abandoned_probe_frees_its_slot() {
+    let breaker = breaker("consecutive_failures: 1\nopen_secs: 0");
+    let now = Instant::now();
+    fail(&breaker, now);
+
+    drop(breaker.start_at(now).unwrap());
+    assert!(breaker.start_at(now).is_ok());
+}
+
+#[test]
+fn probes_outliving_their_round_are_ignored() {
+    let breaker = breaker("consecutive_failures: 1\nopen_secs: 10\nhalf_open_probes: 3");
+    let now = Instant::now();
+    fail(&breaker, now);
+
+    let first_round = now + Duration::from_secs(10);
+    let slow = breaker.start_at(first_round).unwrap();
+    let abandoned = breaker.start_at(first_round).unwrap();
+    breaker
+        .start_at(first_round)
+        .unwrap()
+        .record(true, first_round);
+
+    let second_round = first_round + Duration::from_secs(10);
+    let probe = breaker.start_at(second_round).unwrap();
+    slow.record(false, second_round);
+    drop(abandoned);
+    let inner = breaker.inner.lock().unwrap();
+    assert_eq!(inner.state, CircuitState::HalfOpen);
+    assert_eq!(inner.probes_in_flight, 1);
+    drop(inner);
+
+    probe.record(false, second_round);
+    assert_eq!(breaker.state(), CircuitState::Closed);
+}
+
+#[test]
+fn only_upstream_errors_count() {
+    assert!(is_upstream_failure(&AppError::S3Error("boom".into())));
+    assert!(is_upstream_failure(&AppError::UpstreamTimeout(
+        "slow".into()
+    )));
+    assert!(!is_upstream_failure(&AppError::ObjectNotFound(
+        "key".into()
+    )));
+}
+
+#[test]
+fn invalid_failure_rate_is_a_config_error() {
+    let config = serde_yaml::from_str("failure_rate: 1.5").unwrap();
+    assert!(CircuitBreaker::from_config("photos", &config).is_err());
+}
@@ -0,0 +1,177 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use clap::{Parser, Subcommand};
+use eyre::eyre;
+
+use crate::app::{Components, build_router};
+use crate::config::AppConfig;
+use crate::error::AppError;
+use crate::s3::{ListEntry, ReadTest, S3Clients};
+use crate::shutdown::Shutdown;
+
+/// Serves files from S3-compatible buckets.
+#[derive(Debug, Parser)]
+#[command(version)]
+pub struct Cli {
+    /// Config file; defaults to $MEDIA_SERVER_CONFIG_PATH, then
+    /// /etc/media-server/config.yml.
+    #[arg(long, short, global = true)]
+    pub config: Option<PathBuf>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+impl Cli {
+    pub fn config_path(&self) -> PathBuf {
+        self.config.clone().unwrap_or_else(AppConfig::path)
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the server (the default).
+    Serve {
+        /// Address to listen on, instead of `listen` from the config.
+        #[arg(long)]
+        listen: Option<String>,
+    },
+    /// Validate the config and build every bucket client, without contacting S3.
+    CheckConfig,
+    /// Print a presigned download URL.
+    Presign {
+        /// Config name of the bucket.
+        bucket: String,
+        key: String,
+        /// Seconds the URL stays valid; the bucket's presign expiry by default.
+        #[arg(long, value_name = "SECS")]
+        expires: Option<u64>,
+    },
+    /// List objects and prefixes.
+    Ls {
+        /// Config name of the bucket.
+        bucket: String,
+        #[arg(default_value = "")]
+        prefix: String,
+        /// List every object below the prefix instead of folding them by `/`.
+        #[arg(long, short)]
+        recursive: bool,
+    },
+    /// Show the metadata of an object.
+    Stat {
+        /// Config name of the bucket.
+        bucket: String,
+        key: String,
+    },
+    /// Check that every bucket can be reached, listed and read from.
+    TestBuckets,
+}
+
+/// Runs any command but `serve`.
+pub async fn run(command: Command, config_path: &Path) -> eyre::Result<()> {
+    let config = AppConfig::load_from(config_path)?;
+    let clients = S3Clients::from_config(&config)?;
+
+    match command {
+        Command::Serve { .. } => unreachable!("serve is run by main"),
+        Command::CheckConfig => {
+            let components = Components::from_config(&config)?;
+            let _ = build_router(&config, &components, None, Shutdown::default())?;
+            println!(
+                "{}: OK, {} bucket(s)",
+                config_path.display(),
+                config.buckets.len()
+            );
+        }
+        Command::Presign {
+            bucket,
+            key,
+            expires,
+        } => {
+            let expires = expires.map(Duration::from_secs);
+            let url = clients
+                .presign(&bucket, &key, expires)
+                .await
+                .map_err(report)?;
+            println!("{url}");
+        }
+        Command::Ls {
+            bucket,
+            prefix,
+            recursive,
+        } => {
+            for entry in clients
+                .list(&bucket, &prefix, recursive)
+                .await
+                .map_err(report)?
+            {
+                match entry {
+                    ListEntry::Prefix(prefix) => println!("{:>33} {prefix}", "PRE"),
+                    ListEntry::Object {
+                        key,
+                        size,
+                        last_modified,
+                    } => println!(
+                        "{:>20} {size:>12} {key}",
+                        last_modified.as_deref().unwrap_or("-")
+                    ),
+                }
+            }
+        }
+        Command::Stat { bucket, key } => {
+            let info = clients.stat(&bucket, &key).await.map_err(report)?;
+            let field = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+            println!("key:           {}", info.key);
+            println!(
+                "size:          {}",
+                field(info.size.map(|size| size.to_string()))
+            );
+            println!("content-type:  {}", field(info.content_type));
+            println!("etag:          {}", field(info.etag));
+            println!("last-modified: {}", field(info.last_modified));
+            for (name, value) in info.metadata {
+                println!("x-amz-meta-{name}: {value}");
+            }
+        }
+        Command::TestBuckets => {
+            let tests = clients.test_buckets().await;
+            let mut failed = 0;
+            for (name, test) in &tests {
+                let read = match &test.read {
+                    ReadTest::Read(result) => Ok(result),
+                    ReadTest::Inconclusive(reason) => Err(*reason),
+                };
+                let checks = [
+                    ("reach", Ok(&test.reach)),
+                    ("list", Ok(&test.list)),
+                    ("read", read),
+                ];
+                if checks
+                    .iter()
+                    .any(|(_, result)| result.is_ok_and(Result::is_err))
+                {
+                    failed += 1;
+                }
+                println!("{name} ({}):", test.bucket_name);
+                for (check, result) in checks {
+                    match result {
+                        Ok(Ok(())) => println!("  {check:<5} ok"),
+                        Ok(Err(err)) => println!("  {check:<5} FAILED: {err}"),
+                        Err(reason) => println!("  {check:<5} unknown ({reason})"),
+                    }
+                }
+            }
+            if failed > 0 {
+                return Err(eyre!("{failed} of {} bucket(s) failed", tests.len()));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn report(err: AppError) -> eyre::Report {
+    eyre!("{err}")
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,92 @@
+use clap::CommandFactory;
+
+use super::*;
+
+fn parse(args: &[&str]) -> Cli {
+    Cli::try_parse_from(std::iter::once("media-server").chain(args.iter().copied())).unwrap()
+}
+
+#[test]
+fn cli_definition_is_consistent() {
+    Cli::command().debug_assert();
+}
+
+#[test]
+fn no_subcommand_means_serve() {
+    let cli = parse(&[]);
+    assert!(cli.command.is_none());
+    assert!(cli.config.is_none());
+}
+
+#[test]
+fn serve_takes_config_and_listen_overrides() {
+    let cli = parse(&[
+        "serve",
+        "--config",
+        "/tmp/media.yml",
+        "--listen",
+        "127.0.0.1:9000",
+    ]);
+    assert_eq!(cli.config_path(), PathBuf::from("/tmp/media.yml"));
+    assert!(matches!(
+        cli.command,
+        Some(Command::Serve { listen: Some(listen) }) if listen == "127.0.0.1:9000"
+    ));
+}
+
+#[test]
+fn presign_parses_expiry() {
+    let cli = parse(&["presign", "photos", "cats/1.jpg", "--expires", "60"]);
+    assert!(matches!(
+        cli.command,
+        Some(Command::Presign { bucket, key, expires: Some(60) })
+            if bucket == "photos" && key == "cats/1.jpg"
+    ));
+}
+
+#[test]
+fn ls_prefix_is_optional() {
+    let cli = parse(&["ls", "photos"]);
+    assert!(matches!(
+        cli.command,
+        Some(Command::Ls { prefix, recursive: false, .. }) if prefix.is_empty()
+    ));
+
+    let cli = parse(&["ls", "-r", "photos", "cats/"]);
+    assert!(matches!(
+        cli.command,
+        Some(Command::Ls { prefix, recursive: true, .. }) if prefix == "cats/"
+    ));
+}
+
+#[test]
+fn stat_requires_a_key() {
+    assert!(Cli::try_parse_from(["media-server", "stat", "photos"]).is_err());
+}
+
+#[tokio::test]
+async fn check_config_validates_without_contacting_s3() {
+    let path = std::env::temp_dir().join(format!("media-server-{}.yml", uuid::Uuid::new_v4()));
+    std::fs::write(
+        &path,
+        r#"
+buckets:
+  photos:
+    endpoint_url: "http://127.0.0.1:1"
+    bucket_name: "photos"
+    access_key:
+        plain: "key"
+    secret_key:
+        plain: "secret"
+"#,
+    )
+    .unwrap();
+    let ok = run(Command::CheckConfig, &path).await;
+
+    std::fs::write(&path, "buckets:\n  photos:\n    bucket_name: \"photos\"\n").unwrap();
+    let invalid = run(Command::CheckConfig, &path).await;
+    std::fs::remove_file(&path).unwrap();
+
+    ok.unwrap();
+    assert!(invalid.is_err());
+}
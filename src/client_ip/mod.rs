@@ -0,0 +1,120 @@
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+use axum::extract::{ConnectInfo, Request, State};
+use axum::http::HeaderMap;
+use axum::middleware::Next;
+use axum::response::Response;
+use ipnet::IpNet;
+
+/// Address of the client that originated the request, once forwarding headers
+/// set by trusted proxies have been accounted for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClientIp(pub IpAddr);
+
+#[derive(Default)]
+pub struct TrustedProxies {
+    networks: Vec<IpNet>,
+}
+
+impl TrustedProxies {
+    pub fn new(networks: Vec<IpNet>) -> Self {
+        Self { networks }
+    }
+
+    fn contains(&self, ip: IpAddr) -> bool {
+        self.networks.iter().any(|net| net.contains(&ip))
+    }
+
+    /// Whether headers set by the connected `peer` are believed.
+    pub fn trusts(&self, peer: IpAddr) -> bool {
+        self.contains(peer.to_canonical())
+    }
+
+    /// Walks the forwarding chain from the connected peer towards the client,
+    /// stopping at the first hop that is not a trusted proxy.
+    pub fn resolve(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        let mut client = peer.to_canonical();
+        if !self.contains(client) {
+            return client;
+        }
+
+        for hop in forwarded_chain(headers).into_iter().rev() {
+            let Some(hop) = hop else {
+                break;
+            };
+            client = hop.to_canonical();
+            if !self.contains(client) {
+                break;
+            }
+        }
+
+        client
+    }
+}
+
+/// Hops listed by `Forwarded`, or by `X-Forwarded-For` when the former is
+/// absent, from the client to the last proxy. Unparseable hops (`unknown`,
+/// obfuscated identifiers) are kept as `None` so the walk stops there.
+fn forwarded_chain(headers: &HeaderMap) -> Vec<Option<IpAddr>> {
+    let forwarded: Vec<_> = headers
+        .get_all("forwarded")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|element| {
+            element
+                .split(';')
+                .filter_map(|pair| pair.trim().split_once('='))
+                .find(|(name, _)| name.eq_ignore_ascii_case("for"))
+                .and_then(|(_, node)| parse_node(node.trim_matches('"')))
+        })
+        .collect();
+    if !forwarded.is_empty() {
+        return forwarded;
+    }
+
+    headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|node| parse_node(node.trim()))
+        .collect()
+}
+
+/// Parses `ip`, `ipv4:port` or `[ipv6]:port`.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    if let Ok(ip) = node.parse() {
+        return Some(ip);
+    }
+    if let Some(rest) = node.strip_prefix('[') {
+        return rest.split_once(']')?.0.parse().ok();
+    }
+    node.parse::<SocketAddr>().ok().map(|addr| addr.ip())
+}
+
+/// Resolves the client address of every request and records it both as a
+/// [`ClientIp`] extension and on the request's tracing span.
+pub async fn resolve_client_ip(
+    State(trusted): State<Arc<TrustedProxies>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+    let Some(peer) = peer else {
+        return next.run(request).await;
+    };
+
+    let client_ip = trusted.resolve(peer, request.headers());
+    request.extensions_mut().insert(ClientIp(client_ip));
+
+    tracing::Span::current().record("client_ip", tracing::field::display(client_ip));
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests;
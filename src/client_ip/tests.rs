@@ -0,0 +1,79 @@
+use super::*;
+
+fn trusted() -> TrustedProxies {
+    TrustedProxies::new(vec![
+        "10.0.0.0/8".parse().unwrap(),
+        "2001:db8:cafe::/48".parse().unwrap(),
+    ])
+}
+
+fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    for (name, value) in pairs {
+        headers.append(*name, value.parse().unwrap());
+    }
+    headers
+}
+
+fn ip(s: &str) -> IpAddr {
+    s.parse().unwrap()
+}
+
+#[test]
+fn untrusted_peer_headers_are_ignored() {
+    let headers = headers(&[("x-forwarded-for", "198.51.100.1")]);
+    assert_eq!(
+        trusted().resolve(ip("203.0.113.9"), &headers),
+        ip("203.0.113.9")
+    );
+}
+
+#[test]
+fn trusted_peer_uses_x_forwarded_for() {
+    let headers = headers(&[("x-forwarded-for", "198.51.100.1, 10.1.1.1")]);
+    assert_eq!(
+        trusted().resolve(ip("10.0.0.2"), &headers),
+        ip("198.51.100.1")
+    );
+}
+
+#[test]
+fn spoofed_leftmost_hops_are_not_believed() {
+    // The client prepended a fake hop; the first untrusted address from the
+    // right is the real client.
+    let headers = headers(&[("x-forwarded-for", "1.2.3.4, 198.51.100.1")]);
+    assert_eq!(
+        trusted().resolve(ip("10.0.0.2"), &headers),
+        ip("198.51.100.1")
+    );
+}
+
+#[test]
+fn trusted_peer_uses_forwarded_header() {
+    let headers = headers(&[
+        (
+            "forwarded",
+            r#"for=198.51.100.1:4711;proto=https, for="[2001:db8:cafe::17]:443""#,
+        ),
+        ("x-forwarded-for", "1.2.3.4"),
+    ]);
+    assert_eq!(
+        trusted().resolve(ip("10.0.0.2"), &headers),
+        ip("198.51.100.1")
+    );
+}
+
+#[test]
+fn unknown_hop_stops_the_walk() {
+    let headers = headers(&[("forwarded", "for=198.51.100.1, for=unknown")]);
+    assert_eq!(trusted().resolve(ip("10.0.0.2"), &headers), ip("10.0.0.2"));
+}
+
+#[test]
+fn ipv4_mapped_peer_is_canonicalized() {
+    let headers = headers(&[]);
+    assert_eq!(
+        trusted().resolve(ip("::ffff:203.0.113.9"), &headers),
+        ip("203.0.113.9")
+    );
+}
@@ -0,0 +1,90 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use eyre::bail;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::config::ConcurrencyConfig;
+use crate::error::AppError;
+
+/// Semaphore with a bounded wait queue, shedding load with
+/// [`AppError::Overloaded`] once both are full.
+pub struct ConcurrencyLimit {
+    scope: String,
+    permits: Arc<Semaphore>,
+    queued: AtomicUsize,
+    max_queued: usize,
+    queue_timeout: Duration,
+    retry_after_secs: u64,
+}
+
+impl ConcurrencyLimit {
+    pub fn from_config(scope: &str, config: &ConcurrencyConfig) -> eyre::Result<Self> {
+        if config.max_in_flight == 0 {
+            bail!("`max_in_flight` must be positive");
+        }
+
+        Ok(Self {
+            scope: scope.to_string(),
+            permits: Arc::new(Semaphore::new(config.max_in_flight)),
+            queued: AtomicUsize::new(0),
+            max_queued: config.max_queued,
+            queue_timeout: Duration::from_millis(config.queue_timeout_ms),
+            retry_after_secs: config.retry_after_secs,
+        })
+    }
+
+    /// Takes a slot, waiting in the queue if there is room in it. The slot is
+    /// released when the permit is dropped.
+    pub async fn acquire(&self) -> Result<OwnedSemaphorePermit, AppError> {
+        if let Ok(permit) = self.permits.clone().try_acquire_owned() {
+            return Ok(permit);
+        }
+
+        let queued = self.queued.fetch_add(1, Ordering::AcqRel);
+        let _queued = QueueSlot(&self.queued);
+        if queued >= self.max_queued {
+            return Err(self.shed("queue full"));
+        }
+
+        match tokio::time::timeout(self.queue_timeout, self.permits.clone().acquire_owned()).await {
+            Ok(Ok(permit)) => Ok(permit),
+            Ok(Err(_)) => Err(self.shed("closed")),
+            Err(_) => Err(self.shed("queue timeout")),
+        }
+    }
+
+    fn shed(&self, reason: &'static str) -> AppError {
+        tracing::warn!(scope = self.scope, reason, "Shedding load");
+        metrics::counter!(
+            "media_server_load_shed_total",
+            "scope" => self.scope.clone(),
+            "reason" => reason,
+        )
+        .increment(1);
+        AppError::Overloaded(self.retry_after_secs)
+    }
+}
+
+/// [`ConcurrencyLimit::acquire`] for optional limits; always succeeds without one.
+pub async fn acquire(
+    limit: Option<&ConcurrencyLimit>,
+) -> Result<Option<OwnedSemaphorePermit>, AppError> {
+    match limit {
+        Some(limit) => limit.acquire().await.map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Leaves the wait queue when dropped, whether or not a permit was obtained.
+struct QueueSlot<'a>(&'a AtomicUsize);
+
+impl Drop for QueueSlot<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,71 @@
+use super::*;
+
+fn limit(max_in_flight: usize, max_queued: usize) -> ConcurrencyLimit {
+    ConcurrencyLimit::from_config(
+        "test",
+        &ConcurrencyConfig {
+            max_in_flight,
+            max_queued,
+            queue_timeout_ms: 1000,
+            retry_after_secs: 2,
+        },
+    )
+    .unwrap()
+}
+
+#[tokio::test]
+async fn sheds_when_queue_is_full() {
+    let limit = limit(1, 0);
+
+    let permit = limit.acquire().await.unwrap();
+    assert!(matches!(
+        limit.acquire().await,
+        Err(AppError::Overloaded(2))
+    ));
+
+    drop(permit);
+    assert!(limit.acquire().await.is_ok());
+}
+
+#[tokio::test(start_paused = true)]
+async fn queued_request_gets_released_slot() {
+    let limit = Arc::new(limit(1, 1));
+    let permit = limit.acquire().await.unwrap();
+
+    let waiter = tokio::spawn({
+        let limit = limit.clone();
+        async move { limit.acquire().await.map(|_| ()) }
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    // The queue has room for one waiter only.
+    assert!(limit.acquire().await.is_err());
+
+    drop(permit);
+    assert!(waiter.await.unwrap().is_ok());
+    assert_eq!(limit.queued.load(Ordering::Acquire), 0);
+}
+
+#[tokio::test(start_paused = true)]
+async fn queued_request_times_out() {
+    let limit = limit(1, 1);
+    let _permit = limit.acquire().await.unwrap();
+
+    let started = tokio::time::Instant::now();
+    assert!(matches!(
+        limit.acquire().await,
+        Err(AppError::Overloaded(_))
+    ));
+    assert_eq!(started.elapsed(), Duration::from_secs(1));
+    assert_eq!(limit.queued.load(Ordering::Acquire), 0);
+}
+
+#[test]
+fn zero_in_flight_is_a_config_error() {
+    let config = ConcurrencyConfig {
+        max_in_flight: 0,
+        max_queued: 0,
+        queue_timeout_ms: 0,
+        retry_after_secs: 1,
+    };
+    assert!(ConcurrencyLimit::from_config("test", &config).is_err());
+}
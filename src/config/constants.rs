@@ -6,5 +6,43 @@ pub const DEFAULT_PRESIGN_EXPIRY: u64 = 300;
 pub const DEFAULT_S3_REGION: &str = "us-east-1";
 pub const DEFAULT_S3_FORCE_PATH_STYLE: bool = true;
 
+pub const DEFAULT_API_KEY_HEADER: &str = "X-Api-Key";
+pub const DEFAULT_ALLOW_EMPTY_REFERER: bool = true;
+pub const DEFAULT_SIGNATURE_MAX_EXPIRES_SECS: u64 = 7 * 24 * 60 * 60;
+
+pub const DEFAULT_CORS_ALLOWED_METHODS: &[&str] = &["GET", "HEAD"];
+
 pub const MEDIA_SERVER_CONFIG_PATH: &str = "MEDIA_SERVER_CONFIG_PATH";
 pub const DEFAULT_CONFIG_PATH: &str = "/etc/media-server/config.yml";
+
+pub const DEFAULT_CACHE_MAX_SIZE_BYTES: u64 = 256 * 1024 * 1024;
+pub const DEFAULT_CACHE_MAX_OBJECT_SIZE_BYTES: u64 = 8 * 1024 * 1024;
+
+pub const DEFAULT_PREFETCH_CONCURRENCY: usize = 8;
+
+pub const DEFAULT_QUEUE_TIMEOUT_MS: u64 = 1000;
+pub const DEFAULT_OVERLOAD_RETRY_AFTER_SECS: u64 = 1;
+
+pub const DEFAULT_CIRCUIT_CONSECUTIVE_FAILURES: u32 = 5;
+pub const DEFAULT_CIRCUIT_MIN_CALLS: u32 = 20;
+pub const DEFAULT_CIRCUIT_WINDOW_SECS: u64 = 30;
+pub const DEFAULT_CIRCUIT_OPEN_SECS: u64 = 30;
+pub const DEFAULT_CIRCUIT_HALF_OPEN_PROBES: u32 = 1;
+
+pub const DEFAULT_METRICS_PATH: &str = "/metrics";
+
+pub const DEFAULT_READINESS_CACHE_SECS: u64 = 10;
+pub const DEFAULT_READINESS_REQUIRE_ALL_BUCKETS: bool = true;
+
+pub const DEFAULT_OTLP_GRPC_ENDPOINT: &str = "http://localhost:4317";
+pub const DEFAULT_OTLP_HTTP_ENDPOINT: &str = "http://localhost:4318/v1/traces";
+pub const DEFAULT_SERVICE_NAME: &str = "media-server";
+
+pub const DEFAULT_LOG_LEVEL: &str = "info";
+pub const DEFAULT_LOG_TIMESTAMPS: bool = true;
+
+pub const DEFAULT_READINESS_GRACE_SECS: u64 = 5;
+pub const DEFAULT_DRAIN_TIMEOUT_SECS: u64 = 30;
+
+pub const DEFAULT_RELOAD_WATCH: bool = false;
+pub const DEFAULT_RELOAD_WATCH_INTERVAL_SECS: u64 = 5;
@@ -1,8 +1,12 @@
-use serde::Deserialize;
+use eyre::WrapErr;
+use ipnet::IpNet;
+use serde::{Deserialize, Deserializer};
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
 
 mod constants;
+mod validate;
 
 fn default_region() -> String {
     constants::DEFAULT_S3_REGION.to_string()
@@ -20,22 +24,21 @@ pub enum CredentialConfig {
     Env { env: String },
 }
 
-impl Into<String> for CredentialConfig {
-    fn into(self) -> String {
+impl CredentialConfig {
+    /// Reads the secret from wherever it is kept.
+    pub fn resolve(&self) -> eyre::Result<String> {
         match self {
-            CredentialConfig::Plain { plain } => plain,
-            CredentialConfig::Path { path } => std::fs::read(path.clone())
-                .unwrap_or_else(|_| panic!("Unable to read credential file \"{path}\""))
-                .try_into()
-                .unwrap_or_else(|_| panic!("Unable to read content of credential file \"{path}\"")),
-            CredentialConfig::Env { env } => std::env::var(env.clone()).unwrap_or_else(|_| {
-                panic!("Missing or unprocessable environment variable \"{env}\"")
+            Self::Plain { plain } => Ok(plain.clone()),
+            Self::Path { path } => std::fs::read_to_string(path)
+                .wrap_err_with(|| format!("unable to read credential file \"{path}\"")),
+            Self::Env { env } => std::env::var(env).wrap_err_with(|| {
+                format!("missing or unprocessable environment variable \"{env}\"")
             }),
         }
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, PartialEq, Deserialize)]
 pub struct BucketConfig {
     pub endpoint_url: String,
     pub bucket_name: String,
@@ -48,6 +51,512 @@ pub struct BucketConfig {
     pub presign_expiry_secs: Option<u64>,
     #[serde(default)]
     pub proxy: bool,
+    #[serde(default)]
+    pub cache: bool,
+    pub require_signature: Option<SignatureConfig>,
+    pub jwt: Option<JwtConfig>,
+    #[serde(default)]
+    pub users: Vec<UserConfig>,
+    #[serde(default)]
+    pub api_keys: Vec<ApiKeyConfig>,
+    #[serde(default = "default_api_key_header")]
+    pub api_key_header: String,
+    #[serde(default, deserialize_with = "deserialize_cidrs")]
+    pub allow_cidrs: Vec<IpNet>,
+    #[serde(default, deserialize_with = "deserialize_cidrs")]
+    pub deny_cidrs: Vec<IpNet>,
+    /// Host patterns (`example.com`, `*.example.com`) allowed to embed objects.
+    #[serde(default)]
+    pub allowed_referers: Vec<String>,
+    #[serde(default = "default_allow_empty_referer")]
+    pub allow_empty_referer: bool,
+    /// Key served instead of refused hotlinks; refused with 403 when unset.
+    pub hotlink_placeholder: Option<String>,
+    pub cors: Option<CorsConfig>,
+    pub rate_limit: Option<RateLimitConfig>,
+    pub throttle: Option<ThrottleConfig>,
+    /// Cap on concurrent `get_object` / `head_object` calls to the bucket.
+    pub concurrency: Option<ConcurrencyConfig>,
+    #[serde(default)]
+    pub timeouts: TimeoutsConfig,
+    #[serde(default)]
+    pub retry: RetryConfig,
+    pub circuit_breaker: Option<CircuitBreakerConfig>,
+    /// HTML error page for browsers, replacing `errors.html_template`.
+    pub error_template: Option<PathBuf>,
+}
+
+impl BucketConfig {
+    /// How objects are served, as shown in metrics and logs.
+    pub fn mode(&self) -> &'static str {
+        if self.proxy { "proxy" } else { "redirect" }
+    }
+}
+
+fn default_allow_empty_referer() -> bool {
+    constants::DEFAULT_ALLOW_EMPTY_REFERER
+}
+
+/// Cross-origin policy of a bucket. Buckets without one accept any origin.
+#[derive(Debug, PartialEq, Deserialize)]
+pub struct CorsConfig {
+    /// Exact origins (`https://app.example.com`), wildcard subdomains
+    /// (`https://*.example.com`) or `*`.
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+    #[serde(default = "default_cors_allowed_methods")]
+    pub allowed_methods: Vec<String>,
+    /// Request headers allowed in preflights; any requested header when empty.
+    #[serde(default)]
+    pub allowed_headers: Vec<String>,
+    /// Response headers readable by scripts, such as `Content-Range` and `ETag`.
+    #[serde(default)]
+    pub exposed_headers: Vec<String>,
+    #[serde(default)]
+    pub allow_credentials: bool,
+    pub max_age_secs: Option<u64>,
+}
+
+fn default_cors_allowed_methods() -> Vec<String> {
+    constants::DEFAULT_CORS_ALLOWED_METHODS
+        .iter()
+        .map(|method| method.to_string())
+        .collect()
+}
+
+/// Accepts CIDR blocks as well as bare addresses, taken as single-host networks.
+fn deserialize_cidrs<'de, D>(deserializer: D) -> Result<Vec<IpNet>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|cidr| {
+            cidr.parse::<IpNet>()
+                .or_else(|_| cidr.parse::<IpAddr>().map(IpNet::from))
+                .map_err(|_| serde::de::Error::custom(format!("invalid CIDR \"{cidr}\"")))
+        })
+        .collect()
+}
+
+fn default_api_key_header() -> String {
+    constants::DEFAULT_API_KEY_HEADER.to_string()
+}
+
+/// HTTP Basic user, with a bcrypt (`$2b$...`) or argon2 (`$argon2id$...`) hash.
+#[derive(Debug, PartialEq, Deserialize)]
+pub struct UserConfig {
+    pub name: String,
+    pub password_hash: CredentialConfig,
+}
+
+/// Static API key, sent in the bucket's `api_key_header`.
+#[derive(Debug, PartialEq, Deserialize)]
+pub struct ApiKeyConfig {
+    pub name: String,
+    pub key: CredentialConfig,
+}
+
+/// Requires `?expires=...&sig=...` on every request, signed with `secret`.
+#[derive(Debug, PartialEq, Deserialize)]
+pub struct SignatureConfig {
+    pub secret: CredentialConfig,
+    #[serde(default)]
+    pub bind_client_ip: bool,
+    /// Longest lifetime of a link minted by the admin API.
+    #[serde(default = "default_signature_max_expires_secs")]
+    pub max_expires_secs: u64,
+}
+
+fn default_signature_max_expires_secs() -> u64 {
+    constants::DEFAULT_SIGNATURE_MAX_EXPIRES_SECS
+}
+
+/// Requires a JWT, verified against a JWKS document or a single PEM public key.
+#[derive(Debug, PartialEq, Deserialize)]
+pub struct JwtConfig {
+    pub jwks: Option<CredentialConfig>,
+    pub pem: Option<CredentialConfig>,
+    #[serde(default)]
+    pub algorithms: Vec<jsonwebtoken::Algorithm>,
+    #[serde(default)]
+    pub audience: Vec<String>,
+    #[serde(default)]
+    pub issuer: Vec<String>,
+    /// Cookie checked when there is no `Authorization: Bearer` header.
+    pub cookie: Option<String>,
+    /// Claim listing the key prefixes the token grants access to.
+    pub paths_claim: Option<String>,
+}
+
+fn default_cache_max_size_bytes() -> u64 {
+    constants::DEFAULT_CACHE_MAX_SIZE_BYTES
+}
+
+fn default_cache_max_object_size_bytes() -> u64 {
+    constants::DEFAULT_CACHE_MAX_OBJECT_SIZE_BYTES
+}
+
+/// In-memory object cache shared by proxied buckets that opt in with `cache: true`.
+#[derive(Debug, PartialEq, Deserialize)]
+pub struct CacheConfig {
+    #[serde(default = "default_cache_max_size_bytes")]
+    pub max_size_bytes: u64,
+    #[serde(default = "default_cache_max_object_size_bytes")]
+    pub max_object_size_bytes: u64,
+    pub ttl_secs: Option<u64>,
+}
+
+/// Token-bucket limit: `requests_per_sec` sustained, up to `burst` at once.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct RateLimitConfig {
+    pub requests_per_sec: f64,
+    pub burst: Option<u32>,
+    #[serde(default)]
+    pub key: RateLimitKey,
+}
+
+/// What a rate limit counts requests against.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RateLimitKey {
+    #[default]
+    ClientIp,
+    /// The authenticated principal, or the client IP for anonymous requests.
+    Principal,
+    /// Every request to the bucket (or to the server, for the global limit).
+    Bucket,
+}
+
+/// Bandwidth caps for proxied downloads of a bucket.
+#[derive(Debug, PartialEq, Deserialize)]
+pub struct ThrottleConfig {
+    /// Cap for each download; uncapped when unset.
+    pub bytes_per_sec: Option<u64>,
+    /// Bytes of each download sent before `bytes_per_sec` kicks in.
+    #[serde(default)]
+    pub free_bytes: u64,
+    /// Cap shared by all downloads from the bucket.
+    pub bucket_bytes_per_sec: Option<u64>,
+    /// Per-download caps for matching objects; the first match replaces the defaults above.
+    #[serde(default)]
+    pub rules: Vec<ThrottleRuleConfig>,
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+pub struct ThrottleRuleConfig {
+    /// Content-type globs (`video/*`); any content type when empty.
+    #[serde(default)]
+    pub content_types: Vec<String>,
+    pub min_size_bytes: Option<u64>,
+    pub bytes_per_sec: Option<u64>,
+    #[serde(default)]
+    pub free_bytes: u64,
+}
+
+/// S3 client timeouts; the SDK defaults apply to unset ones.
+#[derive(Debug, Default, PartialEq, Deserialize)]
+pub struct TimeoutsConfig {
+    pub connect_ms: Option<u64>,
+    /// Longest wait for the next bytes of a response, including streamed bodies.
+    pub read_ms: Option<u64>,
+    /// Whole call, across retries; does not cover streaming the body.
+    pub operation_ms: Option<u64>,
+    /// A single attempt of a call.
+    pub operation_attempt_ms: Option<u64>,
+}
+
+#[derive(Debug, Default, PartialEq, Deserialize)]
+pub struct RetryConfig {
+    #[serde(default)]
+    pub mode: RetryMode,
+    /// Attempts per call, including the first one; the SDK default when unset.
+    pub max_attempts: Option<u32>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RetryMode {
+    #[default]
+    Standard,
+    /// Standard retries plus client-side rate limiting while S3 is throttling.
+    Adaptive,
+}
+
+/// Fails calls to a bucket fast once it looks down, probing for recovery
+/// after `open_secs`.
+#[derive(Debug, PartialEq, Deserialize)]
+pub struct CircuitBreakerConfig {
+    /// Opens after this many failures in a row.
+    #[serde(default = "default_circuit_consecutive_failures")]
+    pub consecutive_failures: u32,
+    /// Opens when this fraction of calls in the window failed, once the
+    /// window has at least `min_calls`.
+    pub failure_rate: Option<f64>,
+    #[serde(default = "default_circuit_min_calls")]
+    pub min_calls: u32,
+    #[serde(default = "default_circuit_window_secs")]
+    pub window_secs: u64,
+    #[serde(default = "default_circuit_open_secs")]
+    pub open_secs: u64,
+    /// Calls let through at once while half-open.
+    #[serde(default = "default_circuit_half_open_probes")]
+    pub half_open_probes: u32,
+}
+
+fn default_circuit_consecutive_failures() -> u32 {
+    constants::DEFAULT_CIRCUIT_CONSECUTIVE_FAILURES
+}
+
+fn default_circuit_min_calls() -> u32 {
+    constants::DEFAULT_CIRCUIT_MIN_CALLS
+}
+
+fn default_circuit_window_secs() -> u64 {
+    constants::DEFAULT_CIRCUIT_WINDOW_SECS
+}
+
+fn default_circuit_open_secs() -> u64 {
+    constants::DEFAULT_CIRCUIT_OPEN_SECS
+}
+
+fn default_circuit_half_open_probes() -> u32 {
+    constants::DEFAULT_CIRCUIT_HALF_OPEN_PROBES
+}
+
+/// At most `max_in_flight` at once, with up to `max_queued` more waiting
+/// `queue_timeout_ms` for a slot; anything beyond gets a 503.
+#[derive(Debug, PartialEq, Deserialize)]
+pub struct ConcurrencyConfig {
+    pub max_in_flight: usize,
+    #[serde(default)]
+    pub max_queued: usize,
+    #[serde(default = "default_queue_timeout_ms")]
+    pub queue_timeout_ms: u64,
+    #[serde(default = "default_overload_retry_after_secs")]
+    pub retry_after_secs: u64,
+}
+
+fn default_queue_timeout_ms() -> u64 {
+    constants::DEFAULT_QUEUE_TIMEOUT_MS
+}
+
+fn default_overload_retry_after_secs() -> u64 {
+    constants::DEFAULT_OVERLOAD_RETRY_AFTER_SECS
+}
+
+fn default_prefetch_concurrency() -> usize {
+    constants::DEFAULT_PREFETCH_CONCURRENCY
+}
+
+/// Admin API, mounted under `/_admin` and authenticated with a bearer token.
+#[derive(Debug, Deserialize)]
+pub struct AdminConfig {
+    pub token: CredentialConfig,
+    #[serde(default = "default_prefetch_concurrency")]
+    pub prefetch_concurrency: usize,
+}
+
+/// How error responses are rendered.
+#[derive(Debug, Default, Deserialize)]
+pub struct ErrorPagesConfig {
+    /// Show S3 and internal error details in response bodies.
+    #[serde(default)]
+    pub debug: bool,
+    /// HTML error page for browsers; a built-in page when unset. `{{status}}`,
+    /// `{{title}}`, `{{detail}}` and `{{request_id}}` are filled in.
+    pub html_template: Option<PathBuf>,
+}
+
+/// Prometheus endpoint, served on its own listener so it can stay private.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct MetricsConfig {
+    pub listen: String,
+    #[serde(default = "default_metrics_path")]
+    pub path: String,
+}
+
+fn default_metrics_path() -> String {
+    constants::DEFAULT_METRICS_PATH.to_string()
+}
+
+/// Readiness checks behind `/readyz`.
+#[derive(Debug, Deserialize)]
+pub struct HealthConfig {
+    /// How long `head_bucket` results are reused.
+    #[serde(default = "default_readiness_cache_secs")]
+    pub cache_secs: u64,
+    /// Not ready when any bucket fails its check; otherwise only when all do.
+    #[serde(default = "default_readiness_require_all_buckets")]
+    pub require_all_buckets: bool,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            cache_secs: default_readiness_cache_secs(),
+            require_all_buckets: default_readiness_require_all_buckets(),
+        }
+    }
+}
+
+fn default_readiness_cache_secs() -> u64 {
+    constants::DEFAULT_READINESS_CACHE_SECS
+}
+
+fn default_readiness_require_all_buckets() -> bool {
+    constants::DEFAULT_READINESS_REQUIRE_ALL_BUCKETS
+}
+
+/// Config reloads, on `SIGHUP` and optionally when the file changes. `listen`,
+/// `metrics`, `otlp`, `logging` and `shutdown` only change on restart.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct ReloadConfig {
+    /// Poll the config file and reload when its contents change.
+    #[serde(default = "default_reload_watch")]
+    pub watch: bool,
+    #[serde(default = "default_reload_watch_interval_secs")]
+    pub watch_interval_secs: u64,
+}
+
+impl Default for ReloadConfig {
+    fn default() -> Self {
+        Self {
+            watch: default_reload_watch(),
+            watch_interval_secs: default_reload_watch_interval_secs(),
+        }
+    }
+}
+
+fn default_reload_watch() -> bool {
+    constants::DEFAULT_RELOAD_WATCH
+}
+
+fn default_reload_watch_interval_secs() -> u64 {
+    constants::DEFAULT_RELOAD_WATCH_INTERVAL_SECS
+}
+
+/// Behaviour on `SIGTERM` / `SIGINT`.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct ShutdownConfig {
+    /// How long `/readyz` fails before the listener stops accepting connections.
+    #[serde(default = "default_readiness_grace_secs")]
+    pub readiness_grace_secs: u64,
+    /// How long in-flight requests may take to finish before the process exits anyway.
+    #[serde(default = "default_drain_timeout_secs")]
+    pub drain_timeout_secs: u64,
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        Self {
+            readiness_grace_secs: default_readiness_grace_secs(),
+            drain_timeout_secs: default_drain_timeout_secs(),
+        }
+    }
+}
+
+fn default_readiness_grace_secs() -> u64 {
+    constants::DEFAULT_READINESS_GRACE_SECS
+}
+
+fn default_drain_timeout_secs() -> u64 {
+    constants::DEFAULT_DRAIN_TIMEOUT_SECS
+}
+
+/// Application log output. `RUST_LOG`, when set, replaces `level`.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct LoggingConfig {
+    #[serde(default)]
+    pub format: LogFormat,
+    /// `EnvFilter` directives, e.g. `info,aws_smithy_runtime=warn`.
+    #[serde(default = "default_log_level")]
+    pub level: String,
+    #[serde(default = "default_log_timestamps")]
+    pub timestamps: bool,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            format: LogFormat::default(),
+            level: default_log_level(),
+            timestamps: default_log_timestamps(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    /// Single lines with span context.
+    #[default]
+    Full,
+    Compact,
+    /// Multi-line, for reading in a terminal.
+    Pretty,
+    /// One JSON object per event.
+    Json,
+}
+
+fn default_log_level() -> String {
+    constants::DEFAULT_LOG_LEVEL.to_string()
+}
+
+fn default_log_timestamps() -> bool {
+    constants::DEFAULT_LOG_TIMESTAMPS
+}
+
+/// Per-request access log.
+#[derive(Debug, Deserialize)]
+pub struct AccessLogConfig {
+    #[serde(default)]
+    pub format: AccessLogFormat,
+    /// File to append to, reopened on `SIGHUP`; stdout when unset.
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AccessLogFormat {
+    /// Apache Combined Log Format followed by the fields it lacks.
+    #[default]
+    Combined,
+    Json,
+}
+
+/// OpenTelemetry trace export.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct OtlpConfig {
+    #[serde(default)]
+    pub protocol: OtlpProtocol,
+    /// Collector URL; `localhost:4317` for gRPC or `localhost:4318/v1/traces` for HTTP when unset.
+    pub endpoint: Option<String>,
+    #[serde(default = "default_service_name")]
+    pub service_name: String,
+    /// Fraction of new traces to sample; incoming sampled traces are always followed.
+    pub sample_ratio: Option<f64>,
+}
+
+impl OtlpConfig {
+    pub fn endpoint(&self) -> &str {
+        self.endpoint.as_deref().unwrap_or(match self.protocol {
+            OtlpProtocol::Grpc => constants::DEFAULT_OTLP_GRPC_ENDPOINT,
+            OtlpProtocol::Http => constants::DEFAULT_OTLP_HTTP_ENDPOINT,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OtlpProtocol {
+    #[default]
+    Grpc,
+    Http,
+}
+
+fn default_service_name() -> String {
+    constants::DEFAULT_SERVICE_NAME.to_string()
 }
 
 fn default_listen() -> String {
@@ -68,18 +577,49 @@ pub struct AppConfig {
     pub listen: String,
     #[serde(default = "default_presign_expiry_secs")]
     pub presign_expiry_secs: u64,
+    pub cache: Option<CacheConfig>,
+    pub admin: Option<AdminConfig>,
+    /// Peers whose `Forwarded` / `X-Forwarded-For` headers are believed.
+    #[serde(default, deserialize_with = "deserialize_cidrs")]
+    pub trusted_proxies: Vec<IpNet>,
+    /// Limit applied across all buckets, on top of per-bucket limits.
+    pub rate_limit: Option<RateLimitConfig>,
+    /// Cap on proxied response bodies streaming at once, across all buckets.
+    pub open_streams: Option<ConcurrencyConfig>,
+    #[serde(default)]
+    pub errors: ErrorPagesConfig,
+    pub metrics: Option<MetricsConfig>,
+    #[serde(default)]
+    pub health: HealthConfig,
+    pub otlp: Option<OtlpConfig>,
+    pub access_log: Option<AccessLogConfig>,
+    #[serde(default)]
+    pub logging: LoggingConfig,
+    #[serde(default)]
+    pub shutdown: ShutdownConfig,
+    #[serde(default)]
+    pub reload: ReloadConfig,
     pub buckets: HashMap<String, BucketConfig>,
 }
 
 impl AppConfig {
-    pub fn load() -> eyre::Result<Self> {
-        let path = std::env::var(constants::MEDIA_SERVER_CONFIG_PATH)
+    /// Config file named by `MEDIA_SERVER_CONFIG_PATH`, or the default one.
+    pub fn path() -> PathBuf {
+        std::env::var(constants::MEDIA_SERVER_CONFIG_PATH)
             .map(PathBuf::from)
-            .unwrap_or_else(|_| PathBuf::from(constants::DEFAULT_CONFIG_PATH));
+            .unwrap_or_else(|_| PathBuf::from(constants::DEFAULT_CONFIG_PATH))
+    }
+
+    pub fn load() -> eyre::Result<Self> {
+        Self::load_from(&Self::path())
+    }
 
-        let contents = std::fs::read_to_string(&path)?;
+    pub fn load_from(path: &Path) -> eyre::Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("failed to read config {}", path.display()))?;
 
-        let config: AppConfig = serde_yaml::from_str(&contents)?;
+        let mut config: AppConfig = serde_yaml::from_str(&contents)?;
+        config.validate()?;
         Ok(config)
     }
 }
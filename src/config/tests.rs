@@ -23,7 +23,7 @@ fn test_read_plain_credential() {
     let credential = CredentialConfig::Plain {
         plain: "aaaaaa".to_string(),
     };
-    let secret: String = credential.into();
+    let secret = credential.resolve().unwrap();
     assert_eq!(secret, "aaaaaa");
 }
 
@@ -40,21 +40,20 @@ fn test_read_credential_from_file() {
         path: path.to_string_lossy().to_string(),
     };
 
-    let secret: String = credential.into();
+    let secret = credential.resolve().unwrap();
     assert_eq!(secret, "secret\n");
 
     let _ = std::fs::remove_file(&path);
 }
 
 #[test]
-#[should_panic]
-fn test_read_credential_from_missing_file_panics() {
+fn test_read_credential_from_missing_file_fails() {
     let path = unique_tmp_path("path_missing");
     let path = path.to_string_lossy().to_string();
 
     let credential = CredentialConfig::Path { path };
 
-    let _: String = credential.into();
+    assert!(credential.resolve().is_err());
 }
 
 #[test]
@@ -72,15 +71,14 @@ fn test_read_credential_from_env_var() {
     unsafe { std::env::set_var(&key, "from_env") };
 
     let credential = CredentialConfig::Env { env: key.clone() };
-    let secret: String = credential.into();
+    let secret = credential.resolve().unwrap();
     assert_eq!(secret, "from_env");
 
     unsafe { std::env::remove_var(&key) };
 }
 
 #[test]
-#[should_panic]
-fn into_string_env_panics_if_missing() {
+fn resolve_env_fails_if_missing() {
     let key = format!(
         "CREDTEST_ENV_MISSING_{}_{}",
         std::process::id(),
@@ -94,7 +92,7 @@ fn into_string_env_panics_if_missing() {
 
     let credential = CredentialConfig::Env { env: key.clone() };
 
-    let _: String = credential.into();
+    assert!(credential.resolve().is_err());
 }
 
 #[test]
@@ -200,3 +198,330 @@ buckets:
     let result: Result<AppConfig, _> = serde_yaml::from_str(yaml);
     assert!(result.is_err());
 }
+
+#[test]
+fn parse_cidrs_and_bare_addresses() {
+    let yaml = r#"
+trusted_proxies: ["10.0.0.0/8", "::1"]
+buckets:
+  internal:
+    endpoint_url: "http://localhost:9000"
+    bucket_name: "internal"
+    access_key:
+        plain: "key"
+    secret_key:
+        plain: "secret"
+    allow_cidrs: ["192.168.0.0/16"]
+    deny_cidrs: ["192.168.1.13"]
+"#;
+    let config: AppConfig = serde_yaml::from_str(yaml).unwrap();
+    assert_eq!(
+        config.trusted_proxies,
+        vec![
+            "10.0.0.0/8".parse::<IpNet>().unwrap(),
+            "::1/128".parse::<IpNet>().unwrap()
+        ]
+    );
+
+    let bucket = &config.buckets["internal"];
+    assert_eq!(
+        bucket.allow_cidrs,
+        vec!["192.168.0.0/16".parse::<IpNet>().unwrap()]
+    );
+    assert_eq!(
+        bucket.deny_cidrs,
+        vec!["192.168.1.13/32".parse::<IpNet>().unwrap()]
+    );
+}
+
+#[test]
+fn invalid_cidr_errors() {
+    let yaml = r#"
+trusted_proxies: ["10.0.0.0/33"]
+buckets: {}
+"#;
+    let result: Result<AppConfig, _> = serde_yaml::from_str(yaml);
+    assert!(result.is_err());
+}
+
+#[test]
+fn parse_timeouts_and_retry() {
+    let yaml = r#"
+buckets:
+  photos:
+    endpoint_url: "http://localhost:9000"
+    bucket_name: "photos"
+    access_key:
+        plain: "key"
+    secret_key:
+        plain: "secret"
+    timeouts:
+      connect_ms: 2000
+      operation_ms: 30000
+    retry:
+      mode: adaptive
+      max_attempts: 5
+  docs:
+    endpoint_url: "http://localhost:9000"
+    bucket_name: "docs"
+    access_key:
+        plain: "key"
+    secret_key:
+        plain: "secret"
+"#;
+    let config: AppConfig = serde_yaml::from_str(yaml).unwrap();
+
+    let photos = &config.buckets["photos"];
+    assert_eq!(photos.timeouts.connect_ms, Some(2000));
+    assert_eq!(photos.timeouts.read_ms, None);
+    assert_eq!(photos.timeouts.operation_ms, Some(30000));
+    assert_eq!(photos.retry.mode, RetryMode::Adaptive);
+    assert_eq!(photos.retry.max_attempts, Some(5));
+
+    let docs = &config.buckets["docs"];
+    assert_eq!(docs.retry.mode, RetryMode::Standard);
+    assert_eq!(docs.retry.max_attempts, None);
+}
+
+#[test]
+fn valid_config_passes_validation() {
+    let yaml = r#"
+listen: "localhost:8080"
+metrics:
+  listen: "127.0.0.1:9090"
+buckets:
+  photos:
+    endpoint_url: "https://minio.example.com"
+    bucket_name: "my-photos.2026"
+    access_key:
+        plain: "key"
+    secret_key:
+        plain: "secret"
+    presign_expiry_secs: 604800
+"#;
+    let mut config: AppConfig = serde_yaml::from_str(yaml).unwrap();
+    config.validate().unwrap();
+}
+
+#[test]
+fn validation_reports_every_problem_with_its_path() {
+    let missing_file = unique_tmp_path("validate_missing");
+    let yaml = format!(
+        r#"
+listen: "8080"
+presign_expiry_secs: 0
+reload:
+  watch: true
+  watch_interval_secs: 0
+admin:
+  token:
+    env: "MEDIA_SERVER_TEST_UNSET_ADMIN_TOKEN"
+buckets:
+  photos:
+    endpoint_url: "minio:9000"
+    bucket_name: "My_Photos"
+    access_key:
+        plain: "key"
+    secret_key:
+        path: "{missing_file}"
+    presign_expiry_secs: 604801
+    users:
+      - name: "alice"
+        password_hash:
+          plain: "$2b$12$hash"
+      - name: "bob"
+        password_hash:
+          env: "MEDIA_SERVER_TEST_UNSET_BOB_HASH"
+  docs:
+    endpoint_url: "http://localhost:9000"
+    bucket_name: "192.168.1.1"
+    access_key:
+        plain: "key"
+    secret_key:
+        plain: "secret"
+"#,
+        missing_file = missing_file.display()
+    );
+    let mut config: AppConfig = serde_yaml::from_str(&yaml).unwrap();
+
+    let err = config.validate().unwrap_err();
+    let paths: Vec<_> = err.0.iter().map(|problem| problem.path.as_str()).collect();
+    assert_eq!(
+        paths,
+        [
+            "listen",
+            "presign_expiry_secs",
+            "reload.watch_interval_secs",
+            "admin.token",
+            "buckets.docs.bucket_name",
+            "buckets.photos.endpoint_url",
+            "buckets.photos.bucket_name",
+            "buckets.photos.presign_expiry_secs",
+            "buckets.photos.secret_key",
+            "buckets.photos.users[1].password_hash",
+        ]
+    );
+    assert!(
+        err.to_string().contains(
+            "buckets.photos.bucket_name: \"My_Photos\" may only contain lowercase letters"
+        )
+    );
+}
+
+#[test]
+fn validation_covers_component_settings() {
+    let yaml = r#"
+rate_limit:
+  requests_per_sec: 1e-20
+open_streams:
+  max_in_flight: 0
+logging:
+  level: "info,=="
+otlp:
+  sample_ratio: 2
+buckets:
+  videos:
+    endpoint_url: "http://localhost:9000"
+    bucket_name: "videos"
+    access_key:
+        plain: "key"
+    secret_key:
+        plain: "secret"
+    cors:
+      allowed_origins: ["*"]
+      allowed_methods: ["GET", "GE T"]
+      exposed_headers: ["Content Range"]
+      allow_credentials: true
+    rate_limit:
+      requests_per_sec: 0
+    throttle:
+      bytes_per_sec: 0
+      rules:
+        - content_types: ["video/[mp4"]
+          bytes_per_sec: 0
+    circuit_breaker:
+      consecutive_failures: 0
+      failure_rate: 1.5
+    retry:
+      max_attempts: 0
+  members:
+    endpoint_url: "http://localhost:9000"
+    bucket_name: "members"
+    access_key:
+        plain: "key"
+    secret_key:
+        plain: "secret"
+    jwt:
+      pem:
+        plain: "not a key"
+  docs:
+    endpoint_url: "http://localhost:9000"
+    bucket_name: "docs"
+    access_key:
+        plain: "key"
+    secret_key:
+        plain: "secret"
+    users:
+      - name: "alice"
+        password_hash:
+          plain: "plaintext"
+"#;
+    let mut config: AppConfig = serde_yaml::from_str(yaml).unwrap();
+
+    let err = config.validate().unwrap_err();
+    let paths: Vec<_> = err.0.iter().map(|problem| problem.path.as_str()).collect();
+    assert_eq!(
+        paths,
+        [
+            "otlp.sample_ratio",
+            "logging.level",
+            "rate_limit",
+            "open_streams",
+            "buckets.docs.users",
+            "buckets.members.jwt",
+            "buckets.videos.retry.max_attempts",
+            "buckets.videos.cors",
+            "buckets.videos.rate_limit",
+            "buckets.videos.throttle",
+            "buckets.videos.circuit_breaker",
+        ]
+    );
+    let message = err.to_string();
+    assert!(message.contains("buckets.members.jwt: `algorithms` is required with a PEM key"));
+    assert!(message.contains("buckets.videos.cors: invalid CORS method \"GE T\""));
+    assert!(message.contains("neither bcrypt nor argon2"));
+}
+
+#[test]
+fn bucket_names_of_built_in_routes_are_rejected() {
+    let bucket = r#"
+    endpoint_url: "http://localhost:9000"
+    bucket_name: "media"
+    access_key:
+        plain: "key"
+    secret_key:
+        plain: "secret"
+"#;
+    let yaml = format!("buckets:\n  _admin:{bucket}  healthz:{bucket}  media:{bucket}");
+    let mut config: AppConfig = serde_yaml::from_str(&yaml).unwrap();
+
+    let err = config.validate().unwrap_err();
+    let paths: Vec<_> = err.0.iter().map(|problem| problem.path.as_str()).collect();
+    assert_eq!(paths, ["buckets._admin", "buckets.healthz"]);
+}
+
+#[test]
+fn validation_resolves_credentials_once() {
+    let path = unique_tmp_path("resolved_secret");
+    std::fs::write(&path, "from-file").unwrap();
+    let yaml = format!(
+        r#"
+buckets:
+  docs:
+    endpoint_url: "http://localhost:9000"
+    bucket_name: "docs"
+    access_key:
+        plain: "key"
+    secret_key:
+        path: "{}"
+"#,
+        path.display()
+    );
+    let mut config: AppConfig = serde_yaml::from_str(&yaml).unwrap();
+
+    config.validate().unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    // Building components afterwards no longer touches the file.
+    assert_eq!(
+        config.buckets["docs"].secret_key,
+        CredentialConfig::Plain {
+            plain: "from-file".into()
+        }
+    );
+}
+
+#[test]
+fn load_from_rejects_invalid_config() {
+    let path = unique_tmp_path("load_invalid");
+    std::fs::write(
+        &path,
+        r#"
+buckets:
+  photos:
+    endpoint_url: "ftp://minio"
+    bucket_name: "photos"
+    access_key:
+        plain: "key"
+    secret_key:
+        env: "MEDIA_SERVER_TEST_UNSET_SECRET"
+"#,
+    )
+    .unwrap();
+
+    let err = AppConfig::load_from(&path).unwrap_err();
+    std::fs::remove_file(&path).unwrap();
+
+    let invalid = err.downcast_ref::<validate::InvalidConfig>().unwrap();
+    assert_eq!(invalid.0.len(), 2);
+}
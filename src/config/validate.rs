@@ -0,0 +1,297 @@
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::Path;
+
+use axum::http::Uri;
+use tracing_subscriber::EnvFilter;
+
+use super::{AppConfig, BucketConfig, CredentialConfig};
+use crate::circuit::CircuitBreaker;
+use crate::concurrency::ConcurrencyLimit;
+use crate::throttle::Throttle;
+
+/// Longest lifetime of a presigned URL; SigV4 signatures expire within 7 days.
+const MAX_PRESIGN_EXPIRY_SECS: u64 = 7 * 24 * 60 * 60;
+
+/// First path segments served by built-in routes, which a bucket config
+/// name would collide with.
+const RESERVED_BUCKET_NAMES: [&str; 3] = ["_admin", "healthz", "readyz"];
+
+/// A problem with the value at a dotted YAML path, e.g. `buckets.photos.bucket_name`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Problem {
+    pub path: String,
+    pub message: String,
+}
+
+/// Every problem found in a config.
+#[derive(Debug)]
+pub struct InvalidConfig(pub Vec<Problem>);
+
+impl fmt::Display for InvalidConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid configuration")?;
+        for problem in &self.0 {
+            write!(f, "\n  {}: {}", problem.path, problem.message)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for InvalidConfig {}
+
+#[derive(Default)]
+struct Problems(Vec<Problem>);
+
+impl Problems {
+    fn add(&mut self, path: impl Into<String>, message: impl fmt::Display) {
+        self.0.push(Problem {
+            path: path.into(),
+            message: message.to_string(),
+        });
+    }
+
+    fn check<E: fmt::Display>(&mut self, path: impl Into<String>, result: Result<(), E>) {
+        if let Err(err) = result {
+            self.add(path, err);
+        }
+    }
+
+    fn positive(&mut self, path: impl Into<String>, value: u64) {
+        if value == 0 {
+            self.add(path, "must be positive");
+        }
+    }
+
+    /// Reports a component that could not be built from its settings.
+    fn component(&mut self, path: impl Into<String>, result: eyre::Result<()>) {
+        if let Err(err) = result {
+            self.add(path, format!("{err:#}"));
+        }
+    }
+
+    /// Replaces `credential` with its secret, so that it is read only once.
+    fn resolve(&mut self, path: impl Into<String>, credential: &mut CredentialConfig) {
+        match credential.resolve() {
+            Ok(plain) => *credential = CredentialConfig::Plain { plain },
+            Err(err) => self.add(path, format!("{err:#}")),
+        }
+    }
+}
+
+impl AppConfig {
+    /// Checks what deserialization cannot and reports all problems at once.
+    /// Credentials are resolved in place, so that the components built from
+    /// the config don't read secret files and variables a second time, and
+    /// component settings are checked by building the components.
+    pub fn validate(&mut self) -> Result<(), InvalidConfig> {
+        let mut problems = Problems::default();
+
+        problems.check("listen", listen_address(&self.listen));
+        problems.check(
+            "presign_expiry_secs",
+            presign_expiry(self.presign_expiry_secs),
+        );
+        if let Some(metrics) = &self.metrics {
+            problems.check("metrics.listen", listen_address(&metrics.listen));
+        }
+        if let Some(otlp) = &self.otlp {
+            problems.check("otlp.endpoint", url(otlp.endpoint()));
+        }
+        if let Some(ratio) = self.otlp.as_ref().and_then(|otlp| otlp.sample_ratio) {
+            problems.check("otlp.sample_ratio", fraction(ratio));
+        }
+        problems.check(
+            "logging.level",
+            EnvFilter::try_new(&self.logging.level).map(drop),
+        );
+        problems.positive(
+            "reload.watch_interval_secs",
+            self.reload.watch_interval_secs,
+        );
+        if let Some(admin) = &mut self.admin {
+            problems.resolve("admin.token", &mut admin.token);
+            problems.positive(
+                "admin.prefetch_concurrency",
+                admin.prefetch_concurrency as u64,
+            );
+        }
+        if let Some(rate_limit) = &self.rate_limit {
+            problems.component("rate_limit", crate::rate_limit::check_limit(rate_limit));
+        }
+        if let Some(open_streams) = &self.open_streams {
+            problems.component(
+                "open_streams",
+                ConcurrencyLimit::from_config("open_streams", open_streams).map(drop),
+            );
+        }
+        if let Some(template) = &self.errors.html_template {
+            problems.check("errors.html_template", readable(template));
+        }
+
+        let mut names: Vec<_> = self.buckets.keys().cloned().collect();
+        names.sort();
+        for name in names {
+            let bc = self.buckets.get_mut(&name).expect("listed above");
+            validate_bucket(&mut problems, &name, bc);
+        }
+
+        if problems.0.is_empty() {
+            Ok(())
+        } else {
+            Err(InvalidConfig(problems.0))
+        }
+    }
+}
+
+fn validate_bucket(problems: &mut Problems, name: &str, bc: &mut BucketConfig) {
+    let path = format!("buckets.{name}");
+    if RESERVED_BUCKET_NAMES.contains(&name) {
+        problems.add(&path, format!("\"{name}\" is taken by a built-in route"));
+    }
+    problems.check(format!("{path}.endpoint_url"), url(&bc.endpoint_url));
+    problems.check(format!("{path}.bucket_name"), bucket_name(&bc.bucket_name));
+    if let Some(expiry) = bc.presign_expiry_secs {
+        problems.check(
+            format!("{path}.presign_expiry_secs"),
+            presign_expiry(expiry),
+        );
+    }
+    if let Some(max_attempts) = bc.retry.max_attempts {
+        problems.positive(format!("{path}.retry.max_attempts"), max_attempts.into());
+    }
+    if let Some(template) = &bc.error_template {
+        problems.check(format!("{path}.error_template"), readable(template));
+    }
+
+    let found_before = problems.0.len();
+    problems.resolve(format!("{path}.access_key"), &mut bc.access_key);
+    problems.resolve(format!("{path}.secret_key"), &mut bc.secret_key);
+    if let Some(signature) = &mut bc.require_signature {
+        problems.resolve(
+            format!("{path}.require_signature.secret"),
+            &mut signature.secret,
+        );
+    }
+    if let Some(jwt) = &mut bc.jwt {
+        for (field, credential) in [("jwks", &mut jwt.jwks), ("pem", &mut jwt.pem)] {
+            if let Some(credential) = credential {
+                problems.resolve(format!("{path}.jwt.{field}"), credential);
+            }
+        }
+    }
+    for (i, user) in bc.users.iter_mut().enumerate() {
+        problems.resolve(
+            format!("{path}.users[{i}].password_hash"),
+            &mut user.password_hash,
+        );
+    }
+    for (i, api_key) in bc.api_keys.iter_mut().enumerate() {
+        problems.resolve(format!("{path}.api_keys[{i}].key"), &mut api_key.key);
+    }
+    // Keys and password hashes are only parsed once they could be read.
+    if problems.0.len() == found_before {
+        for (field, err) in crate::access::check_bucket(bc) {
+            problems.component(format!("{path}.{field}"), Err(err));
+        }
+    }
+
+    if let Some(cors) = &bc.cors {
+        problems.component(format!("{path}.cors"), crate::cors::check_policy(cors));
+    }
+    if let Some(rate_limit) = &bc.rate_limit {
+        problems.component(
+            format!("{path}.rate_limit"),
+            crate::rate_limit::check_limit(rate_limit),
+        );
+    }
+    if let Some(throttle) = &bc.throttle {
+        problems.component(
+            format!("{path}.throttle"),
+            Throttle::from_config(throttle).map(drop),
+        );
+    }
+    if let Some(concurrency) = &bc.concurrency {
+        problems.component(
+            format!("{path}.concurrency"),
+            ConcurrencyLimit::from_config(name, concurrency).map(drop),
+        );
+    }
+    if let Some(breaker) = &bc.circuit_breaker {
+        problems.component(
+            format!("{path}.circuit_breaker"),
+            CircuitBreaker::check_config(breaker),
+        );
+    }
+}
+
+/// `ip:port`, `[ipv6]:port` or `host:port`.
+fn listen_address(listen: &str) -> Result<(), String> {
+    if listen.parse::<SocketAddr>().is_ok() {
+        return Ok(());
+    }
+    match listen.rsplit_once(':') {
+        Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => Ok(()),
+        _ => Err(format!("\"{listen}\" is not a host:port address")),
+    }
+}
+
+fn url(url: &str) -> Result<(), String> {
+    let uri: Uri = url
+        .parse()
+        .map_err(|err| format!("\"{url}\" is not a valid URL: {err}"))?;
+    if !matches!(uri.scheme_str(), Some("http" | "https")) {
+        return Err(format!("\"{url}\" must start with http:// or https://"));
+    }
+    if uri.host().is_none_or(str::is_empty) {
+        return Err(format!("\"{url}\" has no host"));
+    }
+    Ok(())
+}
+
+fn fraction(value: f64) -> Result<(), String> {
+    if !(0.0..=1.0).contains(&value) {
+        return Err(format!("{value} is not between 0 and 1"));
+    }
+    Ok(())
+}
+
+fn readable(path: &Path) -> Result<(), String> {
+    std::fs::read_to_string(path)
+        .map(drop)
+        .map_err(|err| format!("cannot read {}: {err}", path.display()))
+}
+
+fn presign_expiry(secs: u64) -> Result<(), String> {
+    if secs == 0 || secs > MAX_PRESIGN_EXPIRY_SECS {
+        return Err(format!(
+            "{secs} is outside 1..={MAX_PRESIGN_EXPIRY_SECS} seconds (7 days)"
+        ));
+    }
+    Ok(())
+}
+
+/// S3 bucket naming rules: 3 to 63 lowercase letters, digits, dots and
+/// hyphens, starting and ending with a letter or digit, not an IP address.
+fn bucket_name(name: &str) -> Result<(), String> {
+    let valid_chars = name
+        .bytes()
+        .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'.' || b == b'-');
+    let valid_ends = name.starts_with(|c: char| c.is_ascii_alphanumeric())
+        && name.ends_with(|c: char| c.is_ascii_alphanumeric());
+
+    let problem = if !(3..=63).contains(&name.len()) {
+        "must be 3 to 63 characters long"
+    } else if !valid_chars {
+        "may only contain lowercase letters, digits, dots and hyphens"
+    } else if !valid_ends {
+        "must start and end with a letter or digit"
+    } else if name.contains("..") {
+        "must not contain consecutive dots"
+    } else if name.parse::<std::net::Ipv4Addr>().is_ok() {
+        "must not be formatted as an IP address"
+    } else {
+        return Ok(());
+    };
+    Err(format!("\"{name}\" {problem}"))
+}
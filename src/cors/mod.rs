@@ -0,0 +1,277 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use axum::extract::{Request, State};
+use axum::http::{HeaderMap, HeaderName, HeaderValue, Method, StatusCode, header};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use eyre::WrapErr;
+
+use crate::config::{AppConfig, CorsConfig};
+
+struct CorsPolicy {
+    /// Lowercased origin patterns; `None` accepts any origin and answers `*`.
+    origins: Option<Vec<String>>,
+    methods: Vec<Method>,
+    /// `None` accepts whatever headers the preflight asks for.
+    allowed_headers: Option<Vec<HeaderName>>,
+    exposed_headers: Vec<HeaderName>,
+    allow_credentials: bool,
+    max_age_secs: Option<u64>,
+}
+
+impl CorsPolicy {
+    /// Policy of buckets without a `cors` section.
+    fn permissive() -> Self {
+        Self {
+            origins: None,
+            methods: vec![Method::GET, Method::HEAD],
+            allowed_headers: None,
+            exposed_headers: Vec::new(),
+            allow_credentials: false,
+            max_age_secs: None,
+        }
+    }
+
+    fn from_config(config: &CorsConfig) -> eyre::Result<Self> {
+        let methods = config
+            .allowed_methods
+            .iter()
+            .map(|method| {
+                Method::from_bytes(method.to_ascii_uppercase().as_bytes())
+                    .wrap_err_with(|| format!("invalid CORS method \"{method}\""))
+            })
+            .collect::<eyre::Result<_>>()?;
+        if config.allow_credentials && config.allowed_origins.iter().any(|origin| origin == "*") {
+            eyre::bail!("`allowed_origins: [\"*\"]` cannot be combined with `allow_credentials`");
+        }
+
+        Ok(Self {
+            origins: Some(
+                config
+                    .allowed_origins
+                    .iter()
+                    .map(|origin| origin.to_ascii_lowercase())
+                    .collect(),
+            ),
+            methods,
+            allowed_headers: (!config.allowed_headers.is_empty())
+                .then(|| header_names(&config.allowed_headers))
+                .transpose()?,
+            exposed_headers: header_names(&config.exposed_headers)?,
+            allow_credentials: config.allow_credentials,
+            max_age_secs: config.max_age_secs,
+        })
+    }
+
+    fn allows_origin(&self, origin: &HeaderValue) -> bool {
+        let Some(patterns) = &self.origins else {
+            return true;
+        };
+        let Ok(origin) = origin.to_str() else {
+            return false;
+        };
+        let origin = origin.to_ascii_lowercase();
+        patterns
+            .iter()
+            .any(|pattern| origin_matches(pattern, &origin))
+    }
+
+    /// Answers a preflight, refusing it with 403 when the origin, method or
+    /// headers are not allowed.
+    fn preflight(&self, origin: &HeaderValue, headers: &HeaderMap) -> Response {
+        let method_allowed = headers
+            .get(header::ACCESS_CONTROL_REQUEST_METHOD)
+            .and_then(|value| Method::from_bytes(value.as_bytes()).ok())
+            .is_some_and(|method| self.methods.contains(&method));
+
+        let requested_headers = headers
+            .get_all(header::ACCESS_CONTROL_REQUEST_HEADERS)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .collect::<Vec<_>>();
+        let headers_allowed = match &self.allowed_headers {
+            None => true,
+            Some(allowed) => requested_headers.iter().all(|name| {
+                allowed
+                    .iter()
+                    .any(|a| a.as_str().eq_ignore_ascii_case(name))
+            }),
+        };
+
+        let mut response = if self.allows_origin(origin) && method_allowed && headers_allowed {
+            StatusCode::NO_CONTENT.into_response()
+        } else {
+            StatusCode::FORBIDDEN.into_response()
+        };
+        self.vary(response.headers_mut(), true);
+        if response.status() == StatusCode::FORBIDDEN {
+            return response;
+        }
+
+        let response_headers = response.headers_mut();
+        self.allow_origin(origin, response_headers);
+        response_headers.insert(
+            header::ACCESS_CONTROL_ALLOW_METHODS,
+            join(self.methods.iter().map(Method::as_str)),
+        );
+        let allowed_headers = match &self.allowed_headers {
+            Some(allowed) => join(allowed.iter().map(HeaderName::as_str)),
+            None => join(requested_headers.into_iter()),
+        };
+        if !allowed_headers.is_empty() {
+            response_headers.insert(header::ACCESS_CONTROL_ALLOW_HEADERS, allowed_headers);
+        }
+        if let Some(max_age) = self.max_age_secs {
+            response_headers.insert(header::ACCESS_CONTROL_MAX_AGE, HeaderValue::from(max_age));
+        }
+        response
+    }
+
+    fn decorate(&self, origin: Option<&HeaderValue>, headers: &mut HeaderMap) {
+        self.vary(headers, false);
+        let Some(origin) = origin.filter(|origin| self.allows_origin(origin)) else {
+            return;
+        };
+
+        self.allow_origin(origin, headers);
+        if !self.exposed_headers.is_empty() {
+            headers.insert(
+                header::ACCESS_CONTROL_EXPOSE_HEADERS,
+                join(self.exposed_headers.iter().map(HeaderName::as_str)),
+            );
+        }
+    }
+
+    fn allow_origin(&self, origin: &HeaderValue, headers: &mut HeaderMap) {
+        let allowed = match self.origins {
+            None => HeaderValue::from_static("*"),
+            Some(_) => origin.clone(),
+        };
+        headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, allowed);
+        if self.allow_credentials {
+            headers.insert(
+                header::ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                HeaderValue::from_static("true"),
+            );
+        }
+    }
+
+    /// Responses of origin-specific policies depend on the request origin.
+    fn vary(&self, headers: &mut HeaderMap, preflight: bool) {
+        if self.origins.is_some() {
+            headers.append(header::VARY, HeaderValue::from_static("origin"));
+        }
+        if preflight {
+            headers.append(
+                header::VARY,
+                HeaderValue::from_static(
+                    "access-control-request-method, access-control-request-headers",
+                ),
+            );
+        }
+    }
+}
+
+/// CORS policy of each bucket config, keyed by config name.
+/// Builds a bucket's CORS policy to check its config.
+pub fn check_policy(config: &CorsConfig) -> eyre::Result<()> {
+    CorsPolicy::from_config(config).map(drop)
+}
+
+pub struct CorsPolicies {
+    buckets: HashMap<String, CorsPolicy>,
+    permissive: CorsPolicy,
+}
+
+impl CorsPolicies {
+    pub fn from_config(config: &AppConfig) -> eyre::Result<Self> {
+        let mut buckets = HashMap::new();
+
+        for (name, bc) in &config.buckets {
+            if let Some(cors) = &bc.cors {
+                let policy = CorsPolicy::from_config(cors)
+                    .wrap_err_with(|| format!("invalid CORS settings for bucket \"{name}\""))?;
+                buckets.insert(name.clone(), policy);
+            }
+        }
+
+        Ok(Self {
+            buckets,
+            permissive: CorsPolicy::permissive(),
+        })
+    }
+
+    /// Paths outside of a bucket with a `cors` section accept any origin.
+    fn policy_for(&self, path: &str) -> &CorsPolicy {
+        path.trim_start_matches('/')
+            .split('/')
+            .next()
+            .and_then(|config_name| self.buckets.get(config_name))
+            .unwrap_or(&self.permissive)
+    }
+}
+
+/// Answers preflights and decorates responses according to the policy of the
+/// bucket in the request path.
+pub async fn apply_cors(
+    State(policies): State<Arc<CorsPolicies>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let policy = policies.policy_for(request.uri().path());
+    let origin = request.headers().get(header::ORIGIN).cloned();
+
+    if let Some(origin) = &origin
+        && request.method() == Method::OPTIONS
+        && request
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_REQUEST_METHOD)
+    {
+        return policy.preflight(origin, request.headers());
+    }
+
+    let mut response = next.run(request).await;
+    policy.decorate(origin.as_ref(), response.headers_mut());
+    response
+}
+
+/// Matches `*`, exact origins, and `scheme://*.domain[:port]` wildcards, which
+/// cover subdomains but not the domain itself.
+fn origin_matches(pattern: &str, origin: &str) -> bool {
+    if pattern == "*" {
+        return true;
+    }
+
+    let Some((scheme, suffix)) = pattern.split_once("://*.") else {
+        return pattern == origin;
+    };
+
+    origin
+        .strip_prefix(scheme)
+        .and_then(|rest| rest.strip_prefix("://"))
+        .and_then(|authority| authority.strip_suffix(suffix))
+        .and_then(|subdomain| subdomain.strip_suffix('.'))
+        .is_some_and(|subdomain| !subdomain.is_empty() && !subdomain.contains([':', '/']))
+}
+
+fn header_names(names: &[String]) -> eyre::Result<Vec<HeaderName>> {
+    names
+        .iter()
+        .map(|name| {
+            HeaderName::try_from(name.as_str())
+                .wrap_err_with(|| format!("invalid header name \"{name}\""))
+        })
+        .collect()
+}
+
+fn join<'a>(values: impl Iterator<Item = &'a str>) -> HeaderValue {
+    HeaderValue::from_str(&values.collect::<Vec<_>>().join(", "))
+        .unwrap_or_else(|_| HeaderValue::from_static(""))
+}
+
+#[cfg(test)]
+mod tests;
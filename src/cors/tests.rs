@@ -0,0 +1,252 @@
+use axum::Router;
+use axum::body::Body;
+use axum::middleware;
+use axum::routing::get;
+use tower::ServiceExt;
+
+use super::*;
+
+fn policies() -> CorsPolicies {
+    let config: AppConfig = serde_yaml::from_str(
+        r#"
+buckets:
+  open:
+    endpoint_url: "http://localhost:9000"
+    bucket_name: "open"
+    access_key:
+        plain: "key"
+    secret_key:
+        plain: "secret"
+  videos:
+    endpoint_url: "http://localhost:9000"
+    bucket_name: "videos"
+    access_key:
+        plain: "key"
+    secret_key:
+        plain: "secret"
+    cors:
+      allowed_origins: ["https://app.example.com", "https://*.example.org"]
+      allowed_headers: ["Range"]
+      exposed_headers: ["Content-Range", "ETag"]
+      allow_credentials: true
+      max_age_secs: 600
+  locked:
+    endpoint_url: "http://localhost:9000"
+    bucket_name: "locked"
+    access_key:
+        plain: "key"
+    secret_key:
+        plain: "secret"
+    cors: {}
+"#,
+    )
+    .unwrap();
+    CorsPolicies::from_config(&config).unwrap()
+}
+
+fn allows(origin: &'static str, path: &str) -> bool {
+    policies()
+        .policy_for(path)
+        .allows_origin(&HeaderValue::from_static(origin))
+}
+
+fn test_router() -> Router {
+    Router::new()
+        .route("/{config_name}/{*file_path}", get(|| async { "data" }))
+        .layer(middleware::from_fn_with_state(
+            Arc::new(policies()),
+            apply_cors,
+        ))
+}
+
+async fn send(method: Method, uri: &str, headers: &[(&'static str, &'static str)]) -> Response {
+    let mut request = Request::builder().method(method).uri(uri);
+    for (name, value) in headers {
+        request = request.header(*name, *value);
+    }
+    test_router()
+        .oneshot(request.body(Body::empty()).unwrap())
+        .await
+        .unwrap()
+}
+
+#[test]
+fn buckets_without_policy_accept_any_origin() {
+    assert!(allows("https://anywhere.com", "/open/a.mp4"));
+    assert!(allows("https://anywhere.com", "/unknown/a.mp4"));
+}
+
+#[test]
+fn exact_origin_is_allowed() {
+    assert!(allows("https://app.example.com", "/videos/a.mp4"));
+    assert!(!allows("http://app.example.com", "/videos/a.mp4"));
+    assert!(!allows("https://app.example.com:8443", "/videos/a.mp4"));
+}
+
+#[test]
+fn wildcard_origin_covers_subdomains_only() {
+    assert!(allows("https://www.example.org", "/videos/a.mp4"));
+    assert!(allows("https://a.b.example.org", "/videos/a.mp4"));
+    assert!(!allows("https://example.org", "/videos/a.mp4"));
+    assert!(!allows("https://evilexample.org", "/videos/a.mp4"));
+    assert!(!allows("https://www.example.org:8443", "/videos/a.mp4"));
+}
+
+#[test]
+fn empty_policy_refuses_all_origins() {
+    assert!(!allows("https://app.example.com", "/locked/a.mp4"));
+}
+
+#[test]
+fn invalid_method_is_a_config_error() {
+    let config: AppConfig = serde_yaml::from_str(
+        r#"
+buckets:
+  videos:
+    endpoint_url: "http://localhost:9000"
+    bucket_name: "videos"
+    access_key:
+        plain: "key"
+    secret_key:
+        plain: "secret"
+    cors:
+      allowed_methods: ["GE T"]
+"#,
+    )
+    .unwrap();
+    assert!(CorsPolicies::from_config(&config).is_err());
+}
+
+#[test]
+fn any_origin_with_credentials_is_a_config_error() {
+    let config: AppConfig = serde_yaml::from_str(
+        r#"
+buckets:
+  videos:
+    endpoint_url: "http://localhost:9000"
+    bucket_name: "videos"
+    access_key:
+        plain: "key"
+    secret_key:
+        plain: "secret"
+    cors:
+      allowed_origins: ["https://app.example.com", "*"]
+      allow_credentials: true
+"#,
+    )
+    .unwrap();
+    assert!(CorsPolicies::from_config(&config).is_err());
+}
+
+#[tokio::test]
+async fn permissive_bucket_answers_with_wildcard() {
+    let resp = send(
+        Method::GET,
+        "/open/a.mp4",
+        &[("origin", "https://anywhere.com")],
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+}
+
+#[tokio::test]
+async fn allowed_origin_gets_cors_headers() {
+    let resp = send(
+        Method::GET,
+        "/videos/a.mp4",
+        &[("origin", "https://app.example.com")],
+    )
+    .await;
+    let headers = resp.headers();
+    assert_eq!(
+        headers[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+        "https://app.example.com"
+    );
+    assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+    assert_eq!(
+        headers[header::ACCESS_CONTROL_EXPOSE_HEADERS],
+        "content-range, etag"
+    );
+    assert_eq!(headers[header::VARY], "origin");
+}
+
+#[tokio::test]
+async fn foreign_origin_gets_no_cors_headers() {
+    let resp = send(
+        Method::GET,
+        "/videos/a.mp4",
+        &[("origin", "https://evil.com")],
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(
+        !resp
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+    );
+}
+
+#[tokio::test]
+async fn preflight_is_answered_per_bucket() {
+    let resp = send(
+        Method::OPTIONS,
+        "/videos/a.mp4",
+        &[
+            ("origin", "https://www.example.org"),
+            ("access-control-request-method", "GET"),
+            ("access-control-request-headers", "range"),
+        ],
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    let headers = resp.headers();
+    assert_eq!(
+        headers[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+        "https://www.example.org"
+    );
+    assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_METHODS], "GET, HEAD");
+    assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_HEADERS], "range");
+    assert_eq!(headers[header::ACCESS_CONTROL_MAX_AGE], "600");
+}
+
+#[tokio::test]
+async fn preflight_with_disallowed_method_or_header_is_refused() {
+    for (method, request_headers) in [("DELETE", "range"), ("GET", "x-custom")] {
+        let resp = send(
+            Method::OPTIONS,
+            "/videos/a.mp4",
+            &[
+                ("origin", "https://app.example.com"),
+                ("access-control-request-method", method),
+                ("access-control-request-headers", request_headers),
+            ],
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        assert!(
+            !resp
+                .headers()
+                .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+        );
+    }
+}
+
+#[tokio::test]
+async fn permissive_preflight_mirrors_requested_headers() {
+    let resp = send(
+        Method::OPTIONS,
+        "/open/a.mp4",
+        &[
+            ("origin", "https://anywhere.com"),
+            ("access-control-request-method", "GET"),
+            ("access-control-request-headers", "range, x-custom"),
+        ],
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    assert_eq!(
+        resp.headers()[header::ACCESS_CONTROL_ALLOW_HEADERS],
+        "range, x-custom"
+    );
+}
@@ -1,11 +1,45 @@
-use axum::http::StatusCode;
+use aws_sdk_s3::config::http::HttpResponse;
+use aws_sdk_s3::error::{DisplayErrorContext, ProvideErrorMetadata, SdkError};
+use axum::http::{HeaderValue, StatusCode, header};
 use axum::response::{IntoResponse, Response};
 
+mod render;
+
+pub use render::{ErrorPages, render_errors};
+
+/// `Retry-After` passed on when S3 asks us to slow down.
+const SLOW_DOWN_RETRY_AFTER_SECS: u64 = 1;
+
 #[derive(Debug)]
 pub enum AppError {
     ConfigNotFound(String),
     ObjectNotFound(String),
+    CacheNotEnabled(String),
+    InvalidGlob(String),
+    InvalidSignature(String),
+    InvalidSigningRequest(String),
+    Unauthorized(String),
+    BasicAuthRequired(String),
+    Forbidden(String),
+    RateLimited(u64),
+    Overloaded(u64),
+    AccessDenied(String),
+    InvalidKey(String),
+    RangeNotSatisfiable(String),
+    PreconditionFailed(String),
+    /// The client's copy, named by `If-None-Match` or `If-Modified-Since`, is current.
+    NotModified(String),
+    UpstreamTimeout(String),
+    /// S3 answered with a server error or an unusable response, or could not be reached.
+    BadGateway(String),
+    /// S3 reported itself unavailable.
+    UpstreamUnavailable(String),
+    CircuitOpen {
+        bucket: String,
+        retry_after_secs: u64,
+    },
     S3Error(String),
+    Internal(String),
 }
 
 impl std::fmt::Display for AppError {
@@ -13,20 +47,176 @@ impl std::fmt::Display for AppError {
         match self {
             Self::ConfigNotFound(name) => write!(f, "config not found: {name}"),
             Self::ObjectNotFound(key) => write!(f, "object not found: {key}"),
+            Self::CacheNotEnabled(name) => write!(f, "cache not enabled for config: {name}"),
+            Self::InvalidGlob(msg) => write!(f, "invalid glob pattern: {msg}"),
+            Self::InvalidSignature(msg) => write!(f, "invalid signature: {msg}"),
+            Self::InvalidSigningRequest(msg) => write!(f, "invalid signing request: {msg}"),
+            Self::Unauthorized(msg) => write!(f, "unauthorized: {msg}"),
+            Self::BasicAuthRequired(msg) => write!(f, "unauthorized: {msg}"),
+            Self::Forbidden(msg) => write!(f, "forbidden: {msg}"),
+            Self::RateLimited(secs) => write!(f, "rate limit exceeded, retry after {secs}s"),
+            Self::Overloaded(secs) => write!(f, "server busy, retry after {secs}s"),
+            Self::AccessDenied(key) => write!(f, "access denied: {key}"),
+            Self::InvalidKey(msg) => write!(f, "invalid key: {msg}"),
+            Self::RangeNotSatisfiable(key) => write!(f, "range not satisfiable: {key}"),
+            Self::PreconditionFailed(key) => write!(f, "precondition failed: {key}"),
+            Self::NotModified(key) => write!(f, "not modified: {key}"),
+            Self::UpstreamTimeout(msg) => write!(f, "S3 timed out: {msg}"),
+            Self::BadGateway(msg) => write!(f, "bad response from S3: {msg}"),
+            Self::UpstreamUnavailable(msg) => write!(f, "S3 unavailable: {msg}"),
+            Self::CircuitOpen { bucket, .. } => {
+                write!(f, "backend of config {bucket} is unavailable")
+            }
             Self::S3Error(msg) => write!(f, "S3 error: {msg}"),
+            Self::Internal(msg) => write!(f, "internal error: {msg}"),
+        }
+    }
+}
+
+impl AppError {
+    /// Maps a failed SDK call about `key` to the error the client should see.
+    pub fn from_sdk<E>(err: SdkError<E, HttpResponse>, key: &str) -> Self
+    where
+        E: ProvideErrorMetadata + std::error::Error + 'static,
+    {
+        match err {
+            SdkError::ServiceError(context) => {
+                let status = context.raw().status().as_u16();
+                let err = context.into_err();
+                Self::from_s3_response(status, err.code(), key, DisplayErrorContext(&err))
+            }
+            SdkError::TimeoutError(_) => {
+                Self::UpstreamTimeout(DisplayErrorContext(&err).to_string())
+            }
+            SdkError::DispatchFailure(ref failure) if failure.is_timeout() => {
+                Self::UpstreamTimeout(DisplayErrorContext(&err).to_string())
+            }
+            SdkError::DispatchFailure(_) | SdkError::ResponseError(_) => {
+                Self::BadGateway(DisplayErrorContext(&err).to_string())
+            }
+            err => Self::S3Error(DisplayErrorContext(&err).to_string()),
+        }
+    }
+
+    /// Classifies an S3 error response by its error code, falling back to the
+    /// HTTP status for bodiless (`HEAD`) responses.
+    fn from_s3_response(
+        status: u16,
+        code: Option<&str>,
+        key: &str,
+        message: impl std::fmt::Display,
+    ) -> Self {
+        match (code, status) {
+            (Some("NoSuchBucket"), _) => Self::S3Error(message.to_string()),
+            (Some("NoSuchKey" | "NotFound"), _) | (None, 404) => {
+                Self::ObjectNotFound(key.to_string())
+            }
+            (Some("AccessDenied"), _) | (None, 403) => Self::AccessDenied(key.to_string()),
+            (
+                Some("InvalidArgument" | "InvalidObjectName" | "InvalidURI" | "KeyTooLongError"),
+                _,
+            ) => Self::InvalidKey(key.to_string()),
+            (Some("InvalidRange"), _) | (_, 416) => Self::RangeNotSatisfiable(key.to_string()),
+            (Some("PreconditionFailed"), _) | (_, 412) => Self::PreconditionFailed(key.to_string()),
+            (_, 304) => Self::NotModified(key.to_string()),
+            (Some("SlowDown" | "TooManyRequests"), _) | (_, 429) => {
+                Self::RateLimited(SLOW_DOWN_RETRY_AFTER_SECS)
+            }
+            (Some("ServiceUnavailable"), _) | (_, 503) => {
+                Self::UpstreamUnavailable(message.to_string())
+            }
+            (_, 500..) => Self::BadGateway(message.to_string()),
+            _ => Self::S3Error(message.to_string()),
         }
     }
 }
 
+/// The full message of an error response, for [`render_errors`] to show or
+/// hide. Internal messages can reveal backend details.
+#[derive(Clone, Debug)]
+struct ErrorDetail {
+    message: String,
+    internal: bool,
+}
+
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
+        if let Self::NotModified(_) = self {
+            // A successful revalidation, which carries no body to render.
+            tracing::debug!("{self}");
+            return StatusCode::NOT_MODIFIED.into_response();
+        }
         let (status, message) = match &self {
             Self::ConfigNotFound(_) => (StatusCode::NOT_FOUND, self.to_string()),
             Self::ObjectNotFound(_) => (StatusCode::NOT_FOUND, self.to_string()),
+            Self::CacheNotEnabled(_) => (StatusCode::BAD_REQUEST, self.to_string()),
+            Self::InvalidGlob(_) => (StatusCode::BAD_REQUEST, self.to_string()),
+            Self::InvalidSignature(_) => (StatusCode::FORBIDDEN, self.to_string()),
+            Self::InvalidSigningRequest(_) => (StatusCode::BAD_REQUEST, self.to_string()),
+            Self::Unauthorized(_) => (StatusCode::UNAUTHORIZED, self.to_string()),
+            Self::BasicAuthRequired(_) => (StatusCode::UNAUTHORIZED, self.to_string()),
+            Self::Forbidden(_) => (StatusCode::FORBIDDEN, self.to_string()),
+            Self::RateLimited(_) => (StatusCode::TOO_MANY_REQUESTS, self.to_string()),
+            Self::Overloaded(_) => (StatusCode::SERVICE_UNAVAILABLE, self.to_string()),
+            Self::AccessDenied(_) => (StatusCode::FORBIDDEN, self.to_string()),
+            Self::InvalidKey(_) => (StatusCode::BAD_REQUEST, self.to_string()),
+            Self::RangeNotSatisfiable(_) => (StatusCode::RANGE_NOT_SATISFIABLE, self.to_string()),
+            Self::PreconditionFailed(_) => (StatusCode::PRECONDITION_FAILED, self.to_string()),
+            Self::NotModified(_) => (StatusCode::NOT_MODIFIED, self.to_string()),
+            Self::UpstreamTimeout(_) => (StatusCode::GATEWAY_TIMEOUT, self.to_string()),
+            Self::BadGateway(_) => (StatusCode::BAD_GATEWAY, self.to_string()),
+            Self::UpstreamUnavailable(_) => (StatusCode::SERVICE_UNAVAILABLE, self.to_string()),
+            Self::CircuitOpen { .. } => (StatusCode::SERVICE_UNAVAILABLE, self.to_string()),
             Self::S3Error(_) => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
+            Self::Internal(_) => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
         };
-        tracing::error!("{message}");
-        (status, message).into_response()
+        // Client errors are routine (bad links, missing keys, probing) and
+        // would drown out the server errors an operator needs to see.
+        if status.is_server_error() {
+            tracing::error!("{message}");
+        } else {
+            tracing::debug!("{message}");
+        }
+        let internal = matches!(
+            self,
+            Self::UpstreamTimeout(_)
+                | Self::BadGateway(_)
+                | Self::UpstreamUnavailable(_)
+                | Self::S3Error(_)
+                | Self::Internal(_)
+        );
+        let public = if internal {
+            status.canonical_reason().unwrap_or("Error").to_string()
+        } else {
+            message.clone()
+        };
+        let mut response = (status, public).into_response();
+        response
+            .extensions_mut()
+            .insert(ErrorDetail { message, internal });
+        let challenge = match self {
+            Self::Unauthorized(_) => Some("Bearer"),
+            Self::BasicAuthRequired(_) => Some("Basic realm=\"media-server\", charset=\"UTF-8\""),
+            _ => None,
+        };
+        if let Some(challenge) = challenge {
+            response.headers_mut().insert(
+                header::WWW_AUTHENTICATE,
+                HeaderValue::from_static(challenge),
+            );
+        }
+        if let Self::RateLimited(secs)
+        | Self::Overloaded(secs)
+        | Self::CircuitOpen {
+            retry_after_secs: secs,
+            ..
+        } = self
+        {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(secs));
+        }
+        response
     }
 }
 
@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use axum::extract::{Request, State};
+use axum::http::{HeaderValue, header};
+use axum::middleware::Next;
+use axum::response::Response;
+use eyre::WrapErr;
+use serde::Serialize;
+
+use super::ErrorDetail;
+use crate::config::AppConfig;
+use crate::request_id::RequestId;
+
+const DEFAULT_HTML_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html>
+<head><meta charset="utf-8"><title>{{status}} {{title}}</title></head>
+<body>
+<h1>{{status}} {{title}}</h1>
+<p>{{detail}}</p>
+<p><small>Request ID: {{request_id}}</small></p>
+</body>
+</html>
+"#;
+
+/// RFC 9457 problem details.
+#[derive(Debug, Serialize)]
+struct Problem<'a> {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    title: &'a str,
+    status: u16,
+    detail: &'a str,
+    instance: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<&'a str>,
+}
+
+/// Renders [`AppError`](super::AppError) responses as problem+json or, for
+/// browsers, as an HTML page.
+pub struct ErrorPages {
+    debug: bool,
+    html_template: String,
+    bucket_templates: HashMap<String, String>,
+}
+
+impl Default for ErrorPages {
+    fn default() -> Self {
+        Self {
+            debug: false,
+            html_template: DEFAULT_HTML_TEMPLATE.to_string(),
+            bucket_templates: HashMap::new(),
+        }
+    }
+}
+
+impl ErrorPages {
+    pub fn from_config(config: &AppConfig) -> eyre::Result<Self> {
+        let html_template = match &config.errors.html_template {
+            Some(path) => read_template(path)?,
+            None => DEFAULT_HTML_TEMPLATE.to_string(),
+        };
+
+        let mut bucket_templates = HashMap::new();
+        for (name, bc) in &config.buckets {
+            if let Some(path) = &bc.error_template {
+                bucket_templates.insert(name.clone(), read_template(path)?);
+            }
+        }
+
+        Ok(Self {
+            debug: config.errors.debug,
+            html_template,
+            bucket_templates,
+        })
+    }
+
+    fn template_for(&self, path: &str) -> &str {
+        path.trim_start_matches('/')
+            .split('/')
+            .next()
+            .and_then(|config_name| self.bucket_templates.get(config_name))
+            .unwrap_or(&self.html_template)
+    }
+
+    fn render(
+        &self,
+        response: &mut Response,
+        detail: &ErrorDetail,
+        path: &str,
+        wants_html: bool,
+        request_id: Option<&RequestId>,
+    ) {
+        let status = response.status();
+        let title = status.canonical_reason().unwrap_or("Error");
+        let detail = if detail.internal && !self.debug {
+            title
+        } else {
+            detail.message.as_str()
+        };
+        let request_id = request_id.map(|RequestId(id)| id.as_ref());
+
+        let (content_type, body) = if wants_html {
+            let page = self
+                .template_for(path)
+                .replace("{{status}}", status.as_str())
+                .replace("{{title}}", &escape_html(title))
+                .replace("{{detail}}", &escape_html(detail))
+                .replace("{{request_id}}", &escape_html(request_id.unwrap_or("")));
+            ("text/html; charset=utf-8", page)
+        } else {
+            let problem = Problem {
+                kind: "about:blank",
+                title,
+                status: status.as_u16(),
+                detail,
+                instance: path,
+                request_id,
+            };
+            let body = serde_json::to_string(&problem).unwrap_or_default();
+            ("application/problem+json", body)
+        };
+
+        response
+            .headers_mut()
+            .insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+        response.headers_mut().remove(header::CONTENT_LENGTH);
+        *response.body_mut() = body.into();
+    }
+}
+
+fn read_template(path: &Path) -> eyre::Result<String> {
+    std::fs::read_to_string(path)
+        .wrap_err_with(|| format!("failed to read error template {}", path.display()))
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Replaces the bodies of error responses according to the `Accept` header.
+pub async fn render_errors(
+    State(pages): State<Arc<ErrorPages>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let path = request.uri().path().to_string();
+    let wants_html = request
+        .headers()
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/html"));
+    let request_id = request.extensions().get::<RequestId>().cloned();
+
+    let mut response = next.run(request).await;
+    if let Some(detail) = response.extensions_mut().remove::<ErrorDetail>() {
+        pages.render(
+            &mut response,
+            &detail,
+            &path,
+            wants_html,
+            request_id.as_ref(),
+        );
+    }
+    response
+}
@@ -1,4 +1,7 @@
 use super::*;
+use aws_sdk_s3::error::ErrorMetadata;
+use aws_sdk_s3::operation::get_object::GetObjectError;
+use aws_sdk_s3::primitives::SdkBody;
 
 #[test]
 fn config_not_found_is_404() {
@@ -12,8 +15,294 @@ fn object_not_found_is_404() {
     assert_eq!(resp.status(), StatusCode::NOT_FOUND);
 }
 
+#[test]
+fn cache_not_enabled_is_400() {
+    let resp = AppError::CacheNotEnabled("x".into()).into_response();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+}
+
+#[test]
+fn invalid_glob_is_400() {
+    let resp = AppError::InvalidGlob("[".into()).into_response();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+}
+
+#[test]
+fn invalid_signature_is_403() {
+    let resp = AppError::InvalidSignature("expired".into()).into_response();
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+}
+
+#[test]
+fn unauthorized_is_401() {
+    let resp = AppError::Unauthorized("missing credentials".into()).into_response();
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(resp.headers()[header::WWW_AUTHENTICATE], "Bearer");
+}
+
+#[test]
+fn basic_auth_required_is_401_with_basic_challenge() {
+    let resp = AppError::BasicAuthRequired("missing credentials".into()).into_response();
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    assert!(
+        resp.headers()[header::WWW_AUTHENTICATE]
+            .to_str()
+            .unwrap()
+            .starts_with("Basic ")
+    );
+}
+
+#[test]
+fn forbidden_is_403() {
+    let resp = AppError::Forbidden("path".into()).into_response();
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+}
+
+#[test]
+fn rate_limited_is_429_with_retry_after() {
+    let resp = AppError::RateLimited(3).into_response();
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(resp.headers()[header::RETRY_AFTER], "3");
+}
+
+#[test]
+fn overloaded_is_503_with_retry_after() {
+    let resp = AppError::Overloaded(1).into_response();
+    assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(resp.headers()[header::RETRY_AFTER], "1");
+}
+
+#[test]
+fn upstream_timeout_is_504() {
+    let resp = AppError::UpstreamTimeout("operation timed out".into()).into_response();
+    assert_eq!(resp.status(), StatusCode::GATEWAY_TIMEOUT);
+}
+
+#[test]
+fn sdk_timeouts_map_to_upstream_timeout() {
+    let err: SdkError<GetObjectError, HttpResponse> =
+        SdkError::timeout_error("operation timed out");
+    let mapped = AppError::from_sdk(err, "img.jpg");
+    assert!(matches!(mapped, AppError::UpstreamTimeout(_)));
+}
+
+#[test]
+fn sdk_service_errors_map_by_code() {
+    let denied = GetObjectError::generic(ErrorMetadata::builder().code("AccessDenied").build());
+    let err = SdkError::service_error(
+        denied,
+        HttpResponse::new(403.try_into().unwrap(), SdkBody::empty()),
+    );
+    assert!(matches!(
+        AppError::from_sdk(err, "img.jpg"),
+        AppError::AccessDenied(key) if key == "img.jpg"
+    ));
+}
+
+#[test]
+fn s3_responses_map_to_statuses() {
+    let cases = [
+        (404, Some("NoSuchKey"), StatusCode::NOT_FOUND),
+        (404, None, StatusCode::NOT_FOUND),
+        (404, Some("NoSuchBucket"), StatusCode::INTERNAL_SERVER_ERROR),
+        (403, Some("AccessDenied"), StatusCode::FORBIDDEN),
+        (403, None, StatusCode::FORBIDDEN),
+        (400, Some("KeyTooLongError"), StatusCode::BAD_REQUEST),
+        (416, Some("InvalidRange"), StatusCode::RANGE_NOT_SATISFIABLE),
+        (412, None, StatusCode::PRECONDITION_FAILED),
+        (304, None, StatusCode::NOT_MODIFIED),
+        (503, Some("SlowDown"), StatusCode::TOO_MANY_REQUESTS),
+        (
+            503,
+            Some("ServiceUnavailable"),
+            StatusCode::SERVICE_UNAVAILABLE,
+        ),
+        (500, Some("InternalError"), StatusCode::BAD_GATEWAY),
+        (
+            400,
+            Some("AuthorizationHeaderMalformed"),
+            StatusCode::INTERNAL_SERVER_ERROR,
+        ),
+    ];
+
+    for (status, code, expected) in cases {
+        let resp = AppError::from_s3_response(status, code, "img.jpg", "boom").into_response();
+        assert_eq!(resp.status(), expected, "{status} {code:?}");
+    }
+}
+
+#[test]
+fn slow_down_passes_on_retry_after() {
+    let resp =
+        AppError::from_s3_response(503, Some("SlowDown"), "img.jpg", "slow down").into_response();
+    assert_eq!(resp.headers()[header::RETRY_AFTER], "1");
+}
+
+#[test]
+fn internal_is_500() {
+    let resp = AppError::Internal("bad header".into()).into_response();
+    assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+}
+
+#[test]
+fn circuit_open_is_503_with_retry_after() {
+    let resp = AppError::CircuitOpen {
+        bucket: "photos".into(),
+        retry_after_secs: 12,
+    }
+    .into_response();
+    assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(resp.headers()[header::RETRY_AFTER], "12");
+}
+
 #[test]
 fn s3_error_is_500() {
     let resp = AppError::S3Error("boom".into()).into_response();
     assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
 }
+
+mod rendering {
+    use std::sync::Arc;
+
+    use axum::Router;
+    use axum::body::Body;
+    use axum::extract::Request;
+    use axum::middleware;
+    use axum::routing::get;
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::client_ip::TrustedProxies;
+    use crate::config::AppConfig;
+    use crate::request_id::assign_request_id;
+
+    fn app(pages: ErrorPages) -> Router {
+        Router::new()
+            .route(
+                "/photos/missing.jpg",
+                get(|| async { AppError::ObjectNotFound("missing.jpg".into()) }),
+            )
+            .route(
+                "/photos/broken.jpg",
+                get(|| async { AppError::BadGateway("connection reset by minio-3".into()) }),
+            )
+            .layer(middleware::from_fn_with_state(
+                Arc::new(pages),
+                render_errors,
+            ))
+            .layer(middleware::from_fn_with_state(
+                Arc::new(TrustedProxies::default()),
+                assign_request_id,
+            ))
+    }
+
+    fn pages(yaml: &str) -> ErrorPages {
+        let config: AppConfig = serde_yaml::from_str(yaml).unwrap();
+        ErrorPages::from_config(&config).unwrap()
+    }
+
+    async fn get_page(app: Router, uri: &str, accept: &str) -> (Response, String) {
+        let resp = app
+            .oneshot(
+                Request::builder()
+                    .uri(uri)
+                    .header(header::ACCEPT, accept)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let (parts, body) = resp.into_parts();
+        let body = body.collect().await.unwrap().to_bytes();
+        (
+            Response::from_parts(parts, Body::empty()),
+            String::from_utf8(body.to_vec()).unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn api_clients_get_problem_json() {
+        let (resp, body) = get_page(app(ErrorPages::default()), "/photos/missing.jpg", "*/*").await;
+
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        assert_eq!(
+            resp.headers()[header::CONTENT_TYPE],
+            "application/problem+json"
+        );
+        let problem: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(problem["status"], 404);
+        assert_eq!(problem["title"], "Not Found");
+        assert_eq!(problem["detail"], "object not found: missing.jpg");
+        assert_eq!(problem["instance"], "/photos/missing.jpg");
+        assert_eq!(
+            problem["request_id"],
+            resp.headers()["x-request-id"].to_str().unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn upstream_details_are_hidden_unless_debugging() {
+        let (resp, body) = get_page(app(ErrorPages::default()), "/photos/broken.jpg", "*/*").await;
+        assert_eq!(resp.status(), StatusCode::BAD_GATEWAY);
+        assert!(!body.contains("minio-3"));
+
+        let debug = pages("errors:\n  debug: true\nbuckets: {}");
+        let (_, body) = get_page(app(debug), "/photos/broken.jpg", "*/*").await;
+        assert!(body.contains("minio-3"));
+    }
+
+    #[tokio::test]
+    async fn browsers_get_escaped_html() {
+        let (resp, body) = get_page(
+            app(ErrorPages::default()),
+            "/photos/missing.jpg",
+            "text/html,application/xhtml+xml,*/*;q=0.8",
+        )
+        .await;
+
+        assert_eq!(
+            resp.headers()[header::CONTENT_TYPE],
+            "text/html; charset=utf-8"
+        );
+        assert!(body.contains("<h1>404 Not Found</h1>"));
+        assert!(body.contains("object not found: missing.jpg"));
+    }
+
+    #[tokio::test]
+    async fn bucket_template_overrides_global_one() {
+        let dir = std::env::temp_dir().join(format!("media-server-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("global.html"), "global {{status}}").unwrap();
+        std::fs::write(dir.join("photos.html"), "photos {{status}}: {{detail}}").unwrap();
+
+        let pages = pages(&format!(
+            r#"
+errors:
+  html_template: "{dir}/global.html"
+buckets:
+  photos:
+    endpoint_url: "http://localhost:9000"
+    bucket_name: "photos"
+    access_key:
+        plain: "key"
+    secret_key:
+        plain: "secret"
+    error_template: "{dir}/photos.html"
+"#,
+            dir = dir.display()
+        ));
+        let (_, body) = get_page(app(pages), "/photos/missing.jpg", "text/html").await;
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(body, "photos 404: object not found: missing.jpg");
+    }
+
+    #[test]
+    fn missing_template_is_a_config_error() {
+        let config: AppConfig =
+            serde_yaml::from_str("errors:\n  html_template: /nonexistent/error.html\nbuckets: {}")
+                .unwrap();
+        assert!(ErrorPages::from_config(&config).is_err());
+    }
+}
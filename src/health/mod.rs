@@ -0,0 +1,136 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use serde::Serialize;
+use tokio::sync::Mutex;
+
+use crate::circuit::CircuitState;
+use crate::config::AppConfig;
+use crate::s3::S3Clients;
+use crate::shutdown::Shutdown;
+
+#[derive(Clone, Debug, Serialize)]
+struct Readiness {
+    ready: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    draining: bool,
+    buckets: BTreeMap<String, BucketHealth>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct BucketHealth {
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    circuit: Option<CircuitState>,
+}
+
+struct HealthState {
+    clients: Arc<S3Clients>,
+    cache_for: Duration,
+    require_all_buckets: bool,
+    debug: bool,
+    shutdown: Shutdown,
+    /// Last report, reused for `cache_for`. Holding the lock while checking
+    /// makes concurrent probes share one round of `head_bucket` calls.
+    last: Mutex<Option<(Instant, Readiness)>>,
+}
+
+impl HealthState {
+    async fn readiness(&self) -> Readiness {
+        let mut last = self.last.lock().await;
+        if let Some((checked_at, readiness)) = last.as_ref()
+            && checked_at.elapsed() < self.cache_for
+        {
+            return readiness.clone();
+        }
+
+        let buckets: BTreeMap<_, _> = self
+            .clients
+            .check_buckets()
+            .await
+            .into_iter()
+            .map(|(name, check)| {
+                let error = check.result.err().map(|err| {
+                    tracing::warn!(bucket = name, "Readiness check failed: {err}");
+                    if self.debug {
+                        err.to_string()
+                    } else {
+                        err.into_response()
+                            .status()
+                            .canonical_reason()
+                            .unwrap_or("error")
+                            .to_string()
+                    }
+                });
+                let health = BucketHealth {
+                    ok: error.is_none(),
+                    error,
+                    circuit: check.circuit,
+                };
+                (name, health)
+            })
+            .collect();
+
+        let ready = if self.require_all_buckets {
+            buckets.values().all(|bucket| bucket.ok)
+        } else {
+            buckets.is_empty() || buckets.values().any(|bucket| bucket.ok)
+        };
+
+        let readiness = Readiness {
+            ready,
+            draining: false,
+            buckets,
+        };
+        *last = Some((Instant::now(), readiness.clone()));
+        readiness
+    }
+}
+
+/// `/healthz` (liveness) and `/readyz` (readiness with per-bucket checks,
+/// failing without them once the server is draining).
+pub fn router(clients: Arc<S3Clients>, config: &AppConfig, shutdown: Shutdown) -> Router {
+    let state = Arc::new(HealthState {
+        clients,
+        cache_for: Duration::from_secs(config.health.cache_secs),
+        require_all_buckets: config.health.require_all_buckets,
+        debug: config.errors.debug,
+        shutdown,
+        last: Mutex::new(None),
+    });
+
+    Router::new()
+        .route("/healthz", get(|| async { "ok" }))
+        .route("/readyz", get(readyz))
+        .with_state(state)
+}
+
+async fn readyz(State(state): State<Arc<HealthState>>) -> Response {
+    if state.shutdown.is_draining() {
+        let readiness = Readiness {
+            ready: false,
+            draining: true,
+            buckets: BTreeMap::new(),
+        };
+        return (StatusCode::SERVICE_UNAVAILABLE, Json(readiness)).into_response();
+    }
+
+    let readiness = state.readiness().await;
+    let status = if readiness.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(readiness)).into_response()
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,181 @@
+use axum::body::Body;
+use axum::http::Request;
+use http_body_util::BodyExt;
+use tower::ServiceExt;
+
+use super::*;
+use crate::mock_s3::MockS3;
+
+/// Nothing listens on port 1, so checks fail right away.
+const UNREACHABLE_BUCKET: &str = r#"
+  down:
+    endpoint_url: "http://127.0.0.1:1"
+    bucket_name: "down"
+    access_key:
+        plain: "key"
+    secret_key:
+        plain: "secret"
+    retry:
+      max_attempts: 1
+"#;
+
+fn health_router(yaml: &str) -> Router {
+    health_router_with(yaml, Shutdown::default())
+}
+
+fn health_router_with(yaml: &str, shutdown: Shutdown) -> Router {
+    let config: AppConfig = serde_yaml::from_str(yaml).unwrap();
+    let clients = Arc::new(S3Clients::from_config(&config).unwrap());
+    router(clients, &config, shutdown)
+}
+
+async fn get_json(app: Router, uri: &str) -> (StatusCode, serde_json::Value) {
+    let resp = app
+        .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let status = resp.status();
+    let body = resp.into_body().collect().await.unwrap().to_bytes();
+    (status, serde_json::from_slice(&body).unwrap_or_default())
+}
+
+#[tokio::test]
+async fn liveness_is_always_ok() {
+    let (status, _) = get_json(health_router("buckets: {}"), "/healthz").await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn ready_without_buckets() {
+    let (status, body) = get_json(health_router("buckets: {}"), "/readyz").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["ready"], true);
+}
+
+#[tokio::test]
+async fn failing_bucket_makes_instance_not_ready() {
+    let app = health_router(&format!("buckets:{UNREACHABLE_BUCKET}"));
+    let (status, body) = get_json(app, "/readyz").await;
+
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body["ready"], false);
+    assert_eq!(body["buckets"]["down"]["ok"], false);
+    assert_eq!(body["buckets"]["down"]["error"], "Bad Gateway");
+}
+
+#[tokio::test]
+async fn failing_bucket_can_be_tolerated() {
+    let endpoint = MockS3::new(&[]).serve().await;
+    let app = health_router(&format!(
+        r#"
+health:
+  require_all_buckets: false
+buckets:
+  up:
+    endpoint_url: "{}"
+    bucket_name: "up"
+    access_key:
+        plain: "key"
+    secret_key:
+        plain: "secret"
+{UNREACHABLE_BUCKET}
+    circuit_breaker:
+      consecutive_failures: 1
+"#,
+        endpoint.url
+    ));
+    let (status, body) = get_json(app, "/readyz").await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["ready"], true);
+    assert_eq!(body["buckets"]["up"]["ok"], true);
+    assert_eq!(body["buckets"]["down"]["ok"], false);
+    // Readiness checks do not count towards the circuit breaker.
+    assert_eq!(body["buckets"]["down"]["circuit"], "closed");
+}
+
+#[tokio::test]
+async fn every_bucket_failing_is_not_tolerated() {
+    let app = health_router(&format!(
+        "health:\n  require_all_buckets: false\nbuckets:{UNREACHABLE_BUCKET}"
+    ));
+    let (status, _) = get_json(app, "/readyz").await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+}
+
+#[tokio::test]
+async fn readiness_is_cached() {
+    let config: AppConfig = serde_yaml::from_str(&format!("buckets:{UNREACHABLE_BUCKET}")).unwrap();
+    let state = HealthState {
+        clients: Arc::new(S3Clients::from_config(&config).unwrap()),
+        cache_for: Duration::from_secs(60),
+        require_all_buckets: true,
+        debug: true,
+        shutdown: Shutdown::default(),
+        last: Mutex::new(None),
+    };
+
+    let first = state.readiness().await;
+    let checked_at = state.last.lock().await.as_ref().unwrap().0;
+    let second = state.readiness().await;
+
+    assert_eq!(state.last.lock().await.as_ref().unwrap().0, checked_at);
+    assert_eq!(first.buckets["down"].error, second.buckets["down"].error);
+}
+
+#[tokio::test]
+async fn draining_instance_is_not_ready() {
+    let shutdown = Shutdown::default();
+    shutdown.begin();
+    let app = health_router_with("buckets: {}", shutdown);
+
+    let (status, body) = get_json(app.clone(), "/readyz").await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body["ready"], false);
+    assert_eq!(body["draining"], true);
+
+    let (status, _) = get_json(app, "/healthz").await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn readiness_fails_before_the_listener_closes() {
+    let shutdown = Shutdown::default();
+    let app = health_router_with("buckets: {}", shutdown.clone());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = tokio::spawn(
+        axum::serve(listener, app)
+            .with_graceful_shutdown(shutdown.clone().closed())
+            .into_future(),
+    );
+
+    let drain = tokio::spawn({
+        let shutdown = shutdown.clone();
+        async move { shutdown.drain(Duration::from_millis(200)).await }
+    });
+    tokio::task::yield_now().await;
+
+    let response = raw_get(addr, "/readyz").await;
+    assert!(response.starts_with("HTTP/1.1 503"), "{response}");
+    assert!(response.contains(r#""draining":true"#), "{response}");
+
+    drain.await.unwrap();
+    server.await.unwrap().unwrap();
+    assert!(tokio::net::TcpStream::connect(addr).await.is_err());
+}
+
+async fn raw_get(addr: std::net::SocketAddr, uri: &str) -> String {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(
+            format!("GET {uri} HTTP/1.1\r\nHost: test\r\nConnection: close\r\n\r\n").as_bytes(),
+        )
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    response
+}
@@ -0,0 +1,89 @@
+use eyre::WrapErr;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use tracing::Subscriber;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
+
+use crate::config::{AppConfig, LogFormat, LoggingConfig};
+use crate::telemetry;
+
+/// Installs the global subscriber: log output as configured, plus span
+/// export when a tracer provider is given.
+pub fn init(
+    config: &LoggingConfig,
+    tracer_provider: Option<&SdkTracerProvider>,
+) -> eyre::Result<()> {
+    let rust_log = std::env::var(EnvFilter::DEFAULT_ENV).ok();
+    let filter = filter(&config.level, rust_log.as_deref())?;
+
+    tracing_subscriber::registry()
+        .with(fmt_layer(config).with_filter(filter))
+        .with(
+            tracer_provider
+                .map(|provider| telemetry::layer(provider).with_filter(telemetry::span_filter())),
+        )
+        .try_init()
+        .wrap_err("failed to install the log subscriber")
+}
+
+/// Log filter from `RUST_LOG` when set, from the configured level otherwise.
+fn filter(level: &str, rust_log: Option<&str>) -> eyre::Result<EnvFilter> {
+    match rust_log.filter(|directives| !directives.trim().is_empty()) {
+        Some(directives) => EnvFilter::try_new(directives)
+            .wrap_err_with(|| format!("invalid {}: {directives}", EnvFilter::DEFAULT_ENV)),
+        None => {
+            EnvFilter::try_new(level).wrap_err_with(|| format!("invalid logging.level: {level}"))
+        }
+    }
+}
+
+fn fmt_layer<S>(config: &LoggingConfig) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    let layer = tracing_subscriber::fmt::layer();
+    match (config.format, config.timestamps) {
+        (LogFormat::Full, true) => layer.boxed(),
+        (LogFormat::Full, false) => layer.without_time().boxed(),
+        (LogFormat::Compact, true) => layer.compact().boxed(),
+        (LogFormat::Compact, false) => layer.compact().without_time().boxed(),
+        (LogFormat::Pretty, true) => layer.pretty().boxed(),
+        (LogFormat::Pretty, false) => layer.pretty().without_time().boxed(),
+        (LogFormat::Json, true) => layer.json().boxed(),
+        (LogFormat::Json, false) => layer.json().without_time().boxed(),
+    }
+}
+
+/// Logs what the server is about to serve, and with which optional features.
+pub fn log_startup(config: &AppConfig) {
+    let mut buckets: Vec<_> = config.buckets.iter().collect();
+    buckets.sort_by_key(|(name, _)| name.as_str());
+    tracing::info!(
+        version = env!("CARGO_PKG_VERSION"),
+        buckets = buckets.len(),
+        cache = config.cache.is_some(),
+        admin = config.admin.is_some(),
+        metrics = config
+            .metrics
+            .as_ref()
+            .map(|metrics| metrics.listen.as_str()),
+        otlp = config.otlp.as_ref().map(|otlp| otlp.endpoint()),
+        access_log = config.access_log.is_some(),
+        "Starting media-server"
+    );
+    for (name, bc) in buckets {
+        tracing::info!(
+            bucket = name,
+            bucket_name = bc.bucket_name,
+            endpoint = bc.endpoint_url,
+            mode = bc.mode(),
+            cache = bc.proxy && bc.cache && config.cache.is_some(),
+            "Configured bucket"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests;
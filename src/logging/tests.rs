@@ -0,0 +1,37 @@
+use super::*;
+
+#[test]
+fn configured_level_applies_without_rust_log() {
+    let filter = filter("warn,media_server=debug", None).unwrap();
+    assert_eq!(filter.to_string(), "media_server=debug,warn");
+}
+
+#[test]
+fn rust_log_overrides_configured_level() {
+    let filter = filter("warn", Some("trace")).unwrap();
+    assert_eq!(filter.to_string(), "trace");
+}
+
+#[test]
+fn empty_rust_log_is_ignored() {
+    let filter = filter("warn", Some(" ")).unwrap();
+    assert_eq!(filter.to_string(), "warn");
+}
+
+#[test]
+fn invalid_level_is_an_error() {
+    assert!(filter("media_server=loud", None).is_err());
+}
+
+#[test]
+fn logging_section_defaults() {
+    let config: AppConfig = serde_yaml::from_str("buckets: {}").unwrap();
+    assert_eq!(config.logging.format, LogFormat::Full);
+    assert_eq!(config.logging.level, "info");
+    assert!(config.logging.timestamps);
+
+    let config: AppConfig =
+        serde_yaml::from_str("logging:\n  format: json\n  timestamps: false\nbuckets: {}").unwrap();
+    assert_eq!(config.logging.format, LogFormat::Json);
+    assert!(!config.logging.timestamps);
+}
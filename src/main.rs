@@ -1,34 +1,101 @@
+mod access;
+mod access_log;
+mod admin;
 mod app;
+mod cache;
+mod circuit;
+mod cli;
+mod client_ip;
+mod concurrency;
 mod config;
+mod cors;
 mod error;
+mod health;
+mod logging;
+#[cfg(test)]
+mod mock_s3;
+mod prometheus;
+mod rate_limit;
+mod reload;
+mod request_id;
 mod routes;
 mod s3;
+mod shutdown;
+mod telemetry;
+mod throttle;
 
-use tracing_subscriber::EnvFilter;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use clap::Parser;
+
+use crate::cli::{Cli, Command};
 
 #[tokio::main]
 async fn main() -> eyre::Result<()> {
-    println!("Hello, world!");
-
     color_eyre::install()?;
 
-    tracing_subscriber::fmt()
-        .with_env_filter(EnvFilter::from_default_env())
-        .init();
+    let cli = Cli::parse();
+    let config_path = cli.config_path();
+    match cli.command.unwrap_or(Command::Serve { listen: None }) {
+        Command::Serve { listen } => serve(config_path, listen).await,
+        command => cli::run(command, &config_path).await,
+    }
+}
+
+async fn serve(config_path: PathBuf, listen: Option<String>) -> eyre::Result<()> {
+    let config = config::AppConfig::load_from(&config_path)?;
+
+    let tracer_provider = config
+        .otlp
+        .as_ref()
+        .map(telemetry::tracer_provider)
+        .transpose()?;
+    logging::init(&config.logging, tracer_provider.as_ref())?;
+    logging::log_startup(&config);
 
-    ctrlc::set_handler(|| {
-        tracing::info!("Received termination signal, exiting...");
-        std::process::exit(0);
-    })?;
+    let shutdown = shutdown::Shutdown::default();
+    let exiting_provider = tracer_provider.clone();
+    shutdown::watch_signals(
+        shutdown.clone(),
+        Duration::from_secs(config.shutdown.readiness_grace_secs),
+        Duration::from_secs(config.shutdown.drain_timeout_secs),
+        move || {
+            // Export the spans of the requests cut short.
+            if let Some(provider) = exiting_provider {
+                let _ = provider.shutdown();
+            }
+        },
+    )?;
 
-    let config = config::AppConfig::load()?;
-    let listen = config.listen.clone();
-    let router = app::build_router(&config);
+    if let Some(metrics) = &config.metrics {
+        prometheus::serve(metrics).await?;
+    }
+    let listen = listen.unwrap_or_else(|| config.listen.clone());
+    let reload = config.reload.clone();
+    let reloader = Arc::new(reload::Reloader::new(
+        config,
+        config_path,
+        shutdown.clone(),
+    )?);
+    reload::watch(reloader.clone(), &reload)?;
+    let router = reloader.router();
 
     let listener = tokio::net::TcpListener::bind(&listen).await?;
     tracing::info!("Listening on {listen}");
 
-    axum::serve(listener, router).await?;
+    axum::serve(
+        listener,
+        router.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(shutdown.closed())
+    .await?;
+    tracing::info!("Drained all connections, exiting");
 
+    if let Some(provider) = tracer_provider {
+        provider.shutdown()?;
+    }
     Ok(())
 }
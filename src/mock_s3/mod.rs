@@ -0,0 +1,190 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use axum::Router;
+use axum::extract::Query;
+use axum::http::{HeaderMap, Method, StatusCode, Uri, header};
+use axum::response::{IntoResponse, Response};
+
+/// A fake S3 endpoint for tests, serving path-style requests for any bucket.
+/// Listing returns the objects under the requested prefix and every object
+/// reads as `hello` with the ETag `"mock"`, honouring single byte ranges and
+/// `If-Match`/`If-None-Match`; reads of other keys are answered with `missing`.
+pub struct MockS3 {
+    objects: Vec<String>,
+    missing: StatusCode,
+    delay: Duration,
+}
+
+impl MockS3 {
+    pub fn new(objects: &[&str]) -> Self {
+        Self {
+            objects: objects.iter().map(|key| key.to_string()).collect(),
+            missing: StatusCode::NOT_FOUND,
+            delay: Duration::ZERO,
+        }
+    }
+
+    /// Holds every object read for `delay` before answering.
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Answers reads of missing keys with `status`, as S3 does with 403 for
+    /// credentials without `s3:ListBucket`.
+    pub fn missing_status(mut self, status: StatusCode) -> Self {
+        self.missing = status;
+        self
+    }
+
+    pub async fn serve(self) -> MockEndpoint {
+        let state = Arc::new(State {
+            objects: self.objects,
+            missing: self.missing,
+            delay: self.delay,
+            downloads: AtomicUsize::new(0),
+        });
+        let app =
+            Router::new().fallback({
+                let state = state.clone();
+                move |method: Method,
+                      uri: Uri,
+                      headers: HeaderMap,
+                      Query(query): Query<ListQuery>| async move {
+                    state.respond(&method, &uri, &headers, &query).await
+                }
+            });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(axum::serve(listener, app).into_future());
+
+        MockEndpoint {
+            url: format!("http://{addr}"),
+            state,
+        }
+    }
+}
+
+pub struct MockEndpoint {
+    pub url: String,
+    state: Arc<State>,
+}
+
+impl MockEndpoint {
+    /// Objects read with `GET` so far.
+    pub fn downloads(&self) -> usize {
+        self.state.downloads.load(Ordering::SeqCst)
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct ListQuery {
+    #[serde(default)]
+    prefix: String,
+}
+
+struct State {
+    objects: Vec<String>,
+    missing: StatusCode,
+    delay: Duration,
+    downloads: AtomicUsize,
+}
+
+impl State {
+    async fn respond(
+        &self,
+        method: &Method,
+        uri: &Uri,
+        headers: &HeaderMap,
+        query: &ListQuery,
+    ) -> Response {
+        let key = uri
+            .path()
+            .trim_start_matches('/')
+            .split_once('/')
+            .map(|(_, key)| key)
+            .filter(|key| !key.is_empty());
+
+        match key {
+            None if method == Method::GET => self.list(&query.prefix).into_response(),
+            None => StatusCode::OK.into_response(),
+            Some(key) if !self.objects.iter().any(|object| object == key) => {
+                self.missing.into_response()
+            }
+            Some(_) => {
+                if method == Method::GET {
+                    self.downloads.fetch_add(1, Ordering::SeqCst);
+                    tokio::time::sleep(self.delay).await;
+                }
+                read(headers)
+            }
+        }
+    }
+
+    fn list(&self, prefix: &str) -> String {
+        let objects: Vec<_> = self
+            .objects
+            .iter()
+            .filter(|key| key.starts_with(prefix))
+            .collect();
+        let contents: String = objects
+            .iter()
+            .map(|key| format!("<Contents><Key>{key}</Key><Size>5</Size></Contents>"))
+            .collect();
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?><ListBucketResult><Name>bucket</Name><Prefix>{prefix}</Prefix><KeyCount>{}</KeyCount><MaxKeys>1000</MaxKeys><IsTruncated>false</IsTruncated>{contents}</ListBucketResult>"#,
+            objects.len()
+        )
+    }
+}
+
+const BODY: &str = "hello";
+const ETAG: &str = "\"mock\"";
+
+fn read(headers: &HeaderMap) -> Response {
+    let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+
+    if header(header::IF_MATCH).is_some_and(|etag| etag != ETAG && etag != "*") {
+        return error(StatusCode::PRECONDITION_FAILED, "PreconditionFailed");
+    }
+    if header(header::IF_NONE_MATCH).is_some_and(|etag| etag == ETAG || etag == "*") {
+        return (StatusCode::NOT_MODIFIED, [(header::ETAG, ETAG)]).into_response();
+    }
+
+    let Some(range) = header(header::RANGE) else {
+        return ([(header::ETAG, ETAG)], BODY).into_response();
+    };
+    let (start, end) = range
+        .strip_prefix("bytes=")
+        .and_then(|range| range.split_once('-'))
+        .expect("a single byte range");
+    let start: usize = start.parse().unwrap();
+    let end = end
+        .parse()
+        .map_or(BODY.len() - 1, |end: usize| end.min(BODY.len() - 1));
+    if start > end {
+        return error(StatusCode::RANGE_NOT_SATISFIABLE, "InvalidRange");
+    }
+    (
+        StatusCode::PARTIAL_CONTENT,
+        [
+            (header::ETAG, ETAG.to_string()),
+            (
+                header::CONTENT_RANGE,
+                format!("bytes {start}-{end}/{}", BODY.len()),
+            ),
+        ],
+        &BODY[start..=end],
+    )
+        .into_response()
+}
+
+fn error(status: StatusCode, code: &str) -> Response {
+    (
+        status,
+        format!(r#"<?xml version="1.0" encoding="UTF-8"?><Error><Code>{code}</Code></Error>"#),
+    )
+        .into_response()
+}
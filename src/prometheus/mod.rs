@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+use axum::Router;
+use axum::body::{Body, Bytes};
+use axum::extract::{Request, State};
+use axum::middleware::Next;
+use axum::response::Response;
+use axum::routing::get;
+use eyre::WrapErr;
+use http_body::{Frame, SizeHint};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+
+use crate::config::{AppConfig, MetricsConfig};
+
+/// Buckets of the latency histograms, in seconds.
+const LATENCY_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0,
+];
+
+/// Installs the Prometheus recorder and serves its output on a separate
+/// listener in the background.
+pub async fn serve(config: &MetricsConfig) -> eyre::Result<()> {
+    let handle = PrometheusBuilder::new()
+        .set_buckets_for_metric(Matcher::Suffix("_seconds".into()), LATENCY_BUCKETS)?
+        .install_recorder()
+        .wrap_err("failed to install the Prometheus recorder")?;
+
+    let listener = tokio::net::TcpListener::bind(&config.listen)
+        .await
+        .wrap_err_with(|| format!("failed to bind metrics listener {}", config.listen))?;
+    tracing::info!("Serving metrics on {}{}", config.listen, config.path);
+
+    let router = router(&config.path, handle);
+    tokio::spawn(async move {
+        if let Err(err) = axum::serve(listener, router).await {
+            tracing::error!("Metrics listener failed: {err}");
+        }
+    });
+    Ok(())
+}
+
+fn router(path: &str, handle: PrometheusHandle) -> Router {
+    Router::new().route(path, get(move || async move { handle.render() }))
+}
+
+/// Labels for file requests: configured buckets and how they serve objects.
+/// Unknown config names share one label to keep cardinality bounded.
+pub struct RequestLabels {
+    modes: HashMap<String, &'static str>,
+}
+
+impl RequestLabels {
+    pub fn from_config(config: &AppConfig) -> Self {
+        let modes = config
+            .buckets
+            .iter()
+            .map(|(name, bc)| (name.clone(), bc.mode()))
+            .collect();
+        Self { modes }
+    }
+
+    fn labels(&self, path: &str) -> (String, &'static str) {
+        let config_name = path.trim_start_matches('/').split('/').next().unwrap_or("");
+        match self.modes.get(config_name) {
+            Some(mode) => (config_name.to_string(), *mode),
+            None => ("unknown".to_string(), "unknown"),
+        }
+    }
+}
+
+/// Counts file requests and times them until their response body has been
+/// sent or dropped.
+pub async fn record_requests(
+    State(labels): State<Arc<RequestLabels>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let (bucket, mode) = labels.labels(request.uri().path());
+    let started = Instant::now();
+
+    let response = next.run(request).await;
+
+    metrics::counter!(
+        "media_server_requests_total",
+        "bucket" => bucket.clone(),
+        "mode" => mode,
+        "status" => response.status().as_str().to_string(),
+    )
+    .increment(1);
+    let duration = metrics::histogram!(
+        "media_server_request_duration_seconds",
+        "bucket" => bucket,
+        "mode" => mode,
+    );
+
+    response.map(|body| {
+        Body::new(TimedBody {
+            inner: body,
+            started,
+            duration,
+        })
+    })
+}
+
+/// Records the duration of a request when its response body is dropped.
+struct TimedBody {
+    inner: Body,
+    started: Instant,
+    duration: metrics::Histogram,
+}
+
+impl http_body::Body for TimedBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, axum::Error>>> {
+        Pin::new(&mut self.inner).poll_frame(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl Drop for TimedBody {
+    fn drop(&mut self) {
+        self.duration.record(self.started.elapsed());
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,91 @@
+use axum::body::Body;
+use axum::http::StatusCode;
+use axum::middleware;
+use http_body_util::BodyExt;
+use tower::ServiceExt;
+
+use super::*;
+
+const CONFIG: &str = r#"
+buckets:
+  photos:
+    endpoint_url: "http://localhost:9000"
+    bucket_name: "photos"
+    access_key:
+        plain: "key"
+    secret_key:
+        plain: "secret"
+    proxy: true
+"#;
+
+fn request(uri: &str) -> Request {
+    Request::builder().uri(uri).body(Body::empty()).unwrap()
+}
+
+async fn body_text(response: Response) -> String {
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    String::from_utf8(body.to_vec()).unwrap()
+}
+
+#[test]
+fn unknown_configs_share_a_label() {
+    let config: AppConfig = serde_yaml::from_str(CONFIG).unwrap();
+    let labels = RequestLabels::from_config(&config);
+
+    assert_eq!(
+        labels.labels("/photos/2026/a.jpg"),
+        ("photos".to_string(), "proxy")
+    );
+    assert_eq!(
+        labels.labels("/random-1234/a.jpg"),
+        ("unknown".to_string(), "unknown")
+    );
+}
+
+#[tokio::test]
+async fn requests_are_counted_and_rendered() {
+    let recorder = PrometheusBuilder::new().build_recorder();
+    let handle = recorder.handle();
+
+    let config: AppConfig = serde_yaml::from_str(CONFIG).unwrap();
+    let app = Router::new()
+        .route(
+            "/{config_name}/{*file_path}",
+            get(|| async { StatusCode::NOT_FOUND }),
+        )
+        .layer(middleware::from_fn_with_state(
+            Arc::new(RequestLabels::from_config(&config)),
+            record_requests,
+        ));
+
+    // The future is polled to completion inside the local recorder's scope.
+    let response = metrics::with_local_recorder(&recorder, || {
+        futures::executor::block_on(app.oneshot(request("/photos/a.jpg")))
+    })
+    .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let render = || async {
+        body_text(
+            router("/metrics", handle.clone())
+                .oneshot(request("/metrics"))
+                .await
+                .unwrap(),
+        )
+        .await
+    };
+    let duration_count =
+        r#"media_server_request_duration_seconds_count{bucket="photos",mode="proxy"} 1"#;
+
+    let rendered = render().await;
+    assert!(
+        rendered.contains(
+            r#"media_server_requests_total{bucket="photos",mode="proxy",status="404"} 1"#
+        )
+    );
+    // Requests are timed until their body is done with.
+    assert!(!rendered.contains(duration_count));
+
+    drop(response);
+    assert!(render().await.contains(duration_count));
+}
@@ -0,0 +1,223 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use eyre::{WrapErr, bail};
+
+use crate::config::{AppConfig, RateLimitConfig, RateLimitKey};
+use crate::error::AppError;
+
+const MAX_TRACKED_KEYS: u64 = 100_000;
+const MAX_IDLE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Slowest accepted rate, one request every ~17 minutes; slower rates would
+/// make refill times overflow.
+pub const MIN_REQUESTS_PER_SEC: f64 = 0.001;
+
+struct TokenBucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    /// Takes a token, or returns how long until one is available.
+    fn take(&mut self, rate: f64, burst: f64, now: Instant) -> Result<(), Duration> {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(burst);
+        self.updated = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / rate))
+        }
+    }
+}
+
+struct Limiter {
+    scope: String,
+    rate: f64,
+    burst: f64,
+    key: RateLimitKey,
+    /// Keys idle long enough to have refilled completely are dropped, since a
+    /// full bucket is the same as no bucket, and keys idle for a week in any
+    /// case.
+    buckets: moka::sync::Cache<String, Arc<Mutex<TokenBucket>>>,
+}
+
+impl Limiter {
+    fn from_config(scope: &str, config: &RateLimitConfig) -> eyre::Result<Self> {
+        if !config.requests_per_sec.is_finite() || config.requests_per_sec < MIN_REQUESTS_PER_SEC {
+            bail!("`requests_per_sec` must be a number of at least {MIN_REQUESTS_PER_SEC}");
+        }
+        let rate = config.requests_per_sec;
+        let burst = f64::from(config.burst.unwrap_or(rate.ceil() as u32).max(1));
+
+        Ok(Self {
+            scope: scope.to_string(),
+            rate,
+            burst,
+            key: config.key,
+            buckets: moka::sync::Cache::builder()
+                .max_capacity(MAX_TRACKED_KEYS)
+                .time_to_idle(
+                    Duration::try_from_secs_f64(burst / rate)
+                        .unwrap_or(MAX_IDLE)
+                        .clamp(Duration::from_secs(1), MAX_IDLE),
+                )
+                .build(),
+        })
+    }
+
+    fn check(
+        &self,
+        principal: Option<&str>,
+        client_ip: Option<IpAddr>,
+        now: Instant,
+    ) -> Result<(), Duration> {
+        let client_ip = || client_ip.map_or_else(|| "unknown".into(), |ip| format!("ip:{ip}"));
+        let key = match (self.key, principal) {
+            (RateLimitKey::Bucket, _) => String::new(),
+            (RateLimitKey::Principal, Some(principal)) => format!("principal:{principal}"),
+            (RateLimitKey::Principal, None) | (RateLimitKey::ClientIp, _) => client_ip(),
+        };
+
+        let bucket = self.buckets.get_with(key, || {
+            Arc::new(Mutex::new(TokenBucket {
+                tokens: self.burst,
+                updated: now,
+            }))
+        });
+        let result = bucket
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .take(self.rate, self.burst, now);
+
+        metrics::gauge!("media_server_rate_limit_tracked_keys", "scope" => self.scope.clone())
+            .set(self.buckets.entry_count() as f64);
+        if result.is_err() {
+            metrics::counter!("media_server_rate_limited_total", "scope" => self.scope.clone())
+                .increment(1);
+        }
+
+        result
+    }
+}
+
+/// Builds a rate limit to check its config.
+pub fn check_limit(config: &RateLimitConfig) -> eyre::Result<()> {
+    Limiter::from_config("", config).map(drop)
+}
+
+/// Global and per-bucket request rate limits.
+#[derive(Default)]
+pub struct RateLimits {
+    global: Option<Arc<Limiter>>,
+    buckets: HashMap<String, Arc<Limiter>>,
+}
+
+impl RateLimits {
+    pub fn from_config(config: &AppConfig) -> eyre::Result<Self> {
+        Self::build(config, None)
+    }
+
+    /// Builds the limits of `config`, keeping the tokens counted by `self`,
+    /// built from `previous`, for each limit whose settings did not change.
+    pub fn reconfigured(&self, previous: &AppConfig, config: &AppConfig) -> eyre::Result<Self> {
+        Self::build(config, Some((previous, self)))
+    }
+
+    fn build(config: &AppConfig, previous: Option<(&AppConfig, &Self)>) -> eyre::Result<Self> {
+        let global = match previous {
+            Some((old, limits)) if old.rate_limit == config.rate_limit => limits.global.clone(),
+            _ => config
+                .rate_limit
+                .as_ref()
+                .map(|rl| Limiter::from_config("global", rl))
+                .transpose()
+                .wrap_err("invalid global rate limit")?
+                .map(Arc::new),
+        };
+
+        let mut buckets = HashMap::new();
+        for (name, bc) in &config.buckets {
+            let Some(rl) = &bc.rate_limit else {
+                continue;
+            };
+            let unchanged = previous.and_then(|(old, limits)| {
+                let same = old
+                    .buckets
+                    .get(name)
+                    .and_then(|old| old.rate_limit.as_ref())
+                    == Some(rl);
+                limits.buckets.get(name).filter(|_| same)
+            });
+            let limiter = match unchanged {
+                Some(limiter) => limiter.clone(),
+                None => Arc::new(
+                    Limiter::from_config(name, rl)
+                        .wrap_err_with(|| format!("invalid rate limit for bucket \"{name}\""))?,
+                ),
+            };
+            buckets.insert(name.clone(), limiter);
+        }
+
+        Ok(Self { global, buckets })
+    }
+
+    /// Applies the limits counting requests by client address or for the
+    /// whole bucket. Runs before authentication, so that failed attempts are
+    /// limited too.
+    pub fn check_client(
+        &self,
+        config_name: &str,
+        client_ip: Option<IpAddr>,
+    ) -> Result<(), AppError> {
+        self.check_keyed(config_name, None, client_ip, |key| {
+            key != RateLimitKey::Principal
+        })
+    }
+
+    /// Applies the limits counting requests by principal, once authentication
+    /// has succeeded or failed. Anonymous and failed requests count against
+    /// the client address.
+    pub fn check_principal(
+        &self,
+        config_name: &str,
+        principal: Option<&str>,
+        client_ip: Option<IpAddr>,
+    ) -> Result<(), AppError> {
+        self.check_keyed(config_name, principal, client_ip, |key| {
+            key == RateLimitKey::Principal
+        })
+    }
+
+    fn check_keyed(
+        &self,
+        config_name: &str,
+        principal: Option<&str>,
+        client_ip: Option<IpAddr>,
+        keyed: impl Fn(RateLimitKey) -> bool,
+    ) -> Result<(), AppError> {
+        let now = Instant::now();
+
+        for limiter in self
+            .buckets
+            .get(config_name)
+            .into_iter()
+            .chain(&self.global)
+            .filter(|limiter| keyed(limiter.key))
+        {
+            limiter
+                .check(principal, client_ip, now)
+                .map_err(|wait| AppError::RateLimited(wait.as_secs_f64().ceil() as u64))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,180 @@
+use std::net::Ipv4Addr;
+
+use super::*;
+
+const CLIENT: Option<IpAddr> = Some(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 7)));
+const OTHER_CLIENT: Option<IpAddr> = Some(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 8)));
+
+fn limiter(requests_per_sec: f64, burst: u32, key: RateLimitKey) -> Limiter {
+    Limiter::from_config(
+        "test",
+        &RateLimitConfig {
+            requests_per_sec,
+            burst: Some(burst),
+            key,
+        },
+    )
+    .unwrap()
+}
+
+#[test]
+fn burst_then_refill() {
+    let limiter = limiter(2.0, 3, RateLimitKey::ClientIp);
+    let start = Instant::now();
+
+    for _ in 0..3 {
+        assert!(limiter.check(None, CLIENT, start).is_ok());
+    }
+    let wait = limiter.check(None, CLIENT, start).unwrap_err();
+    assert_eq!(wait, Duration::from_millis(500));
+
+    assert!(
+        limiter
+            .check(None, CLIENT, start + Duration::from_millis(500))
+            .is_ok()
+    );
+}
+
+#[test]
+fn client_ips_are_limited_separately() {
+    let limiter = limiter(1.0, 1, RateLimitKey::ClientIp);
+    let now = Instant::now();
+
+    assert!(limiter.check(None, CLIENT, now).is_ok());
+    assert!(limiter.check(None, CLIENT, now).is_err());
+    assert!(limiter.check(None, OTHER_CLIENT, now).is_ok());
+}
+
+#[test]
+fn principals_share_limit_across_addresses() {
+    let limiter = limiter(1.0, 1, RateLimitKey::Principal);
+    let now = Instant::now();
+
+    assert!(limiter.check(Some("alice"), CLIENT, now).is_ok());
+    assert!(limiter.check(Some("alice"), OTHER_CLIENT, now).is_err());
+    assert!(limiter.check(Some("bob"), CLIENT, now).is_ok());
+    // Anonymous requests fall back to the client address.
+    assert!(limiter.check(None, CLIENT, now).is_ok());
+}
+
+#[test]
+fn bucket_key_limits_everyone_together() {
+    let limiter = limiter(1.0, 1, RateLimitKey::Bucket);
+    let now = Instant::now();
+
+    assert!(limiter.check(None, CLIENT, now).is_ok());
+    assert!(limiter.check(None, OTHER_CLIENT, now).is_err());
+}
+
+#[test]
+fn non_positive_rate_is_a_config_error() {
+    let config = RateLimitConfig {
+        requests_per_sec: 0.0,
+        burst: None,
+        key: RateLimitKey::ClientIp,
+    };
+    assert!(Limiter::from_config("test", &config).is_err());
+}
+
+#[test]
+fn tiny_rate_is_a_config_error() {
+    let config = RateLimitConfig {
+        requests_per_sec: 1e-20,
+        burst: None,
+        key: RateLimitKey::ClientIp,
+    };
+    assert!(Limiter::from_config("test", &config).is_err());
+}
+
+#[test]
+fn slowest_rate_does_not_overflow() {
+    let now = Instant::now();
+    let large_burst = limiter(MIN_REQUESTS_PER_SEC, u32::MAX, RateLimitKey::ClientIp);
+    assert!(large_burst.check(None, CLIENT, now).is_ok());
+
+    let single = limiter(MIN_REQUESTS_PER_SEC, 1, RateLimitKey::ClientIp);
+    assert!(single.check(None, CLIENT, now).is_ok());
+    assert_eq!(
+        single.check(None, CLIENT, now).unwrap_err(),
+        Duration::from_secs(1000)
+    );
+}
+
+#[test]
+fn global_and_bucket_limits_both_apply() {
+    let config: AppConfig = serde_yaml::from_str(
+        r#"
+rate_limit:
+  requests_per_sec: 100
+  burst: 2
+buckets:
+  photos:
+    endpoint_url: "http://localhost:9000"
+    bucket_name: "photos"
+    access_key:
+        plain: "key"
+    secret_key:
+        plain: "secret"
+    rate_limit:
+      requests_per_sec: 1
+      burst: 1
+  docs:
+    endpoint_url: "http://localhost:9000"
+    bucket_name: "docs"
+    access_key:
+        plain: "key"
+    secret_key:
+        plain: "secret"
+"#,
+    )
+    .unwrap();
+    let limits = RateLimits::from_config(&config).unwrap();
+
+    let check = |config_name| {
+        limits.check_client(config_name, CLIENT)?;
+        limits.check_principal(config_name, None, CLIENT)
+    };
+
+    assert!(check("photos").is_ok());
+    assert!(matches!(check("photos"), Err(AppError::RateLimited(1))));
+
+    assert!(check("docs").is_ok());
+    // The global burst of 2 is now used up as well.
+    assert!(matches!(check("docs"), Err(AppError::RateLimited(_))));
+}
+
+#[test]
+fn client_limits_apply_before_principal_limits() {
+    let config: AppConfig = serde_yaml::from_str(
+        r#"
+buckets:
+  photos:
+    endpoint_url: "http://localhost:9000"
+    bucket_name: "photos"
+    access_key:
+        plain: "key"
+    secret_key:
+        plain: "secret"
+    rate_limit:
+      requests_per_sec: 1
+      burst: 1
+      key: principal
+"#,
+    )
+    .unwrap();
+    let limits = RateLimits::from_config(&config).unwrap();
+
+    // Principal limits are left to `check_principal`.
+    assert!(limits.check_client("photos", CLIENT).is_ok());
+    assert!(limits.check_client("photos", CLIENT).is_ok());
+    assert!(
+        limits
+            .check_principal("photos", Some("alice"), CLIENT)
+            .is_ok()
+    );
+    assert!(
+        limits
+            .check_principal("photos", Some("alice"), CLIENT)
+            .is_err()
+    );
+}
@@ -0,0 +1,204 @@
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use arc_swap::{ArcSwap, ArcSwapOption};
+use axum::Router;
+use axum::extract::Request;
+use tokio::signal::unix::{SignalKind, signal};
+use tokio::time::Interval;
+use tower::ServiceExt;
+
+use crate::access_log::AccessLog;
+use crate::app::{Components, build_router};
+use crate::config::{
+    AppConfig, LoggingConfig, MetricsConfig, OtlpConfig, ReloadConfig, ShutdownConfig,
+};
+use crate::shutdown::Shutdown;
+
+/// Holds the router built from the current config and replaces it when the
+/// config file changes. Requests keep the router they started on, so in-flight
+/// requests finish on the old state.
+pub struct Reloader {
+    path: PathBuf,
+    startup: StartupSettings,
+    shutdown: Shutdown,
+    router: ArcSwap<Router>,
+    access_log: ArcSwapOption<AccessLog>,
+    /// The config of the current router and its components, whose state the
+    /// next reload keeps where settings did not change.
+    current: Mutex<(AppConfig, Components)>,
+}
+
+impl Reloader {
+    pub fn new(config: AppConfig, path: PathBuf, shutdown: Shutdown) -> eyre::Result<Self> {
+        let access_log = AccessLog::from_config(&config)?.map(Arc::new);
+        let components = Components::from_config(&config)?;
+        let router = build_router(&config, &components, access_log.clone(), shutdown.clone())?;
+        Ok(Self {
+            path,
+            startup: StartupSettings::from_config(&config),
+            shutdown,
+            router: ArcSwap::from_pointee(router),
+            access_log: ArcSwapOption::new(access_log),
+            current: Mutex::new((config, components)),
+        })
+    }
+
+    /// A router handing each request to the router current when it arrives.
+    pub fn router(self: &Arc<Self>) -> Router {
+        let reloader = self.clone();
+        Router::new().fallback_service(tower::service_fn(move |request: Request| {
+            Router::clone(&reloader.router.load()).oneshot(request)
+        }))
+    }
+
+    /// Reads and validates the config file and swaps in a router built from
+    /// it. The current router stays in place on any error.
+    pub async fn reload(&self) -> eyre::Result<()> {
+        let path = self.path.clone();
+        let config = tokio::task::spawn_blocking(move || AppConfig::load_from(&path)).await??;
+        let access_log = AccessLog::from_config(&config)?.map(Arc::new);
+
+        let mut current = self.current.lock().unwrap_or_else(PoisonError::into_inner);
+        let (previous, components) = &*current;
+        let components = components.reconfigured(previous, &config)?;
+        let router = build_router(
+            &config,
+            &components,
+            access_log.clone(),
+            self.shutdown.clone(),
+        )?;
+
+        let changed = self.startup.changes(&StartupSettings::from_config(&config));
+        if !changed.is_empty() {
+            tracing::warn!(
+                settings = changed.join(", "),
+                "Changes to these settings take effect on restart"
+            );
+        }
+        self.router.store(Arc::new(router));
+        self.access_log.store(access_log);
+        *current = (config, components);
+        Ok(())
+    }
+
+    /// Reloads if the contents of the config file differ from `contents`,
+    /// which are updated. An unchanged file on `SIGHUP` only reopens the
+    /// access log, which is what logrotate sends it for.
+    async fn check(&self, trigger: &'static str, contents: &mut Option<Vec<u8>>) {
+        let current = tokio::fs::read(&self.path).await.ok();
+        if current == *contents {
+            if trigger == "SIGHUP" {
+                self.reopen_access_log();
+            }
+            return;
+        }
+        *contents = current;
+        self.reload_logged(trigger).await;
+    }
+
+    /// Reloads and reports the outcome. A rejected config still reopens the
+    /// access log, as logrotate expects of `SIGHUP`.
+    async fn reload_logged(&self, trigger: &'static str) {
+        let result = match self.reload().await {
+            Ok(()) => {
+                tracing::info!(trigger, "Reloaded configuration");
+                "success"
+            }
+            Err(err) => {
+                tracing::error!(
+                    trigger,
+                    "Rejected new configuration, keeping the current one: {err:#}"
+                );
+                self.reopen_access_log();
+                "failure"
+            }
+        };
+        metrics::counter!(
+            "media_server_config_reloads_total",
+            "result" => result,
+        )
+        .increment(1);
+    }
+
+    fn reopen_access_log(&self) {
+        if let Some(log) = self.access_log.load_full()
+            && let Err(err) = log.reopen()
+        {
+            tracing::error!("{err:#}");
+        }
+    }
+}
+
+/// Settings read once at startup, which reloads cannot change.
+#[derive(Debug)]
+struct StartupSettings {
+    listen: String,
+    metrics: Option<MetricsConfig>,
+    otlp: Option<OtlpConfig>,
+    logging: LoggingConfig,
+    shutdown: ShutdownConfig,
+    reload: ReloadConfig,
+}
+
+impl StartupSettings {
+    fn from_config(config: &AppConfig) -> Self {
+        Self {
+            listen: config.listen.clone(),
+            metrics: config.metrics.clone(),
+            otlp: config.otlp.clone(),
+            logging: config.logging.clone(),
+            shutdown: config.shutdown.clone(),
+            reload: config.reload.clone(),
+        }
+    }
+
+    /// Names of the settings that differ in `other`.
+    fn changes(&self, other: &Self) -> Vec<&'static str> {
+        [
+            ("listen", self.listen != other.listen),
+            ("metrics", self.metrics != other.metrics),
+            ("otlp", self.otlp != other.otlp),
+            ("logging", self.logging != other.logging),
+            ("shutdown", self.shutdown != other.shutdown),
+            ("reload", self.reload != other.reload),
+        ]
+        .into_iter()
+        .filter_map(|(name, changed)| changed.then_some(name))
+        .collect()
+    }
+}
+
+/// Reloads on `SIGHUP` and, with `reload.watch`, whenever the contents of the
+/// config file change.
+pub fn watch(reloader: Arc<Reloader>, config: &ReloadConfig) -> eyre::Result<()> {
+    let mut hangups = signal(SignalKind::hangup())?;
+    let mut polls = config
+        .watch
+        .then(|| tokio::time::interval(Duration::from_secs(config.watch_interval_secs)));
+
+    tokio::spawn(async move {
+        let mut contents = tokio::fs::read(&reloader.path).await.ok();
+        loop {
+            let trigger = tokio::select! {
+                Some(()) = hangups.recv() => "SIGHUP",
+                () = poll(&mut polls) => "file change",
+            };
+            reloader.check(trigger, &mut contents).await;
+        }
+    });
+    Ok(())
+}
+
+async fn poll(polls: &mut Option<Interval>) {
+    match polls {
+        Some(polls) => {
+            polls.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests;
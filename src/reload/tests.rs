@@ -0,0 +1,134 @@
+use std::net::SocketAddr;
+use std::path::Path;
+
+use axum::body::Body;
+use axum::extract::ConnectInfo;
+use axum::http::StatusCode;
+
+use super::*;
+
+const DENY_ALL: &str = r#"
+buckets:
+  photos:
+    endpoint_url: "http://localhost:9000"
+    bucket_name: "photos"
+    access_key:
+        plain: "key"
+    secret_key:
+        plain: "secret"
+    deny_cidrs: ["0.0.0.0/0", "::/0"]
+"#;
+
+fn temp_config(contents: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("media-server-{}.yml", uuid::Uuid::new_v4()));
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+fn reloader(path: &Path) -> Arc<Reloader> {
+    let config = AppConfig::load_from(path).unwrap();
+    Arc::new(Reloader::new(config, path.to_path_buf(), Shutdown::default()).unwrap())
+}
+
+async fn status(app: &Router) -> StatusCode {
+    let request = Request::builder()
+        .uri("/photos/cat.jpg")
+        .extension(ConnectInfo("192.0.2.1:4000".parse::<SocketAddr>().unwrap()))
+        .body(Body::empty())
+        .unwrap();
+    app.clone().oneshot(request).await.unwrap().status()
+}
+
+#[tokio::test]
+async fn reload_swaps_in_new_buckets() {
+    let path = temp_config("buckets: {}");
+    let reloader = reloader(&path);
+    let app = reloader.router();
+    assert_eq!(status(&app).await, StatusCode::NOT_FOUND);
+
+    std::fs::write(&path, DENY_ALL).unwrap();
+    reloader.reload().await.unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(status(&app).await, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn invalid_config_keeps_the_current_one() {
+    let path = temp_config(DENY_ALL);
+    let reloader = reloader(&path);
+    let app = reloader.router();
+
+    std::fs::write(&path, "buckets: [not, a, map]").unwrap();
+    assert!(reloader.reload().await.is_err());
+    std::fs::write(&path, DENY_ALL.replace("0.0.0.0/0", "not-a-cidr")).unwrap();
+    assert!(reloader.reload().await.is_err());
+    std::fs::remove_file(&path).unwrap();
+    assert!(reloader.reload().await.is_err());
+
+    assert_eq!(status(&app).await, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn unchanged_file_only_reopens_the_access_log() {
+    let path = temp_config(DENY_ALL);
+    let reloader = reloader(&path);
+    let app = reloader.router();
+    let mut contents = std::fs::read(&path).ok();
+
+    let router = reloader.router.load_full();
+    reloader.check("SIGHUP", &mut contents).await;
+    assert!(Arc::ptr_eq(&router, &reloader.router.load_full()));
+
+    std::fs::write(&path, "buckets: {}").unwrap();
+    reloader.check("SIGHUP", &mut contents).await;
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(!Arc::ptr_eq(&router, &reloader.router.load_full()));
+    assert_eq!(status(&app).await, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn reload_keeps_the_state_of_unchanged_buckets() {
+    let limited = format!(
+        "{DENY_ALL}    rate_limit:\n      requests_per_sec: 0.001\n      burst: 1\n      key: bucket\n"
+    );
+    let path = temp_config(&limited);
+    let reloader = reloader(&path);
+    let app = reloader.router();
+    assert_eq!(status(&app).await, StatusCode::FORBIDDEN);
+    assert_eq!(status(&app).await, StatusCode::TOO_MANY_REQUESTS);
+
+    let other = DENY_ALL.replace("photos:", "videos:");
+    let other = other.trim_start_matches("\nbuckets:\n");
+    std::fs::write(&path, format!("{limited}{other}")).unwrap();
+    reloader.reload().await.unwrap();
+    assert_eq!(status(&app).await, StatusCode::TOO_MANY_REQUESTS);
+
+    std::fs::write(&path, limited.replace("burst: 1", "burst: 2")).unwrap();
+    reloader.reload().await.unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(status(&app).await, StatusCode::FORBIDDEN);
+}
+
+#[test]
+fn startup_only_changes_are_reported() {
+    let startup = |yaml: &str| {
+        StartupSettings::from_config(
+            &serde_yaml::from_str(&format!("{yaml}\nbuckets: {{}}")).unwrap(),
+        )
+    };
+    let current = startup("listen: \"[::]:8080\"");
+
+    assert!(
+        current
+            .changes(&startup("listen: \"[::]:8080\""))
+            .is_empty()
+    );
+    assert_eq!(
+        current.changes(&startup(
+            "listen: \"[::]:9090\"\nmetrics:\n  listen: \"127.0.0.1:9100\"\nlogging:\n  level: debug"
+        )),
+        ["listen", "metrics", "logging"]
+    );
+}
@@ -0,0 +1,74 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use axum::extract::{ConnectInfo, Request, State};
+use axum::http::{HeaderName, HeaderValue};
+use axum::middleware::Next;
+use axum::response::Response;
+
+use crate::client_ip::TrustedProxies;
+
+pub static X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Longest incoming request ID that is passed on rather than replaced.
+const MAX_INCOMING_LEN: usize = 128;
+
+tokio::task_local! {
+    static CURRENT: RequestId;
+}
+
+/// Identifies a request in error bodies and logs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RequestId(pub Arc<str>);
+
+impl RequestId {
+    pub fn generate() -> Self {
+        Self(uuid::Uuid::new_v4().to_string().into())
+    }
+
+    /// Accepts an incoming ID made of visible ASCII characters, short enough
+    /// to be logged and forwarded as is.
+    fn parse(value: &HeaderValue) -> Option<Self> {
+        let bytes = value.as_bytes();
+        let valid = !bytes.is_empty()
+            && bytes.len() <= MAX_INCOMING_LEN
+            && bytes.iter().all(u8::is_ascii_graphic);
+        valid.then(|| Self(String::from_utf8_lossy(bytes).into()))
+    }
+
+    /// ID of the request being served by the current task, if any.
+    pub fn current() -> Option<Self> {
+        CURRENT.try_with(Clone::clone).ok()
+    }
+}
+
+/// Assigns every request an ID, available as a [`RequestId`] extension and
+/// through [`RequestId::current`], recorded on the request's tracing span and
+/// returned in `X-Request-Id`. IDs sent by trusted proxies are kept.
+pub async fn assign_request_id(
+    State(trusted): State<Arc<TrustedProxies>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let from_trusted_proxy = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .is_some_and(|ConnectInfo(addr)| trusted.trusts(addr.ip()));
+    let request_id = request
+        .headers()
+        .get(&X_REQUEST_ID)
+        .filter(|_| from_trusted_proxy)
+        .and_then(RequestId::parse)
+        .unwrap_or_else(RequestId::generate);
+    request.extensions_mut().insert(request_id.clone());
+    tracing::Span::current().record("request_id", &*request_id.0);
+
+    let mut response = CURRENT.scope(request_id.clone(), next.run(request)).await;
+    if let Ok(value) = HeaderValue::from_str(&request_id.0) {
+        response.headers_mut().insert(X_REQUEST_ID.clone(), value);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests;
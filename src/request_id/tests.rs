@@ -0,0 +1,94 @@
+use super::*;
+use axum::Router;
+use axum::body::Body;
+use axum::extract::Extension;
+use axum::middleware;
+use axum::routing::get;
+use http_body_util::BodyExt;
+use tower::ServiceExt;
+
+fn app() -> Router {
+    Router::new()
+        .route(
+            "/",
+            get(|Extension(RequestId(id)): Extension<RequestId>| async move { id.to_string() }),
+        )
+        .route(
+            "/current",
+            get(|| async { RequestId::current().unwrap().0.to_string() }),
+        )
+        .layer(middleware::from_fn_with_state(
+            Arc::new(TrustedProxies::new(vec!["10.0.0.0/8".parse().unwrap()])),
+            assign_request_id,
+        ))
+}
+
+fn request(uri: &str, peer: &str, request_id: Option<&str>) -> Request {
+    let mut builder = Request::builder()
+        .uri(uri)
+        .extension(ConnectInfo(peer.parse::<SocketAddr>().unwrap()));
+    if let Some(request_id) = request_id {
+        builder = builder.header(&X_REQUEST_ID, request_id);
+    }
+    builder.body(Body::empty()).unwrap()
+}
+
+async fn response_id(request: Request) -> String {
+    let resp = app().oneshot(request).await.unwrap();
+    resp.headers()[&X_REQUEST_ID].to_str().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn request_id_is_shared_with_handler_and_response() {
+    let resp = app()
+        .oneshot(Request::builder().uri("/").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+
+    let header = resp.headers()[&X_REQUEST_ID].to_str().unwrap().to_string();
+    let body = resp.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(header.as_bytes(), &body[..]);
+    assert_eq!(header.len(), 36);
+}
+
+#[tokio::test]
+async fn current_request_id_is_available_to_the_task() {
+    let resp = app()
+        .oneshot(request("/current", "192.0.2.1:4000", None))
+        .await
+        .unwrap();
+
+    let header = resp.headers()[&X_REQUEST_ID].to_str().unwrap().to_string();
+    let body = resp.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(header.as_bytes(), &body[..]);
+}
+
+#[tokio::test]
+async fn trusted_proxies_pass_on_their_request_id() {
+    let id = response_id(request("/", "10.1.2.3:4000", Some("lb-1234"))).await;
+    assert_eq!(id, "lb-1234");
+}
+
+#[tokio::test]
+async fn other_clients_get_a_fresh_request_id() {
+    let id = response_id(request("/", "192.0.2.1:4000", Some("lb-1234"))).await;
+    assert_ne!(id, "lb-1234");
+    assert_eq!(id.len(), 36);
+}
+
+#[tokio::test]
+async fn malformed_request_ids_are_replaced() {
+    let id = response_id(request("/", "10.1.2.3:4000", Some("has spaces"))).await;
+    assert_ne!(id, "has spaces");
+
+    let long = "x".repeat(MAX_INCOMING_LEN + 1);
+    let id = response_id(request("/", "10.1.2.3:4000", Some(&long))).await;
+    assert_eq!(id.len(), 36);
+}
+
+#[test]
+fn request_ids_are_unique() {
+    let first = RequestId::generate();
+    let second = RequestId::generate();
+    assert_ne!(first, second);
+}
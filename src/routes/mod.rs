@@ -1,35 +1,107 @@
-use std::sync::Arc;
-
-use axum::extract::{Path, State};
-use axum::http::{HeaderValue, StatusCode};
+use axum::Extension;
+use axum::extract::{Path, RawQuery, State};
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
 use axum::response::{IntoResponse, Response};
+use tracing::Instrument;
 
+use crate::access::AccessRequest;
+use crate::app::AppState;
+use crate::client_ip::ClientIp;
 use crate::error::AppError;
-use crate::s3::{FileResponse, FileServer};
+use crate::s3::{FileResponse, ReadConditions};
 
 pub async fn get_file(
-    State(server): State<Arc<dyn FileServer>>,
+    State(state): State<AppState>,
     Path((config_name, file_path)): Path<(String, String)>,
+    RawQuery(query): RawQuery,
+    headers: HeaderMap,
+    client_ip: Option<Extension<ClientIp>>,
+) -> Result<Response, AppError> {
+    let span = tracing::info_span!(
+        "get_file",
+        bucket = config_name,
+        key = file_path,
+        http.route = "/{config_name}/{*file_path}",
+    );
+    serve_file(state, config_name, file_path, query, headers, client_ip)
+        .instrument(span)
+        .await
+}
+
+async fn serve_file(
+    state: AppState,
+    config_name: String,
+    file_path: String,
+    query: Option<String>,
+    headers: HeaderMap,
+    client_ip: Option<Extension<ClientIp>>,
 ) -> Result<Response, AppError> {
-    let response = server.get_file(&config_name, &file_path).await?;
+    let request = AccessRequest {
+        file_path: &file_path,
+        query: query.as_deref(),
+        headers: &headers,
+        client_ip: client_ip.map(|Extension(ClientIp(ip))| ip),
+    };
+    let client_ip = request.client_ip;
+    state.rate_limits.check_client(&config_name, client_ip)?;
+    let grant = match state.access.check(&config_name, &request).await {
+        Ok(grant) => grant,
+        Err(err) => {
+            state
+                .rate_limits
+                .check_principal(&config_name, None, client_ip)?;
+            return Err(err);
+        }
+    };
+    if let Some(principal) = &grant.principal {
+        tracing::info!(
+            bucket = config_name,
+            principal,
+            key = file_path,
+            "Authenticated access"
+        );
+    }
+
+    state
+        .rate_limits
+        .check_principal(&config_name, grant.principal.as_deref(), client_ip)?;
+
+    let file_path = grant.substitute.unwrap_or(file_path);
+    let conditions = ReadConditions::from_headers(&headers);
+    let response = state
+        .server
+        .get_file(&config_name, &file_path, &conditions)
+        .await?;
 
     match response {
         FileResponse::Redirect(url) => {
             let mut resp = StatusCode::FOUND.into_response();
             resp.headers_mut().insert(
                 "Location",
-                HeaderValue::from_str(&url).map_err(|e| AppError::S3Error(e.to_string()))?,
+                HeaderValue::from_str(&url).map_err(|e| AppError::Internal(e.to_string()))?,
             );
             Ok(resp)
         }
 
-        FileResponse::Stream { content_type, body } => {
+        FileResponse::Stream {
+            content_type,
+            content_range,
+            body,
+        } => {
             let mut resp = Response::new(body);
             resp.headers_mut().insert(
                 "Content-Type",
                 HeaderValue::from_str(&content_type)
-                    .map_err(|e| AppError::S3Error(e.to_string()))?,
+                    .map_err(|e| AppError::Internal(e.to_string()))?,
             );
+            if let Some(content_range) = content_range {
+                *resp.status_mut() = StatusCode::PARTIAL_CONTENT;
+                resp.headers_mut().insert(
+                    "Content-Range",
+                    HeaderValue::from_str(&content_range)
+                        .map_err(|e| AppError::Internal(e.to_string()))?,
+                );
+            }
             Ok(resp)
         }
     }
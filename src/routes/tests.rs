@@ -1,10 +1,14 @@
 use super::*;
+use crate::access::AccessControl;
+use crate::rate_limit::RateLimits;
+use crate::s3::{FileServer, ReadConditions};
 use axum::Router;
 use axum::body::Body;
 use axum::routing::get;
 use http_body_util::BodyExt;
 use std::future::Future;
 use std::pin::Pin;
+use std::sync::Arc;
 use tower::ServiceExt;
 
 struct MockFileServer {
@@ -16,6 +20,7 @@ impl FileServer for MockFileServer {
         &self,
         config_name: &str,
         _file_path: &str,
+        _conditions: &ReadConditions,
     ) -> Pin<Box<dyn Future<Output = Result<FileResponse, AppError>> + Send + '_>> {
         let config_name = config_name.to_string();
         let result = self.response.lock().unwrap().take();
@@ -24,10 +29,18 @@ impl FileServer for MockFileServer {
 }
 
 fn test_router(mock: MockFileServer) -> Router {
-    let server: Arc<dyn FileServer> = Arc::new(mock);
+    test_router_with_access(mock, AccessControl::default())
+}
+
+fn test_router_with_access(mock: MockFileServer, access: AccessControl) -> Router {
+    let state = AppState {
+        server: Arc::new(mock),
+        access: Arc::new(access),
+        rate_limits: Arc::new(RateLimits::default()),
+    };
     Router::new()
         .route("/{config_name}/{*file_path}", get(get_file))
-        .with_state(server)
+        .with_state(state)
 }
 
 fn request(uri: &str) -> axum::http::Request<Body> {
@@ -58,6 +71,7 @@ async fn proxy_mode_streams_body() {
     let mock = MockFileServer {
         response: std::sync::Mutex::new(Some(Ok(FileResponse::Stream {
             content_type: "image/jpeg".into(),
+            content_range: None,
             body: Body::from("fake-image-data"),
         }))),
     };
@@ -69,6 +83,21 @@ async fn proxy_mode_streams_body() {
     assert_eq!(&body[..], b"fake-image-data");
 }
 
+#[tokio::test]
+async fn partial_read_returns_206() {
+    let mock = MockFileServer {
+        response: std::sync::Mutex::new(Some(Ok(FileResponse::Stream {
+            content_type: "video/mp4".into(),
+            content_range: Some("bytes 0-3/100".into()),
+            body: Body::from("fake"),
+        }))),
+    };
+    let app = test_router(mock);
+    let resp = app.oneshot(request("/videos/clip.mp4")).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(resp.headers()["content-range"], "bytes 0-3/100");
+}
+
 #[tokio::test]
 async fn unknown_config_returns_404() {
     let mock = MockFileServer {
@@ -98,3 +127,116 @@ async fn s3_error_returns_500() {
     let resp = app.oneshot(request("/photos/img.jpg")).await.unwrap();
     assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
 }
+
+fn signed_access() -> AccessControl {
+    let config: crate::config::AppConfig = serde_yaml::from_str(
+        r#"
+buckets:
+  private:
+    endpoint_url: "http://localhost:9000"
+    bucket_name: "private"
+    access_key:
+        plain: "key"
+    secret_key:
+        plain: "secret"
+    require_signature:
+      secret:
+        plain: "signing-secret"
+"#,
+    )
+    .unwrap();
+    AccessControl::from_config(&config).unwrap()
+}
+
+fn redirect_mock() -> MockFileServer {
+    MockFileServer {
+        response: std::sync::Mutex::new(Some(Ok(FileResponse::Redirect(
+            "https://s3.example.com/presigned".into(),
+        )))),
+    }
+}
+
+#[tokio::test]
+async fn unsigned_request_to_private_bucket_returns_403() {
+    let app = test_router_with_access(redirect_mock(), signed_access());
+    let resp = app.oneshot(request("/private/img.jpg")).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn signed_request_to_private_bucket_is_served() {
+    let access = signed_access();
+    let query = access
+        .sign(
+            "private",
+            &crate::access::SignatureScope::Path("img.jpg".into()),
+            60,
+            None,
+        )
+        .unwrap();
+    let app = test_router_with_access(redirect_mock(), access);
+    let resp = app
+        .oneshot(request(&format!("/private/img.jpg?{query}")))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::FOUND);
+}
+
+#[tokio::test]
+async fn rate_limited_request_returns_429() {
+    let config: crate::config::AppConfig = serde_yaml::from_str(
+        r#"
+rate_limit:
+  requests_per_sec: 0.5
+  burst: 1
+buckets: {}
+"#,
+    )
+    .unwrap();
+    let state = AppState {
+        server: Arc::new(redirect_mock()),
+        access: Arc::new(AccessControl::default()),
+        rate_limits: Arc::new(RateLimits::from_config(&config).unwrap()),
+    };
+    let app = Router::new()
+        .route("/{config_name}/{*file_path}", get(get_file))
+        .with_state(state);
+
+    let resp = app.clone().oneshot(request("/photos/a.jpg")).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::FOUND);
+
+    let resp = app.oneshot(request("/photos/a.jpg")).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(resp.headers().get("retry-after").unwrap(), "2");
+}
+
+#[tokio::test]
+async fn failed_authentication_is_rate_limited() {
+    let config: crate::config::AppConfig = serde_yaml::from_str(
+        r#"
+rate_limit:
+  requests_per_sec: 0.5
+  burst: 1
+buckets: {}
+"#,
+    )
+    .unwrap();
+    let state = AppState {
+        server: Arc::new(redirect_mock()),
+        access: Arc::new(signed_access()),
+        rate_limits: Arc::new(RateLimits::from_config(&config).unwrap()),
+    };
+    let app = Router::new()
+        .route("/{config_name}/{*file_path}", get(get_file))
+        .with_state(state);
+
+    let resp = app
+        .clone()
+        .oneshot(request("/private/a.jpg"))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+    let resp = app.oneshot(request("/private/a.jpg")).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+}
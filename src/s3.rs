@@ -1,70 +1,331 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::future::Future;
 use std::pin::Pin;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use aws_credential_types::Credentials;
-use aws_sdk_s3::config::Region;
+use aws_sdk_s3::config::interceptors::BeforeTransmitInterceptorContextMut;
+use aws_sdk_s3::config::timeout::TimeoutConfig;
+use aws_sdk_s3::config::{ConfigBag, Intercept, Region, RuntimeComponents, retry};
+use aws_sdk_s3::error::BoxError;
+use aws_sdk_s3::operation::get_object::GetObjectOutput;
+use aws_sdk_s3::operation::head_object::HeadObjectOutput;
 use aws_sdk_s3::presigning::PresigningConfig;
-use axum::body::Body;
+use axum::body::{Body, Bytes};
+use axum::http::{HeaderMap, header};
+use eyre::WrapErr;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use tokio::sync::OwnedSemaphorePermit;
+use tracing::Instrument;
 
-use crate::config::{AppConfig, BucketConfig};
+use crate::cache::{CachedObject, ObjectCache, PurgeTarget};
+use crate::circuit::{CircuitBreaker, CircuitState};
+use crate::concurrency::{self, ConcurrencyLimit};
+use crate::config::{AppConfig, BucketConfig, RetryMode};
 use crate::error::AppError;
+use crate::request_id::{RequestId, X_REQUEST_ID};
+use crate::throttle::Throttle;
 
 struct BucketClient {
+    /// Config name, used as the `bucket` label of metrics.
+    name: String,
     client: aws_sdk_s3::Client,
     bucket_name: String,
     proxy: bool,
+    cache: bool,
     presign_expiry: Duration,
+    throttle: Option<Arc<Throttle>>,
+    concurrency: Option<ConcurrencyLimit>,
+    breaker: Option<CircuitBreaker>,
+}
+
+impl BucketClient {
+    fn from_config(name: &str, bc: &BucketConfig, config: &AppConfig) -> eyre::Result<Self> {
+        let client = build_s3_client(bc)
+            .wrap_err_with(|| format!("invalid credentials for bucket \"{name}\""))?;
+        let presign_expiry =
+            Duration::from_secs(bc.presign_expiry_secs.unwrap_or(config.presign_expiry_secs));
+        let throttle = bc
+            .throttle
+            .as_ref()
+            .map(Throttle::from_config)
+            .transpose()
+            .wrap_err_with(|| format!("invalid throttle for bucket \"{name}\""))?
+            .map(Arc::new);
+        let concurrency = bc
+            .concurrency
+            .as_ref()
+            .map(|limit| ConcurrencyLimit::from_config(name, limit))
+            .transpose()
+            .wrap_err_with(|| format!("invalid concurrency limit for bucket \"{name}\""))?;
+        let breaker = bc
+            .circuit_breaker
+            .as_ref()
+            .map(|breaker| CircuitBreaker::from_config(name, breaker))
+            .transpose()
+            .wrap_err_with(|| format!("invalid circuit breaker for bucket \"{name}\""))?;
+
+        Ok(Self {
+            name: name.to_string(),
+            client,
+            bucket_name: bc.bucket_name.clone(),
+            proxy: bc.proxy,
+            cache: bc.proxy && bc.cache && config.cache.is_some(),
+            presign_expiry,
+            throttle,
+            concurrency,
+            breaker,
+        })
+    }
+
+    /// Runs an S3 call under the bucket's circuit breaker and concurrency limit.
+    async fn call<T>(
+        &self,
+        operation: &'static str,
+        key: &str,
+        call: impl Future<Output = Result<T, AppError>>,
+    ) -> Result<T, AppError> {
+        self.call_inner(operation, true, call)
+            .instrument(self.span(operation, key))
+            .await
+    }
+
+    /// Runs a health check, bypassing the circuit breaker and concurrency
+    /// limit so that probes neither count as traffic nor take the half-open
+    /// slots meant for real requests.
+    async fn call_unguarded<T>(
+        &self,
+        operation: &'static str,
+        key: &str,
+        call: impl Future<Output = Result<T, AppError>>,
+    ) -> Result<T, AppError> {
+        self.call_inner(operation, false, call)
+            .instrument(self.span(operation, key))
+            .await
+    }
+
+    fn span(&self, operation: &'static str, key: &str) -> tracing::Span {
+        tracing::info_span!(
+            "s3",
+            otel.name = operation,
+            otel.kind = "client",
+            aws.s3.bucket = self.bucket_name,
+            aws.s3.key = key,
+        )
+    }
+
+    async fn call_inner<T>(
+        &self,
+        operation: &'static str,
+        guarded: bool,
+        call: impl Future<Output = Result<T, AppError>>,
+    ) -> Result<T, AppError> {
+        let breaker = self
+            .breaker
+            .as_ref()
+            .filter(|_| guarded)
+            .map(CircuitBreaker::start)
+            .transpose()?;
+        let _slot = concurrency::acquire(self.concurrency.as_ref().filter(|_| guarded)).await?;
+
+        let started = Instant::now();
+        let result = call.await;
+        let elapsed = started.elapsed();
+        metrics::histogram!(
+            "media_server_upstream_duration_seconds",
+            "bucket" => self.name.clone(),
+            "operation" => operation,
+        )
+        .record(elapsed);
+        crate::access_log::record_upstream(elapsed);
+
+        if let Some(breaker) = breaker {
+            breaker.finish(&result);
+        }
+        result
+    }
 }
 
 pub struct S3Clients {
-    buckets: HashMap<String, BucketClient>,
+    buckets: HashMap<String, Arc<BucketClient>>,
+    cache: Option<Arc<ObjectCache>>,
+    open_streams: Option<Arc<ConcurrencyLimit>>,
 }
 
 impl S3Clients {
-    pub fn from_config(config: &AppConfig) -> Self {
-        let mut buckets = HashMap::new();
+    pub fn from_config(config: &AppConfig) -> eyre::Result<Self> {
+        Self::build(config, None)
+    }
+
+    /// Builds the clients of `config`, taking over from `self`, built from
+    /// `previous`, whatever has unchanged settings: the object cache, the open
+    /// stream limit and each unchanged bucket with its circuit breaker and
+    /// concurrency limit. Cached objects of the other buckets are dropped.
+    pub fn reconfigured(&self, previous: &AppConfig, config: &AppConfig) -> eyre::Result<Self> {
+        Self::build(config, Some((previous, self)))
+    }
 
+    fn build(config: &AppConfig, previous: Option<(&AppConfig, &Self)>) -> eyre::Result<Self> {
+        let cache = match previous {
+            Some((old, clients)) if old.cache == config.cache => clients.cache.clone(),
+            _ => config
+                .cache
+                .as_ref()
+                .map(|cache| Arc::new(ObjectCache::from_config(cache))),
+        };
+
+        let mut buckets = HashMap::new();
         for (name, bc) in &config.buckets {
-            let client = build_s3_client(bc);
-            let presign_expiry =
-                Duration::from_secs(bc.presign_expiry_secs.unwrap_or(config.presign_expiry_secs));
-
-            buckets.insert(
-                name.clone(),
-                BucketClient {
-                    client,
-                    bucket_name: bc.bucket_name.clone(),
-                    proxy: bc.proxy,
-                    presign_expiry,
-                },
-            );
+            let unchanged = previous.and_then(|(old, clients)| {
+                let same = old.buckets.get(name) == Some(bc)
+                    && old.presign_expiry_secs == config.presign_expiry_secs
+                    && old.cache.is_some() == config.cache.is_some();
+                clients.buckets.get(name).filter(|_| same)
+            });
+            let client = match unchanged {
+                Some(client) => client.clone(),
+                None => Arc::new(BucketClient::from_config(name, bc, config)?),
+            };
+            buckets.insert(name.clone(), client);
+        }
+
+        if let Some((old, clients)) = previous
+            && old.cache == config.cache
+            && let Some(cache) = &cache
+        {
+            for name in old.buckets.keys() {
+                let kept = buckets
+                    .get(name)
+                    .zip(clients.buckets.get(name))
+                    .is_some_and(|(new, old)| Arc::ptr_eq(new, old));
+                if !kept {
+                    cache.forget(name);
+                }
+            }
         }
 
-        Self { buckets }
+        let open_streams = match previous {
+            Some((old, clients)) if old.open_streams == config.open_streams => {
+                clients.open_streams.clone()
+            }
+            _ => config
+                .open_streams
+                .as_ref()
+                .map(|limit| ConcurrencyLimit::from_config("open_streams", limit))
+                .transpose()
+                .wrap_err("invalid open stream limit")?
+                .map(Arc::new),
+        };
+
+        Ok(Self {
+            buckets,
+            cache,
+            open_streams,
+        })
+    }
+
+    fn bucket(&self, config_name: &str) -> Result<&BucketClient, AppError> {
+        self.buckets
+            .get(config_name)
+            .map(Arc::as_ref)
+            .ok_or_else(|| AppError::ConfigNotFound(config_name.to_string()))
+    }
+
+    fn cache_for(&self, bc: &BucketClient) -> Option<&ObjectCache> {
+        self.cache.as_deref().filter(|_| bc.cache)
+    }
+
+    /// Runs `head_bucket` against every configured bucket.
+    pub async fn check_buckets(&self) -> BTreeMap<String, BucketCheck> {
+        let checks = self.buckets.iter().map(|(name, bc)| async move {
+            let check = BucketCheck {
+                result: head_bucket(bc).await,
+                circuit: bc.breaker.as_ref().map(CircuitBreaker::state),
+            };
+            (name.clone(), check)
+        });
+
+        futures::future::join_all(checks)
+            .await
+            .into_iter()
+            .collect()
+    }
+
+    /// Circuit state of each bucket that has a breaker.
+    pub fn circuit_states(&self) -> BTreeMap<String, CircuitState> {
+        self.buckets
+            .iter()
+            .filter_map(|(name, bc)| Some((name.clone(), bc.breaker.as_ref()?.state())))
+            .collect()
+    }
+}
+
+/// Sends the ID of the request being served along with S3 calls, so they can
+/// be found in MinIO audit logs. Added after signing, which also keeps it out
+/// of presigned URLs.
+#[derive(Debug)]
+struct ForwardRequestId;
+
+impl Intercept for ForwardRequestId {
+    fn name(&self) -> &'static str {
+        "ForwardRequestId"
+    }
+
+    fn modify_before_transmit(
+        &self,
+        context: &mut BeforeTransmitInterceptorContextMut<'_>,
+        _runtime_components: &RuntimeComponents,
+        _cfg: &mut ConfigBag,
+    ) -> Result<(), BoxError> {
+        if let Some(RequestId(id)) = RequestId::current() {
+            context
+                .request_mut()
+                .headers_mut()
+                .insert(X_REQUEST_ID.as_str(), id.to_string());
+        }
+        Ok(())
     }
 }
 
-fn build_s3_client(bc: &BucketConfig) -> aws_sdk_s3::Client {
+fn build_s3_client(bc: &BucketConfig) -> eyre::Result<aws_sdk_s3::Client> {
     let credentials = Credentials::new(
-        bc.access_key.clone(),
-        bc.secret_key.clone(),
+        bc.access_key.resolve()?,
+        bc.secret_key.resolve()?,
         None,
         None,
         "media-server",
     );
 
+    let millis = |ms: Option<u64>| ms.map(Duration::from_millis);
+    let mut timeouts = TimeoutConfig::builder();
+    timeouts
+        .set_connect_timeout(millis(bc.timeouts.connect_ms))
+        .set_read_timeout(millis(bc.timeouts.read_ms))
+        .set_operation_timeout(millis(bc.timeouts.operation_ms))
+        .set_operation_attempt_timeout(millis(bc.timeouts.operation_attempt_ms));
+
+    let mut retry = match bc.retry.mode {
+        RetryMode::Standard => retry::RetryConfig::standard(),
+        RetryMode::Adaptive => retry::RetryConfig::adaptive(),
+    };
+    if let Some(max_attempts) = bc.retry.max_attempts {
+        retry = retry.with_max_attempts(max_attempts);
+    }
+
     let s3_config = aws_sdk_s3::Config::builder()
         .endpoint_url(&bc.endpoint_url)
         .region(Region::new(bc.region.clone()))
         .credentials_provider(credentials)
         .force_path_style(bc.force_path_style)
+        .timeout_config(timeouts.build())
+        .retry_config(retry)
+        .interceptor(ForwardRequestId)
         .behavior_version_latest()
         .build();
 
-    aws_sdk_s3::Client::from_conf(s3_config)
+    Ok(aws_sdk_s3::Client::from_conf(s3_config))
 }
 
 impl S3Clients {
@@ -72,82 +333,654 @@ impl S3Clients {
         &self,
         bc: &BucketClient,
         file_path: &str,
+        conditions: &ReadConditions,
     ) -> Result<FileResponse, AppError> {
-        // head_object to verify existence and distinguish 404 from other errors
-        let head_result = bc
-            .client
-            .head_object()
-            .bucket(&bc.bucket_name)
-            .key(file_path)
-            .send()
-            .await;
-
-        match head_result {
-            Ok(_) => {}
-            Err(err) => {
-                let service_err = err.into_service_error();
-                if service_err.is_not_found() {
-                    return Err(AppError::ObjectNotFound(file_path.to_string()));
-                }
-                return Err(AppError::S3Error(service_err.to_string()));
-            }
-        }
-
-        let presign_config = PresigningConfig::builder()
-            .expires_in(bc.presign_expiry)
-            .build()
-            .map_err(|e| AppError::S3Error(e.to_string()))?;
-
-        let presigned = bc
-            .client
-            .get_object()
-            .bucket(&bc.bucket_name)
-            .key(file_path)
-            .presigned(presign_config)
-            .await
-            .map_err(|e| AppError::S3Error(e.to_string()))?;
+        // head_object to verify existence and distinguish 404 from other errors,
+        // and to answer ranges and preconditions S3 would refuse
+        head_object(bc, file_path, conditions).await?;
 
-        Ok(FileResponse::Redirect(presigned.uri().to_string()))
+        let url = presign(bc, file_path, bc.presign_expiry).await?;
+        Ok(FileResponse::Redirect(url))
     }
 
     async fn proxy_file(
         &self,
+        config_name: &str,
         bc: &BucketClient,
         file_path: &str,
+        conditions: &ReadConditions,
     ) -> Result<FileResponse, AppError> {
-        let result = bc
-            .client
-            .get_object()
-            .bucket(&bc.bucket_name)
-            .key(file_path)
-            .send()
-            .await;
+        // Partial and conditional reads are left to S3, which holds the
+        // object's current validators.
+        let cache = self.cache_for(bc).filter(|_| conditions.is_empty());
+
+        if let Some(cache) = cache {
+            let hit = cache.get(config_name, file_path).await;
+            metrics::counter!(
+                "media_server_cache_requests_total",
+                "bucket" => bc.name.clone(),
+                "result" => if hit.is_some() { "hit" } else { "miss" },
+            )
+            .increment(1);
+
+            if let Some(hit) = hit {
+                bytes_streamed(bc).increment(hit.body.len() as u64);
+                let body = buffered_body(bc, hit.body, &hit.content_type);
+                return Ok(FileResponse::Stream {
+                    content_type: hit.content_type,
+                    content_range: None,
+                    body,
+                });
+            }
+        }
+
+        let stream_slot = concurrency::acquire(self.open_streams.as_deref()).await?;
+        let fill = cache.map(|cache| cache.begin_fill(config_name, file_path));
+        let output = get_object(bc, file_path, conditions).await?;
+        let content_type = output
+            .content_type()
+            .unwrap_or("application/octet-stream")
+            .to_string();
+
+        if let Some((cache, fill)) = cache.zip(fill)
+            && cache.accepts(output.content_length().unwrap_or(i64::MAX) as u64)
+        {
+            let object = collect_object(output, content_type).await?;
+            bytes_streamed(bc).increment(object.body.len() as u64);
+            let body = buffered_body(bc, object.body.clone(), &object.content_type);
+            fill.insert(object.clone()).await;
+            return Ok(FileResponse::Stream {
+                content_type: object.content_type,
+                content_range: None,
+                body,
+            });
+        }
+
+        let size = output.content_length().map(|len| len.max(0) as u64);
+        let content_range = output.content_range().map(str::to_string);
+        let reader = output.body.into_async_read();
+        // The guard moves into the stream and is dropped along with the body.
+        let guard = OpenStream::new(bc, stream_slot);
+        let stream = tokio_util::io::ReaderStream::new(reader).inspect(move |chunk| {
+            if let Ok(chunk) = chunk {
+                guard.bytes.increment(chunk.len() as u64);
+            }
+        });
+        let body = match &bc.throttle {
+            Some(throttle) => Body::from_stream(throttle.apply(stream, &content_type, size)),
+            None => Body::from_stream(stream),
+        };
+        Ok(FileResponse::Stream {
+            content_type,
+            content_range,
+            body,
+        })
+    }
+
+    /// Fails unless `config_name` exists and caches objects, so that a
+    /// prefetch can be refused before it starts.
+    pub fn check_prefetch(&self, config_name: &str) -> Result<(), AppError> {
+        let bc = self.bucket(config_name)?;
+        self.cache_for(bc)
+            .map(drop)
+            .ok_or_else(|| AppError::CacheNotEnabled(config_name.to_string()))
+    }
+
+    /// Pulls the objects selected by `target` into the proxy cache, fetching at
+    /// most `concurrency` objects at a time. `progress` is handed the running
+    /// totals once the objects are listed and then every
+    /// `PREFETCH_PROGRESS_INTERVAL` objects.
+    pub async fn prefetch(
+        &self,
+        config_name: &str,
+        target: PrefetchTarget,
+        concurrency: usize,
+        mut progress: impl FnMut(&PrefetchReport),
+    ) -> Result<PrefetchReport, AppError> {
+        let bc = self.bucket(config_name)?;
+        let cache = self
+            .cache_for(bc)
+            .ok_or_else(|| AppError::CacheNotEnabled(config_name.to_string()))?;
+
+        let mut report = PrefetchReport::default();
+        let mut candidates = Vec::new();
+
+        for key in target.keys {
+            candidates.push((key, None));
+        }
+
+        if let Some(prefix) = target.prefix {
+            let mut pages = bc
+                .client
+                .list_objects_v2()
+                .bucket(&bc.bucket_name)
+                .prefix(&prefix)
+                .into_paginator()
+                .send();
+
+            while let Some(page) = pages.next().await {
+                let page = page.map_err(|err| AppError::from_sdk(err, &prefix))?;
+                for object in page.contents() {
+                    if let Some(key) = object.key() {
+                        candidates.push((key.to_string(), object.size()));
+                    }
+                }
+            }
+        }
+
+        report.listed = candidates.len();
+        tracing::info!(
+            bucket = config_name,
+            objects = report.listed,
+            "Starting cache prefetch"
+        );
+        progress(&report);
+
+        let mut results = futures::stream::iter(candidates)
+            .map(|(key, size)| async move {
+                if let Some(size) = size
+                    && !cache.accepts(size.max(0) as u64)
+                {
+                    return PrefetchOutcome::Skipped;
+                }
+
+                let fill = cache.begin_fill(config_name, &key);
+                let output = match get_object(bc, &key, &ReadConditions::default()).await {
+                    Ok(output) => output,
+                    Err(err) => return PrefetchOutcome::Failed(key, err),
+                };
+                if !cache.accepts(output.content_length().unwrap_or(i64::MAX) as u64) {
+                    return PrefetchOutcome::Skipped;
+                }
 
-        match result {
-            Ok(output) => {
                 let content_type = output
                     .content_type()
                     .unwrap_or("application/octet-stream")
                     .to_string();
-                let reader = output.body.into_async_read();
-                let stream = tokio_util::io::ReaderStream::new(reader);
-                let body = Body::from_stream(stream);
-                Ok(FileResponse::Stream { content_type, body })
+                match collect_object(output, content_type).await {
+                    Ok(object) => {
+                        let bytes = object.body.len() as u64;
+                        if fill.insert(object).await {
+                            PrefetchOutcome::Cached(bytes)
+                        } else {
+                            PrefetchOutcome::Skipped
+                        }
+                    }
+                    Err(err) => PrefetchOutcome::Failed(key, err),
+                }
+            })
+            .buffer_unordered(concurrency.max(1));
+
+        while let Some(outcome) = results.next().await {
+            match outcome {
+                PrefetchOutcome::Cached(bytes) => {
+                    report.cached += 1;
+                    report.bytes += bytes;
+                }
+                PrefetchOutcome::Skipped => report.skipped += 1,
+                PrefetchOutcome::Failed(key, err) => {
+                    tracing::warn!(bucket = config_name, key, "Prefetch failed: {err}");
+                    report.failed += 1;
+                }
             }
-            Err(err) => {
-                let service_err = err.into_service_error();
-                if service_err.is_no_such_key() {
-                    return Err(AppError::ObjectNotFound(file_path.to_string()));
+
+            let done = report.cached + report.skipped + report.failed;
+            if done % PREFETCH_PROGRESS_INTERVAL == 0 {
+                tracing::info!(
+                    bucket = config_name,
+                    done,
+                    total = report.listed,
+                    "Cache prefetch progress"
+                );
+                progress(&report);
+            }
+        }
+
+        tracing::info!(
+            bucket = config_name,
+            cached = report.cached,
+            skipped = report.skipped,
+            failed = report.failed,
+            bytes = report.bytes,
+            "Cache prefetch finished"
+        );
+
+        Ok(report)
+    }
+}
+
+impl S3Clients {
+    pub async fn purge(&self, config_name: &str, target: &PurgeTarget) -> Result<usize, AppError> {
+        let bc = self.bucket(config_name)?;
+        let cache = self
+            .cache_for(bc)
+            .ok_or_else(|| AppError::CacheNotEnabled(config_name.to_string()))?;
+        cache.purge(config_name, target).await
+    }
+
+    /// Invalidates `key` in every cached config backed by the S3 bucket
+    /// `bucket_name`, returning how many entries were removed.
+    pub async fn invalidate_object(&self, bucket_name: &str, key: &str) -> usize {
+        let target = PurgeTarget::Key(key.to_string());
+        let mut invalidated = 0;
+
+        for (config_name, bc) in &self.buckets {
+            if bc.bucket_name != bucket_name {
+                continue;
+            }
+            if let Some(cache) = self.cache_for(bc) {
+                invalidated += cache.purge(config_name, &target).await.unwrap_or(0);
+            }
+        }
+
+        invalidated
+    }
+}
+
+impl S3Clients {
+    /// Presigns a download of `key`, valid for `expires_in` or the bucket's
+    /// configured expiry.
+    pub async fn presign(
+        &self,
+        config_name: &str,
+        key: &str,
+        expires_in: Option<Duration>,
+    ) -> Result<String, AppError> {
+        let bc = self.bucket(config_name)?;
+        presign(bc, key, expires_in.unwrap_or(bc.presign_expiry)).await
+    }
+
+    /// Lists the objects under `prefix`; without `recursive`, keys below the
+    /// next `/` are folded into their common prefix.
+    pub async fn list(
+        &self,
+        config_name: &str,
+        prefix: &str,
+        recursive: bool,
+    ) -> Result<Vec<ListEntry>, AppError> {
+        let bc = self.bucket(config_name)?;
+        let mut pages = bc
+            .client
+            .list_objects_v2()
+            .bucket(&bc.bucket_name)
+            .prefix(prefix)
+            .set_delimiter((!recursive).then(|| "/".to_string()))
+            .into_paginator()
+            .send();
+
+        let mut entries = Vec::new();
+        while let Some(page) = pages.next().await {
+            let page = page.map_err(|err| AppError::from_sdk(err, prefix))?;
+            for common in page.common_prefixes() {
+                if let Some(prefix) = common.prefix() {
+                    entries.push(ListEntry::Prefix(prefix.to_string()));
+                }
+            }
+            for object in page.contents() {
+                if let Some(key) = object.key() {
+                    entries.push(ListEntry::Object {
+                        key: key.to_string(),
+                        size: object.size().unwrap_or(0),
+                        last_modified: object.last_modified().map(format_time),
+                    });
                 }
-                Err(AppError::S3Error(service_err.to_string()))
             }
         }
+        Ok(entries)
     }
+
+    pub async fn stat(&self, config_name: &str, key: &str) -> Result<ObjectInfo, AppError> {
+        let bc = self.bucket(config_name)?;
+        let output = head_object(bc, key, &ReadConditions::default()).await?;
+        Ok(ObjectInfo {
+            key: key.to_string(),
+            size: output.content_length(),
+            content_type: output.content_type().map(str::to_string),
+            etag: output.e_tag().map(str::to_string),
+            last_modified: output.last_modified().map(format_time),
+            metadata: output
+                .metadata()
+                .map(|metadata| metadata.clone().into_iter().collect())
+                .unwrap_or_default(),
+        })
+    }
+
+    /// Checks that every bucket can be reached, listed and read from,
+    /// bypassing circuit breakers so that a diagnostic run can't trip them.
+    pub async fn test_buckets(&self) -> BTreeMap<String, BucketTest> {
+        let tests = self.buckets.iter().map(|(name, bc)| async move {
+            let list = bc
+                .call_unguarded("list_objects_v2", "", async {
+                    bc.client
+                        .list_objects_v2()
+                        .bucket(&bc.bucket_name)
+                        .max_keys(1)
+                        .send()
+                        .await
+                        .map_err(|err| AppError::from_sdk(err, ""))
+                })
+                .await
+                .map(|output| {
+                    output
+                        .contents
+                        .and_then(|objects| objects.into_iter().next())
+                        .and_then(|object| object.key)
+                });
+            let read = match &list {
+                Ok(Some(key)) => ReadTest::Read(probe_object(bc, key).await),
+                // Without a key known to exist, a random one is read: S3
+                // answers 404 when reads are allowed, but also 403 when the
+                // credentials lack `s3:ListBucket`, which tells nothing.
+                _ => {
+                    let probe = format!("media-server-probe-{}", uuid::Uuid::new_v4());
+                    match probe_object(bc, &probe).await {
+                        Ok(()) | Err(AppError::ObjectNotFound(_)) => ReadTest::Read(Ok(())),
+                        Err(AppError::AccessDenied(_)) if list.is_ok() => {
+                            ReadTest::Inconclusive("no objects to read; probe refused")
+                        }
+                        Err(AppError::AccessDenied(_)) => {
+                            ReadTest::Inconclusive("listing failed; probe refused")
+                        }
+                        Err(err) => ReadTest::Read(Err(err)),
+                    }
+                }
+            };
+
+            let test = BucketTest {
+                bucket_name: bc.bucket_name.clone(),
+                reach: head_bucket(bc).await,
+                list: list.map(drop),
+                read,
+            };
+            (name.clone(), test)
+        });
+
+        futures::future::join_all(tests).await.into_iter().collect()
+    }
+}
+
+fn format_time(time: &aws_sdk_s3::primitives::DateTime) -> String {
+    time.fmt(aws_sdk_s3::primitives::DateTimeFormat::DateTime)
+        .unwrap_or_else(|_| time.to_string())
+}
+
+pub enum ListEntry {
+    Prefix(String),
+    Object {
+        key: String,
+        size: i64,
+        last_modified: Option<String>,
+    },
+}
+
+pub struct ObjectInfo {
+    pub key: String,
+    pub size: Option<i64>,
+    pub content_type: Option<String>,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub metadata: BTreeMap<String, String>,
+}
+
+pub struct BucketTest {
+    pub bucket_name: String,
+    pub reach: Result<(), AppError>,
+    pub list: Result<(), AppError>,
+    pub read: ReadTest,
+}
+
+/// Outcome of the read check, which can only be inferred from a refused
+/// probe when no object is known to exist.
+pub enum ReadTest {
+    Read(Result<(), AppError>),
+    /// Why the read could be neither confirmed nor ruled out.
+    Inconclusive(&'static str),
+}
+
+/// Body of an object held in memory, paced by the bucket's throttle like
+/// streamed ones.
+fn buffered_body(bc: &BucketClient, body: Bytes, content_type: &str) -> Body {
+    match &bc.throttle {
+        Some(throttle) => Body::from_stream(throttle.apply_buffered(body, content_type)),
+        None => Body::from(body),
+    }
+}
+
+fn bytes_streamed(bc: &BucketClient) -> metrics::Counter {
+    metrics::counter!("media_server_bytes_streamed_total", "bucket" => bc.name.clone())
+}
+
+/// Counts a proxied body in the open stream gauge, holding its open stream
+/// slot, until dropped.
+struct OpenStream {
+    bytes: metrics::Counter,
+    open: metrics::Gauge,
+    _slot: Option<OwnedSemaphorePermit>,
+}
+
+impl OpenStream {
+    fn new(bc: &BucketClient, slot: Option<OwnedSemaphorePermit>) -> Self {
+        let open = metrics::gauge!("media_server_open_streams", "bucket" => bc.name.clone());
+        open.increment(1);
+        Self {
+            bytes: bytes_streamed(bc),
+            open,
+            _slot: slot,
+        }
+    }
+}
+
+impl Drop for OpenStream {
+    fn drop(&mut self) {
+        self.open.decrement(1);
+    }
+}
+
+pub struct BucketCheck {
+    pub result: Result<(), AppError>,
+    pub circuit: Option<CircuitState>,
+}
+
+const PREFETCH_PROGRESS_INTERVAL: usize = 100;
+
+/// Objects to prefetch: an explicit key list, every key under a prefix, or both.
+#[derive(Debug, Default, Deserialize)]
+pub struct PrefetchTarget {
+    pub prefix: Option<String>,
+    #[serde(default)]
+    pub keys: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct PrefetchReport {
+    pub listed: usize,
+    pub cached: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    pub bytes: u64,
+}
+
+enum PrefetchOutcome {
+    Cached(u64),
+    Skipped,
+    Failed(String, AppError),
+}
+
+async fn head_bucket(bc: &BucketClient) -> Result<(), AppError> {
+    bc.call_unguarded("head_bucket", "", async {
+        bc.client
+            .head_bucket()
+            .bucket(&bc.bucket_name)
+            .send()
+            .await
+            .map(|_| ())
+            .map_err(|err| match AppError::from_sdk(err, &bc.bucket_name) {
+                AppError::ObjectNotFound(bucket) => {
+                    AppError::S3Error(format!("bucket not found: {bucket}"))
+                }
+                err => err,
+            })
+    })
+    .await
+}
+
+async fn head_object(
+    bc: &BucketClient,
+    file_path: &str,
+    conditions: &ReadConditions,
+) -> Result<HeadObjectOutput, AppError> {
+    bc.call("head_object", file_path, async {
+        bc.client
+            .head_object()
+            .bucket(&bc.bucket_name)
+            .key(file_path)
+            .set_range(conditions.range.clone())
+            .set_if_match(conditions.if_match.clone())
+            .set_if_none_match(conditions.if_none_match.clone())
+            .set_if_modified_since(conditions.if_modified_since)
+            .set_if_unmodified_since(conditions.if_unmodified_since)
+            .send()
+            .await
+            .map_err(|err| AppError::from_sdk(err, file_path))
+    })
+    .await
+}
+
+/// Reads the metadata of `file_path` for diagnostics, past the circuit
+/// breaker.
+async fn probe_object(bc: &BucketClient, file_path: &str) -> Result<(), AppError> {
+    bc.call_unguarded("head_object", file_path, async {
+        bc.client
+            .head_object()
+            .bucket(&bc.bucket_name)
+            .key(file_path)
+            .send()
+            .await
+            .map(drop)
+            .map_err(|err| AppError::from_sdk(err, file_path))
+    })
+    .await
+}
+
+/// Presigns a `GetObject` request for `file_path`, valid for `expires_in`.
+async fn presign(
+    bc: &BucketClient,
+    file_path: &str,
+    expires_in: Duration,
+) -> Result<String, AppError> {
+    let presign_config = PresigningConfig::builder()
+        .expires_in(expires_in)
+        .build()
+        .map_err(|e| AppError::Internal(format!("invalid presign expiry: {e}")))?;
+
+    let presigned = bc
+        .client
+        .get_object()
+        .bucket(&bc.bucket_name)
+        .key(file_path)
+        .presigned(presign_config)
+        .instrument(tracing::info_span!(
+            "presign",
+            aws.s3.bucket = bc.bucket_name,
+            aws.s3.key = file_path,
+        ))
+        .await
+        .map_err(|err| AppError::from_sdk(err, file_path))?;
+
+    metrics::counter!("media_server_presigned_urls_total", "bucket" => bc.name.clone())
+        .increment(1);
+    Ok(presigned.uri().to_string())
+}
+
+async fn get_object(
+    bc: &BucketClient,
+    file_path: &str,
+    conditions: &ReadConditions,
+) -> Result<GetObjectOutput, AppError> {
+    bc.call("get_object", file_path, async {
+        bc.client
+            .get_object()
+            .bucket(&bc.bucket_name)
+            .key(file_path)
+            .set_range(conditions.range.clone())
+            .set_if_match(conditions.if_match.clone())
+            .set_if_none_match(conditions.if_none_match.clone())
+            .set_if_modified_since(conditions.if_modified_since)
+            .set_if_unmodified_since(conditions.if_unmodified_since)
+            .send()
+            .await
+            .map_err(|err| AppError::from_sdk(err, file_path))
+    })
+    .await
+}
+
+async fn collect_object(
+    output: GetObjectOutput,
+    content_type: String,
+) -> Result<CachedObject, AppError> {
+    let body = output
+        .body
+        .collect()
+        .await
+        .map_err(|e| AppError::BadGateway(e.to_string()))?
+        .into_bytes();
+    Ok(CachedObject { content_type, body })
 }
 
 pub enum FileResponse {
     Redirect(String),
-    Stream { content_type: String, body: Body },
+    Stream {
+        content_type: String,
+        /// `Content-Range` of a partial read, to be served as `206`.
+        content_range: Option<String>,
+        body: Body,
+    },
+}
+
+/// The `Range` and precondition headers of a client request, passed on to S3
+/// so that it answers `206`, `304`, `412` or `416` as it would directly.
+#[derive(Clone, Debug, Default)]
+pub struct ReadConditions {
+    pub range: Option<String>,
+    pub if_match: Option<String>,
+    pub if_none_match: Option<String>,
+    pub if_modified_since: Option<aws_sdk_s3::primitives::DateTime>,
+    pub if_unmodified_since: Option<aws_sdk_s3::primitives::DateTime>,
+}
+
+impl ReadConditions {
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let text = |name| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        // Unparseable dates are ignored, as RFC 9110 requires.
+        let date = |name| {
+            let value = text(name)?;
+            aws_sdk_s3::primitives::DateTime::from_str(
+                &value,
+                aws_sdk_s3::primitives::DateTimeFormat::HttpDate,
+            )
+            .ok()
+        };
+        Self {
+            range: text(header::RANGE),
+            if_match: text(header::IF_MATCH),
+            if_none_match: text(header::IF_NONE_MATCH),
+            if_modified_since: date(header::IF_MODIFIED_SINCE),
+            if_unmodified_since: date(header::IF_UNMODIFIED_SINCE),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.range.is_none()
+            && self.if_match.is_none()
+            && self.if_none_match.is_none()
+            && self.if_modified_since.is_none()
+            && self.if_unmodified_since.is_none()
+    }
 }
 
 pub trait FileServer: Send + Sync {
@@ -155,6 +988,7 @@ pub trait FileServer: Send + Sync {
         &self,
         config_name: &str,
         file_path: &str,
+        conditions: &ReadConditions,
     ) -> Pin<Box<dyn Future<Output = Result<FileResponse, AppError>> + Send + '_>>;
 }
 
@@ -163,24 +997,23 @@ impl FileServer for S3Clients {
         &self,
         config_name: &str,
         file_path: &str,
+        conditions: &ReadConditions,
     ) -> Pin<Box<dyn Future<Output = Result<FileResponse, AppError>> + Send + '_>> {
         let config_name = config_name.to_string();
         let file_path = file_path.to_string();
+        let conditions = conditions.clone();
         Box::pin(async move {
-            let bc = self
-                .buckets
-                .get(&config_name)
-                .ok_or_else(|| AppError::ConfigNotFound(config_name.clone()))?;
+            let bc = self.bucket(&config_name)?;
 
             if bc.proxy {
-                self.proxy_file(bc, &file_path).await
+                self.proxy_file(&config_name, bc, &file_path, &conditions)
+                    .await
             } else {
-                self.redirect_file(bc, &file_path).await
+                self.redirect_file(bc, &file_path, &conditions).await
             }
         })
     }
 }
 
-pub fn new_file_server(config: &AppConfig) -> Arc<dyn FileServer> {
-    Arc::new(S3Clients::from_config(config))
-}
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,278 @@
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use http_body_util::BodyExt;
+
+use super::*;
+use crate::mock_s3::MockS3;
+
+fn clients(yaml: &str) -> S3Clients {
+    S3Clients::from_config(&serde_yaml::from_str(yaml).unwrap()).unwrap()
+}
+
+#[tokio::test(start_paused = true)]
+async fn cached_objects_are_throttled() {
+    let clients = clients(
+        r#"
+cache: {}
+buckets:
+  videos:
+    endpoint_url: "http://127.0.0.1:1"
+    bucket_name: "videos"
+    access_key:
+        plain: "key"
+    secret_key:
+        plain: "secret"
+    proxy: true
+    cache: true
+    throttle:
+      bytes_per_sec: 16384
+"#,
+    );
+    let object = CachedObject {
+        content_type: "video/mp4".into(),
+        body: Bytes::from(vec![0; 3 * 16384]),
+    };
+    clients
+        .cache
+        .as_ref()
+        .unwrap()
+        .insert("videos", "clip.mp4", object)
+        .await;
+
+    let FileResponse::Stream { body, .. } = clients
+        .get_file("videos", "clip.mp4", &ReadConditions::default())
+        .await
+        .unwrap()
+    else {
+        panic!("expected a proxied body");
+    };
+    let start = tokio::time::Instant::now();
+    let body = body.collect().await.unwrap().to_bytes();
+
+    assert_eq!(body.len(), 3 * 16384);
+    assert_eq!(start.elapsed(), Duration::from_secs(2));
+}
+
+fn bucket_config(endpoint: &str) -> String {
+    format!(
+        r#"
+buckets:
+  files:
+    endpoint_url: "{endpoint}"
+    bucket_name: "files"
+    access_key:
+        plain: "key"
+    secret_key:
+        plain: "secret"
+    retry:
+      max_attempts: 1
+"#
+    )
+}
+
+#[tokio::test]
+async fn read_check_uses_a_listed_object() {
+    let endpoint = MockS3::new(&["a.txt"])
+        .missing_status(StatusCode::FORBIDDEN)
+        .serve()
+        .await;
+    let tests = clients(&bucket_config(&endpoint.url)).test_buckets().await;
+
+    let test = &tests["files"];
+    assert!(test.reach.is_ok());
+    assert!(test.list.is_ok());
+    assert!(matches!(test.read, ReadTest::Read(Ok(()))));
+}
+
+#[tokio::test]
+async fn refused_read_of_a_missing_key_is_inconclusive() {
+    let endpoint = MockS3::new(&[])
+        .missing_status(StatusCode::FORBIDDEN)
+        .serve()
+        .await;
+    let tests = clients(&bucket_config(&endpoint.url)).test_buckets().await;
+
+    let test = &tests["files"];
+    assert!(test.list.is_ok());
+    assert!(matches!(
+        test.read,
+        ReadTest::Inconclusive("no objects to read; probe refused")
+    ));
+}
+
+#[tokio::test]
+async fn bucket_tests_leave_the_circuit_closed() {
+    let config = bucket_config("http://127.0.0.1:1")
+        + "    circuit_breaker:\n      consecutive_failures: 1\n";
+    let clients = clients(&config);
+    let tests = clients.test_buckets().await;
+
+    assert!(tests["files"].reach.is_err());
+    assert_eq!(clients.circuit_states()["files"], CircuitState::Closed);
+}
+
+async fn read_with(proxy: bool, conditions: ReadConditions) -> Result<FileResponse, AppError> {
+    let endpoint = MockS3::new(&["a.txt"]).serve().await;
+    let mut config = bucket_config(&endpoint.url);
+    if proxy {
+        config += "    proxy: true\n";
+    }
+    clients(&config)
+        .get_file("files", "a.txt", &conditions)
+        .await
+}
+
+#[tokio::test]
+async fn ranges_are_passed_on() {
+    let conditions = ReadConditions {
+        range: Some("bytes=1-3".into()),
+        ..Default::default()
+    };
+    let Ok(FileResponse::Stream {
+        content_range,
+        body,
+        ..
+    }) = read_with(true, conditions).await
+    else {
+        panic!("expected a proxied body");
+    };
+
+    assert_eq!(content_range.as_deref(), Some("bytes 1-3/5"));
+    assert_eq!(&body.collect().await.unwrap().to_bytes()[..], b"ell");
+}
+
+#[tokio::test]
+async fn unsatisfiable_range_is_416() {
+    for proxy in [true, false] {
+        let conditions = ReadConditions {
+            range: Some("bytes=10-".into()),
+            ..Default::default()
+        };
+        let Err(err) = read_with(proxy, conditions).await else {
+            panic!("expected an error");
+        };
+        assert_eq!(
+            err.into_response().status(),
+            StatusCode::RANGE_NOT_SATISFIABLE
+        );
+    }
+}
+
+#[tokio::test]
+async fn failed_precondition_is_412() {
+    for proxy in [true, false] {
+        let conditions = ReadConditions {
+            if_match: Some("\"stale\"".into()),
+            ..Default::default()
+        };
+        let Err(err) = read_with(proxy, conditions).await else {
+            panic!("expected an error");
+        };
+        assert_eq!(
+            err.into_response().status(),
+            StatusCode::PRECONDITION_FAILED
+        );
+    }
+}
+
+#[tokio::test]
+async fn current_copy_is_304() {
+    for proxy in [true, false] {
+        let conditions = ReadConditions {
+            if_none_match: Some("\"mock\"".into()),
+            ..Default::default()
+        };
+        let Err(err) = read_with(proxy, conditions).await else {
+            panic!("expected an error");
+        };
+        let resp = err.into_response();
+        assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+        assert!(
+            resp.into_body()
+                .collect()
+                .await
+                .unwrap()
+                .to_bytes()
+                .is_empty()
+        );
+    }
+}
+
+#[test]
+fn read_conditions_are_taken_from_headers() {
+    let mut headers = HeaderMap::new();
+    headers.insert(header::RANGE, "bytes=0-99".parse().unwrap());
+    headers.insert(header::IF_NONE_MATCH, "\"abc\"".parse().unwrap());
+    headers.insert(
+        header::IF_MODIFIED_SINCE,
+        "Sat, 14 Feb 2026 18:34:23 GMT".parse().unwrap(),
+    );
+    headers.insert(header::IF_UNMODIFIED_SINCE, "yesterday".parse().unwrap());
+    let conditions = ReadConditions::from_headers(&headers);
+
+    assert_eq!(conditions.range.as_deref(), Some("bytes=0-99"));
+    assert_eq!(conditions.if_none_match.as_deref(), Some("\"abc\""));
+    assert_eq!(
+        conditions.if_modified_since.map(|date| date.secs()),
+        Some(1_771_094_063)
+    );
+    assert!(conditions.if_unmodified_since.is_none());
+    assert!(conditions.if_match.is_none());
+    assert!(ReadConditions::default().is_empty());
+}
+
+#[tokio::test]
+async fn reconfiguring_keeps_unchanged_buckets() {
+    let bucket = |name: &str, bucket_name: &str| {
+        format!(
+            r#"
+  {name}:
+    endpoint_url: "http://127.0.0.1:1"
+    bucket_name: "{bucket_name}"
+    access_key:
+        plain: "key"
+    secret_key:
+        plain: "secret"
+    proxy: true
+    cache: true
+    circuit_breaker: {{}}"#
+        )
+    };
+    let config = |videos_bucket: &str| -> AppConfig {
+        let buckets = bucket("photos", "photos") + &bucket("videos", videos_bucket);
+        serde_yaml::from_str(&format!(
+            "cache: {{}}\nopen_streams:\n  max_in_flight: 10\nbuckets:{buckets}"
+        ))
+        .unwrap()
+    };
+    let previous = config("videos");
+    let clients = S3Clients::from_config(&previous).unwrap();
+    let cache = clients.cache.as_ref().unwrap();
+    for name in ["photos", "videos"] {
+        let object = CachedObject {
+            content_type: "text/plain".into(),
+            body: Bytes::from_static(b"hello"),
+        };
+        cache.insert(name, "a.txt", object).await;
+    }
+
+    let reconfigured = clients
+        .reconfigured(&previous, &config("other-videos"))
+        .unwrap();
+
+    assert!(Arc::ptr_eq(
+        &clients.buckets["photos"],
+        &reconfigured.buckets["photos"]
+    ));
+    assert!(!Arc::ptr_eq(
+        &clients.buckets["videos"],
+        &reconfigured.buckets["videos"]
+    ));
+    assert!(Arc::ptr_eq(
+        clients.open_streams.as_ref().unwrap(),
+        reconfigured.open_streams.as_ref().unwrap()
+    ));
+    let cache = reconfigured.cache.as_ref().unwrap();
+    assert!(cache.get("photos", "a.txt").await.is_some());
+    assert!(cache.get("videos", "a.txt").await.is_none());
+}
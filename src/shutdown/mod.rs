@@ -0,0 +1,87 @@
+use std::time::Duration;
+
+use tokio::signal::unix::{SignalKind, signal};
+use tokio_util::sync::CancellationToken;
+
+/// Marks the server as draining, shared by the listener and the readiness
+/// check.
+#[derive(Clone, Default)]
+pub struct Shutdown {
+    draining: CancellationToken,
+    closing: CancellationToken,
+}
+
+impl Shutdown {
+    /// Fails readiness checks from now on.
+    pub fn begin(&self) {
+        self.draining.cancel();
+    }
+
+    /// Stops the listener from accepting connections.
+    pub fn close(&self) {
+        self.draining.cancel();
+        self.closing.cancel();
+    }
+
+    /// Begins draining, and closes the listener once `readiness_grace` has
+    /// passed, so that load balancers see the failing readiness check before
+    /// connections are refused.
+    pub async fn drain(&self, readiness_grace: Duration) {
+        self.begin();
+        tokio::time::sleep(readiness_grace).await;
+        self.close();
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.draining.is_cancelled()
+    }
+
+    /// Completes once the listener is to stop accepting connections.
+    pub async fn closed(self) {
+        self.closing.cancelled_owned().await;
+    }
+}
+
+/// Begins draining on the first `SIGTERM` or `SIGINT`. The process exits,
+/// calling `on_exit` first, on a second signal or once `drain_timeout` has
+/// passed after closing the listener with requests still open.
+pub fn watch_signals(
+    shutdown: Shutdown,
+    readiness_grace: Duration,
+    drain_timeout: Duration,
+    on_exit: impl FnOnce() + Send + 'static,
+) -> eyre::Result<()> {
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut interrupt = signal(SignalKind::interrupt())?;
+    tokio::spawn(async move {
+        tokio::select! {
+            _ = terminate.recv() => {}
+            _ = interrupt.recv() => {}
+        }
+        tracing::info!(
+            readiness_grace_secs = readiness_grace.as_secs(),
+            drain_timeout_secs = drain_timeout.as_secs(),
+            "Received termination signal, draining connections"
+        );
+
+        tokio::select! {
+            _ = terminate.recv() => {}
+            _ = interrupt.recv() => {}
+            () = async {
+                shutdown.drain(readiness_grace).await;
+                tokio::time::sleep(drain_timeout).await;
+            } => {
+                tracing::warn!("Drain timeout passed, exiting with requests in flight");
+                on_exit();
+                std::process::exit(1);
+            }
+        }
+        tracing::warn!("Received second termination signal, exiting now");
+        on_exit();
+        std::process::exit(1);
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests;
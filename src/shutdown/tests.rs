@@ -0,0 +1,20 @@
+use super::*;
+
+#[tokio::test(start_paused = true)]
+async fn listener_closes_after_readiness_grace() {
+    let shutdown = Shutdown::default();
+    assert!(!shutdown.is_draining());
+
+    let closed = tokio::spawn(shutdown.clone().closed());
+    let drain = tokio::spawn({
+        let shutdown = shutdown.clone();
+        async move { shutdown.drain(Duration::from_secs(5)).await }
+    });
+    tokio::task::yield_now().await;
+    assert!(shutdown.is_draining());
+    assert!(!closed.is_finished());
+
+    tokio::time::sleep(Duration::from_secs(5)).await;
+    drain.await.unwrap();
+    closed.await.unwrap();
+}
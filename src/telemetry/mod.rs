@@ -0,0 +1,103 @@
+use axum::extract::Request;
+use axum::http::HeaderMap;
+use axum::middleware::Next;
+use axum::response::Response;
+use eyre::WrapErr;
+use opentelemetry::propagation::{Extractor, TextMapPropagator};
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{Sampler, SdkTracerProvider};
+use tracing::Instrument;
+use tracing::Level;
+use tracing::field::Empty;
+use tracing_opentelemetry::{OpenTelemetryLayer, OpenTelemetrySpanExt};
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::config::{OtlpConfig, OtlpProtocol};
+
+/// Builds the OTLP exporter pipeline. The provider must be shut down on exit
+/// to flush pending spans.
+pub fn tracer_provider(config: &OtlpConfig) -> eyre::Result<SdkTracerProvider> {
+    let exporter = match config.protocol {
+        OtlpProtocol::Grpc => SpanExporter::builder()
+            .with_tonic()
+            .with_endpoint(config.endpoint())
+            .build(),
+        OtlpProtocol::Http => SpanExporter::builder()
+            .with_http()
+            .with_endpoint(config.endpoint())
+            .build(),
+    }
+    .wrap_err("failed to build the OTLP exporter")?;
+
+    let sampler = match config.sample_ratio {
+        Some(ratio) => Sampler::TraceIdRatioBased(ratio),
+        None => Sampler::AlwaysOn,
+    };
+
+    Ok(SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_sampler(Sampler::ParentBased(Box::new(sampler)))
+        .with_resource(
+            Resource::builder()
+                .with_service_name(config.service_name.clone())
+                .build(),
+        )
+        .build())
+}
+
+/// A `tracing` layer exporting spans through `provider`.
+pub fn layer<S>(
+    provider: &SdkTracerProvider,
+) -> OpenTelemetryLayer<S, opentelemetry_sdk::trace::Tracer>
+where
+    S: tracing::Subscriber + for<'span> LookupSpan<'span>,
+{
+    tracing_opentelemetry::layer().with_tracer(provider.tracer("media-server"))
+}
+
+/// Exports this crate's spans only, leaving out those of the HTTP and gRPC
+/// clients, the exporter's own among them.
+pub fn span_filter() -> Targets {
+    Targets::new().with_target(env!("CARGO_CRATE_NAME"), Level::INFO)
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|name| name.as_str()).collect()
+    }
+}
+
+/// Opens the `request` span, continuing the trace of an incoming W3C
+/// `traceparent` header. Inner layers fill in `client_ip` and `request_id`.
+pub async fn trace_requests(request: Request, next: Next) -> Response {
+    let parent = TraceContextPropagator::new().extract(&HeaderExtractor(request.headers()));
+
+    let span = tracing::info_span!(
+        "request",
+        otel.kind = "server",
+        http.request.method = %request.method(),
+        url.path = request.uri().path(),
+        http.response.status_code = Empty,
+        client_ip = Empty,
+        request_id = Empty,
+    );
+    // Fails only when no OpenTelemetry layer is installed, which is fine.
+    let _ = span.set_parent(parent);
+
+    let response = next.run(request).instrument(span.clone()).await;
+    span.record("http.response.status_code", response.status().as_u16());
+    response
+}
+
+#[cfg(test)]
+mod tests;
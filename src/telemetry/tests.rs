@@ -0,0 +1,61 @@
+use axum::Router;
+use axum::body::Body;
+use axum::middleware;
+use axum::routing::get;
+use opentelemetry::trace::{SpanId, TraceId};
+use opentelemetry_sdk::trace::InMemorySpanExporter;
+use tower::ServiceExt;
+use tracing::instrument::WithSubscriber;
+use tracing_subscriber::layer::SubscriberExt;
+
+use super::*;
+
+const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+const PARENT_ID: &str = "00f067aa0ba902b7";
+
+#[tokio::test]
+async fn request_span_continues_incoming_trace() {
+    let exporter = InMemorySpanExporter::default();
+    let provider = SdkTracerProvider::builder()
+        .with_simple_exporter(exporter.clone())
+        .build();
+    let subscriber = tracing_subscriber::registry().with(layer(&provider));
+
+    let app = Router::new()
+        .route("/photos/{*key}", get(|| async { "ok" }))
+        .layer(middleware::from_fn(trace_requests));
+    let request = Request::builder()
+        .uri("/photos/a.jpg")
+        .header("traceparent", format!("00-{TRACE_ID}-{PARENT_ID}-01"))
+        .body(Body::empty())
+        .unwrap();
+
+    let response = app
+        .oneshot(request)
+        .with_subscriber(subscriber)
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    provider.force_flush().unwrap();
+
+    let spans = exporter.get_finished_spans().unwrap();
+    let span = spans.iter().find(|span| span.name == "request").unwrap();
+    assert_eq!(
+        span.span_context.trace_id(),
+        TraceId::from_hex(TRACE_ID).unwrap()
+    );
+    assert_eq!(span.parent_span_id, SpanId::from_hex(PARENT_ID).unwrap());
+    assert!(span.attributes.iter().any(|attribute| {
+        attribute.key.as_str() == "http.response.status_code" && attribute.value.as_str() == "200"
+    }));
+}
+
+#[test]
+fn extractor_reads_headers() {
+    let mut headers = HeaderMap::new();
+    headers.insert("traceparent", "00-abc".parse().unwrap());
+
+    let extractor = HeaderExtractor(&headers);
+    assert_eq!(extractor.get("traceparent"), Some("00-abc"));
+    assert_eq!(extractor.keys(), vec!["traceparent"]);
+}
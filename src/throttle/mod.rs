@@ -0,0 +1,213 @@
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::body::Bytes;
+use eyre::{WrapErr, bail};
+use futures::{Stream, StreamExt};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use tokio::time::Instant;
+
+use crate::config::{ThrottleConfig, ThrottleRuleConfig};
+
+/// Size of the chunks bodies held in memory are split into, so that they can
+/// be paced like streamed ones.
+const BUFFERED_CHUNK_SIZE: usize = 16 * 1024;
+
+/// Spaces out sends so that they average `rate` bytes per second.
+struct Pacer {
+    rate: f64,
+    /// Earliest time the next send may start.
+    next: Instant,
+}
+
+impl Pacer {
+    fn new(bytes_per_sec: u64) -> Self {
+        Self {
+            rate: bytes_per_sec as f64,
+            next: Instant::now(),
+        }
+    }
+
+    /// Books `bytes` and returns how long to wait before sending them.
+    fn reserve(&mut self, bytes: u64, now: Instant) -> Duration {
+        if bytes == 0 {
+            return Duration::ZERO;
+        }
+        let start = self.next.max(now);
+        self.next = start + Duration::from_secs_f64(bytes as f64 / self.rate);
+        start - now
+    }
+}
+
+/// Per-download cap: the first `free_bytes` go out unpaced, the rest at
+/// `bytes_per_sec` (or uncapped when unset).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct ConnectionLimit {
+    bytes_per_sec: Option<u64>,
+    free_bytes: u64,
+}
+
+struct Rule {
+    content_types: Option<GlobSet>,
+    min_size: Option<u64>,
+    limit: ConnectionLimit,
+}
+
+impl Rule {
+    fn from_config(config: &ThrottleRuleConfig) -> eyre::Result<Self> {
+        let content_types = if config.content_types.is_empty() {
+            None
+        } else {
+            let mut builder = GlobSetBuilder::new();
+            for pattern in &config.content_types {
+                let glob = GlobBuilder::new(pattern)
+                    .case_insensitive(true)
+                    .build()
+                    .wrap_err_with(|| format!("invalid content type pattern \"{pattern}\""))?;
+                builder.add(glob);
+            }
+            Some(builder.build()?)
+        };
+
+        Ok(Self {
+            content_types,
+            min_size: config.min_size_bytes,
+            limit: connection_limit(config.bytes_per_sec, config.free_bytes)?,
+        })
+    }
+
+    fn matches(&self, content_type: &str, size: Option<u64>) -> bool {
+        let essence = content_type.split(';').next().unwrap_or("").trim();
+        self.content_types
+            .as_ref()
+            .is_none_or(|globs| globs.is_match(essence))
+            && self
+                .min_size
+                .is_none_or(|min| size.is_some_and(|size| size >= min))
+    }
+}
+
+fn connection_limit(bytes_per_sec: Option<u64>, free_bytes: u64) -> eyre::Result<ConnectionLimit> {
+    if bytes_per_sec == Some(0) {
+        bail!("`bytes_per_sec` must be positive");
+    }
+    Ok(ConnectionLimit {
+        bytes_per_sec,
+        free_bytes,
+    })
+}
+
+/// Bandwidth caps of one bucket, applied to proxied response bodies.
+pub struct Throttle {
+    default: ConnectionLimit,
+    rules: Vec<Rule>,
+    /// Shared by every download from the bucket.
+    aggregate: Option<Arc<Mutex<Pacer>>>,
+}
+
+impl Throttle {
+    pub fn from_config(config: &ThrottleConfig) -> eyre::Result<Self> {
+        let aggregate = match config.bucket_bytes_per_sec {
+            Some(0) => bail!("`bucket_bytes_per_sec` must be positive"),
+            Some(rate) => Some(Arc::new(Mutex::new(Pacer::new(rate)))),
+            None => None,
+        };
+
+        Ok(Self {
+            default: connection_limit(config.bytes_per_sec, config.free_bytes)?,
+            rules: config
+                .rules
+                .iter()
+                .map(Rule::from_config)
+                .collect::<eyre::Result<_>>()?,
+            aggregate,
+        })
+    }
+
+    fn limit_for(&self, content_type: &str, size: Option<u64>) -> ConnectionLimit {
+        self.rules
+            .iter()
+            .find(|rule| rule.matches(content_type, size))
+            .map_or(self.default, |rule| rule.limit)
+    }
+
+    /// Paces `stream`, a download of `size` bytes of `content_type`.
+    pub fn apply<S>(
+        &self,
+        stream: S,
+        content_type: &str,
+        size: Option<u64>,
+    ) -> impl Stream<Item = io::Result<Bytes>> + Send + 'static
+    where
+        S: Stream<Item = io::Result<Bytes>> + Send + 'static,
+    {
+        let limit = self.limit_for(content_type, size);
+        let state = Paced {
+            connection: limit.bytes_per_sec.map(Pacer::new),
+            free_bytes: limit.free_bytes,
+            aggregate: self.aggregate.clone(),
+        };
+
+        futures::stream::unfold(
+            (Box::pin(stream), state),
+            |(mut stream, mut state)| async move {
+                let chunk = stream.next().await?;
+                if let Ok(bytes) = &chunk {
+                    let delay = state.delay(bytes.len() as u64);
+                    if !delay.is_zero() {
+                        tokio::time::sleep(delay).await;
+                    }
+                }
+                Some((chunk, (stream, state)))
+            },
+        )
+    }
+
+    /// Paces `body`, a whole object of `content_type` held in memory.
+    pub fn apply_buffered(
+        &self,
+        body: Bytes,
+        content_type: &str,
+    ) -> impl Stream<Item = io::Result<Bytes>> + Send + 'static {
+        let size = body.len() as u64;
+        let chunks = futures::stream::unfold(body, |mut rest| async move {
+            if rest.is_empty() {
+                return None;
+            }
+            let chunk = rest.split_to(rest.len().min(BUFFERED_CHUNK_SIZE));
+            Some((Ok(chunk), rest))
+        });
+        self.apply(chunks, content_type, Some(size))
+    }
+}
+
+struct Paced {
+    connection: Option<Pacer>,
+    free_bytes: u64,
+    aggregate: Option<Arc<Mutex<Pacer>>>,
+}
+
+impl Paced {
+    fn delay(&mut self, bytes: u64) -> Duration {
+        let now = Instant::now();
+        let free = bytes.min(self.free_bytes);
+        self.free_bytes -= free;
+
+        let connection = self
+            .connection
+            .as_mut()
+            .map_or(Duration::ZERO, |pacer| pacer.reserve(bytes - free, now));
+        let aggregate = self.aggregate.as_ref().map_or(Duration::ZERO, |pacer| {
+            pacer
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .reserve(bytes, now)
+        });
+
+        connection.max(aggregate)
+    }
+}
+
+#[cfg(test)]
+mod tests;
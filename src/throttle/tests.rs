@@ -0,0 +1,119 @@
+use super::*;
+
+fn throttle(yaml: &str) -> Throttle {
+    Throttle::from_config(&serde_yaml::from_str(yaml).unwrap()).unwrap()
+}
+
+fn chunks(count: usize, size: usize) -> impl Stream<Item = io::Result<Bytes>> + Send + 'static {
+    futures::stream::iter((0..count).map(move |_| Ok(Bytes::from(vec![0; size]))))
+}
+
+async fn drain(stream: impl Stream<Item = io::Result<Bytes>>) -> usize {
+    stream
+        .map(|chunk| chunk.unwrap().len())
+        .fold(0, |total, len| async move { total + len })
+        .await
+}
+
+#[test]
+fn pacer_spaces_out_reservations() {
+    let mut pacer = Pacer::new(1000);
+    let now = Instant::now();
+
+    assert_eq!(pacer.reserve(500, now), Duration::ZERO);
+    assert_eq!(pacer.reserve(500, now), Duration::from_millis(500));
+    assert_eq!(
+        pacer.reserve(100, now + Duration::from_secs(5)),
+        Duration::ZERO
+    );
+}
+
+#[test]
+fn first_matching_rule_wins() {
+    let throttle = throttle(
+        r#"
+bytes_per_sec: 1000
+rules:
+  - content_types: ["video/*"]
+    min_size_bytes: 100
+    bytes_per_sec: 2000
+    free_bytes: 50
+  - content_types: ["video/*"]
+"#,
+    );
+
+    assert_eq!(
+        throttle.limit_for("video/mp4", Some(500)),
+        ConnectionLimit {
+            bytes_per_sec: Some(2000),
+            free_bytes: 50
+        }
+    );
+    // Too small (or of unknown size) for the first rule, so the uncapped second one applies.
+    assert_eq!(
+        throttle
+            .limit_for("VIDEO/webm; codecs=vp9", Some(10))
+            .bytes_per_sec,
+        None
+    );
+    assert_eq!(throttle.limit_for("video/mp4", None).bytes_per_sec, None);
+    assert_eq!(
+        throttle.limit_for("image/png", Some(500)).bytes_per_sec,
+        Some(1000)
+    );
+}
+
+#[test]
+fn zero_rate_is_a_config_error() {
+    let config = serde_yaml::from_str("bytes_per_sec: 0").unwrap();
+    assert!(Throttle::from_config(&config).is_err());
+    let config = serde_yaml::from_str("bucket_bytes_per_sec: 0").unwrap();
+    assert!(Throttle::from_config(&config).is_err());
+}
+
+#[tokio::test(start_paused = true)]
+async fn free_bytes_are_sent_before_throttling() {
+    let throttle = throttle("bytes_per_sec: 1000\nfree_bytes: 3000");
+    let start = Instant::now();
+
+    let sent = drain(throttle.apply(chunks(5, 1000), "video/mp4", Some(5000))).await;
+
+    assert_eq!(sent, 5000);
+    // Three free chunks, then one second for the fourth before the fifth may start.
+    assert_eq!(start.elapsed(), Duration::from_secs(1));
+}
+
+#[tokio::test(start_paused = true)]
+async fn bucket_limit_is_shared_between_downloads() {
+    let throttle = throttle("bucket_bytes_per_sec: 1000");
+    let start = Instant::now();
+
+    let first = drain(throttle.apply(chunks(2, 1000), "image/png", None));
+    let second = drain(throttle.apply(chunks(2, 1000), "image/png", None));
+    let (first, second) = tokio::join!(first, second);
+
+    assert_eq!(first + second, 4000);
+    assert_eq!(start.elapsed(), Duration::from_secs(3));
+}
+
+#[tokio::test(start_paused = true)]
+async fn buffered_bodies_are_paced_in_chunks() {
+    let throttle = throttle(
+        r#"
+rules:
+  - content_types: ["video/*"]
+    bytes_per_sec: 16384
+"#,
+    );
+    let body = Bytes::from(vec![0; 4 * BUFFERED_CHUNK_SIZE + 1]);
+    let start = Instant::now();
+
+    let sent = drain(throttle.apply_buffered(body.clone(), "video/mp4")).await;
+    assert_eq!(sent, body.len());
+    // The first chunk goes out at once, then one second per chunk.
+    assert_eq!(start.elapsed(), Duration::from_secs(4));
+
+    let start = Instant::now();
+    drain(throttle.apply_buffered(body, "image/png")).await;
+    assert_eq!(start.elapsed(), Duration::ZERO);
+}